@@ -0,0 +1,64 @@
+//! Compares label set lookups in a `RegistryMap` and a `HashRegistryMap`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use tinymetrics::registry::{HashRegistryMap, RegistryMap};
+
+type Labels = (&'static str, &'static str);
+
+fn labels(n: usize) -> Vec<Labels> {
+    (0..n)
+        .map(|i| ("peer", &*Box::leak(format!("10.0.0.{i}").into_boxed_str())))
+        .collect()
+}
+
+fn bench_lookup<const CAPACITY: usize>(c: &mut Criterion, len: usize) {
+    let labels = labels(len);
+    let linear = RegistryMap::<Labels, usize, CAPACITY>::new();
+    let hashed = HashRegistryMap::<Labels, usize, CAPACITY>::new();
+    for (i, key) in labels.iter().enumerate() {
+        linear.get_or_register(*key, i).expect("map has capacity");
+        hashed.get_or_register(*key, i).expect("map has capacity");
+    }
+
+    let mut group = c.benchmark_group("lookup");
+    // look up the most recently registered label set, which is the worst case
+    // for a linear scan.
+    let last = labels[len - 1];
+    group.bench_with_input(BenchmarkId::new("RegistryMap", len), &last, |b, key| {
+        b.iter(|| linear.get_or_register(black_box(*key), 0))
+    });
+    group.bench_with_input(BenchmarkId::new("HashRegistryMap", len), &last, |b, key| {
+        b.iter(|| hashed.get_or_register(black_box(*key), 0))
+    });
+    group.bench_with_input(
+        BenchmarkId::new("RegistryMap/all", len),
+        &labels,
+        |b, labels| {
+            b.iter(|| {
+                for key in labels {
+                    black_box(linear.get_or_register(*key, 0));
+                }
+            })
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("HashRegistryMap/all", len),
+        &labels,
+        |b, labels| {
+            b.iter(|| {
+                for key in labels {
+                    black_box(hashed.get_or_register(*key, 0));
+                }
+            })
+        },
+    );
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    bench_lookup::<8>(c, 8);
+    bench_lookup::<64>(c, 48);
+    bench_lookup::<512>(c, 384);
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
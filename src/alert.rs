@@ -0,0 +1,536 @@
+//! Local alerts on the values of metrics.
+//!
+//! An [`Alert`] is a rule, such as "the temperature is above 80 degrees",
+//! which invokes a callback function when a metric starts or stops matching
+//! it. Alerts are intended for devices which must react to a metric crossing a
+//! threshold locally (such as by lighting an LED or logging a message),
+//! rather than waiting for a remote alerting system.
+//!
+//! Alerts are not evaluated when a metric is updated, which would add
+//! overhead to every update. Instead, they are evaluated by calling
+//! [`Alert::check`] (for every series in a [`MetricFamily`]) or
+//! [`Alert::check_metric`] (for a single metric) periodically, such as from
+//! the same task which calls [`Meter::tick`](crate::Meter::tick).
+//!
+//! Alerts may be checked for any metric type which implements the
+//! [`AlertValue`] trait.
+//!
+//! # Examples
+//!
+//! ```
+//! use tinymetrics::{
+//!     alert::{Alert, AlertEvent, Condition},
+//!     GaugeFamily, MetricBuilder,
+//! };
+//!
+//! static TEMPERATURE: GaugeFamily<'static, 4> = MetricBuilder::new("temperature")
+//!     .with_unit("celsius")
+//!     .build();
+//!
+//! fn overheating(event: &AlertEvent<'_>) {
+//!     // turn on an LED, or log a message...
+//!     println!(
+//!         "{}: {}{{{}}} = {} (firing: {})",
+//!         event.name(),
+//!         event.family(),
+//!         event.labels(),
+//!         event.value(),
+//!         event.is_firing(),
+//!     );
+//! }
+//!
+//! // fires when a sensor is above 80 degrees, and resolves once it has cooled
+//! // to 75 degrees or below.
+//! static OVERHEATING: Alert<'static, 4> =
+//!     Alert::new("overheating", Condition::Above(80.0), overheating).with_hysteresis(5.0);
+//!
+//! let sensor = TEMPERATURE.register(&[("sensor", "cpu")]).unwrap();
+//!
+//! sensor.set_value(85.0);
+//! OVERHEATING.check(&TEMPERATURE);
+//! assert!(OVERHEATING.is_firing());
+//!
+//! // still within the hysteresis band, so the alert keeps firing.
+//! sensor.sub(7.0);
+//! OVERHEATING.check(&TEMPERATURE);
+//! assert!(OVERHEATING.is_firing());
+//!
+//! sensor.sub(8.0);
+//! OVERHEATING.check(&TEMPERATURE);
+//! assert!(!OVERHEATING.is_firing());
+//! ```
+use crate::{
+    registry::{FnvHasher, Storage},
+    Counter, FixedGauge, FmtLabels, Gauge, IntGauge, IsizeGauge, Metric, MetricFamily, Unknown,
+};
+use core::{fmt, hash::Hasher, hint, ptr};
+use portable_atomic::{AtomicBool, AtomicF64, AtomicUsize, Ordering};
+
+/// A rule which invokes a callback when a metric starts or stops matching a
+/// [`Condition`].
+///
+/// An `Alert` tracks whether it is firing separately for up to `SERIES`
+/// series. Series are identified by the address of their metric and a hash of
+/// their label set, so an alert should only be checked against metrics in
+/// `static`s (or which otherwise outlive the alert). If a series is removed
+/// and a new series reuses its metric, the new series does not inherit the
+/// old one's state. If more than `SERIES` distinct series are checked,
+/// the additional series are ignored. When [`Alert::check`] finds that a
+/// series which it previously checked is no longer in the family, the
+/// series' state is released (without invoking the callback), so that it may
+/// be used for another series.
+///
+/// See [the module-level documentation](self) for details.
+pub struct Alert<'a, const SERIES: usize = 1> {
+    name: &'a str,
+    condition: Condition,
+    hysteresis: f64,
+    callback: fn(&AlertEvent<'_>),
+    series: [SeriesState; SERIES],
+    /// Incremented by each call to [`Alert::check`], so that states which
+    /// were not checked by the latest call can be released.
+    epoch: AtomicUsize,
+}
+
+/// The condition under which an [`Alert`] fires.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// The metric's value is greater than the threshold.
+    Above(f64),
+    /// The metric's value is less than the threshold.
+    Below(f64),
+    /// The metric's value has increased by more than the threshold since the
+    /// alert was last checked.
+    RisesBy(f64),
+    /// The metric's value has decreased by more than the threshold since the
+    /// alert was last checked.
+    FallsBy(f64),
+}
+
+/// Passed to an [`Alert`]'s callback when the alert starts or stops firing
+/// for a series.
+pub struct AlertEvent<'a> {
+    name: &'a str,
+    family: &'a str,
+    labels: &'a dyn fmt::Display,
+    value: f64,
+    firing: bool,
+}
+
+/// Trait implemented by metrics whose values may be checked by an [`Alert`].
+pub trait AlertValue: Metric {
+    /// Returns this metric's current value, as an `f64`.
+    fn alert_value(&self) -> f64;
+}
+
+struct SeriesState {
+    /// The address of the series' metric, 0 if this state is unused, or
+    /// [`CLAIMING`] while the state is being claimed.
+    metric: AtomicUsize,
+    /// A hash of the series' label set, or 0 if it was checked using
+    /// [`Alert::check_metric`].
+    labels: AtomicUsize,
+    /// The address of the family containing the series' metric, or 0 if it
+    /// was checked using [`Alert::check_metric`].
+    family: AtomicUsize,
+    /// The epoch of the last call to [`Alert::check`] which checked the
+    /// series.
+    epoch: AtomicUsize,
+    firing: AtomicBool,
+    /// The metric's value when the alert was last checked, or `NaN` if it
+    /// has not been checked.
+    previous: AtomicF64,
+}
+
+/// Identifies the series a [`SeriesState`] belongs to.
+#[derive(Copy, Clone)]
+struct SeriesKey {
+    metric: usize,
+    labels: usize,
+    family: usize,
+}
+
+/// Formats a label set using its [`FmtLabels`] implementation.
+struct DisplayLabels<'a, L>(&'a L);
+
+/// Hashes a label set's formatted representation.
+struct HashLabels(FnvHasher);
+
+/// The value of [`SeriesState::metric`] while the state is being claimed,
+/// which is never the address of a metric.
+const CLAIMING: usize = usize::MAX;
+
+// === impl Alert ===
+
+impl<'a, const SERIES: usize> Alert<'a, SERIES> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW_STATE: SeriesState = SeriesState {
+        metric: AtomicUsize::new(0),
+        labels: AtomicUsize::new(0),
+        family: AtomicUsize::new(0),
+        epoch: AtomicUsize::new(0),
+        firing: AtomicBool::new(false),
+        previous: AtomicF64::new(f64::NAN),
+    };
+
+    /// Returns a new alert named `name`, which calls `callback` when a
+    /// metric starts or stops matching `condition`.
+    #[must_use]
+    pub const fn new(name: &'a str, condition: Condition, callback: fn(&AlertEvent<'_>)) -> Self {
+        Self {
+            name,
+            condition,
+            hysteresis: 0.0,
+            callback,
+            series: [Self::NEW_STATE; SERIES],
+            epoch: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the hysteresis of this alert.
+    ///
+    /// Once the alert is firing, it does not resolve until the metric's value
+    /// (or, for the [`RisesBy`](Condition::RisesBy) and
+    /// [`FallsBy`](Condition::FallsBy) conditions, its change in value) is
+    /// `hysteresis` past the threshold, so that a value which fluctuates
+    /// around the threshold does not repeatedly fire and resolve the alert.
+    #[must_use]
+    pub const fn with_hysteresis(self, hysteresis: f64) -> Self {
+        Self { hysteresis, ..self }
+    }
+
+    /// Returns the name of this alert.
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the condition under which this alert fires.
+    #[must_use]
+    pub fn condition(&self) -> Condition {
+        self.condition
+    }
+
+    /// Returns `true` if this alert is firing for any series.
+    #[must_use]
+    pub fn is_firing(&self) -> bool {
+        self.series
+            .iter()
+            .any(|state| state.firing.load(Ordering::Acquire))
+    }
+
+    /// Checks this alert against every recorded series in `family`, invoking
+    /// the callback for each series which starts or stops matching.
+    ///
+    /// The state of any series in `family` which was checked previously, but
+    /// is no longer in `family`, is released.
+    pub fn check<M, L, R, const METRICS: usize>(&self, family: &MetricFamily<'_, M, METRICS, L, R>)
+    where
+        M: AlertValue,
+        L: FmtLabels,
+        R: Storage<L, M>,
+    {
+        let family_addr = family as *const _ as usize;
+        let epoch = self.epoch.fetch_add(1, Ordering::AcqRel).wrapping_add(1);
+        for entry in family.metrics().refs() {
+            let metric = entry.value();
+            if !metric.has_been_recorded() {
+                continue;
+            }
+            let key = SeriesKey {
+                metric: metric as *const M as usize,
+                labels: HashLabels::hash(entry.key()),
+                family: family_addr,
+            };
+            if let Some(state) = self.state(key, epoch) {
+                state.epoch.store(epoch, Ordering::Release);
+                self.check_series(state, family.name(), &DisplayLabels(entry.key()), metric);
+            }
+        }
+
+        for state in &self.series {
+            let metric = state.metric.load(Ordering::Acquire);
+            // states checked by a concurrent, later call are not released.
+            let stale = epoch.wrapping_sub(state.epoch.load(Ordering::Acquire)) as isize > 0;
+            if state.family.load(Ordering::Acquire) == family_addr
+                && metric != 0
+                && metric != CLAIMING
+                && stale
+            {
+                state.release();
+            }
+        }
+    }
+
+    /// Checks this alert against a single metric, invoking the callback if it
+    /// starts or stops matching.
+    ///
+    /// The [`AlertEvent`] passed to the callback has an empty family name and
+    /// label set.
+    pub fn check_metric<M: AlertValue>(&self, metric: &M) {
+        if !metric.has_been_recorded() {
+            return;
+        }
+        let key = SeriesKey {
+            metric: metric as *const M as usize,
+            labels: 0,
+            family: 0,
+        };
+        if let Some(state) = self.state(key, self.epoch.load(Ordering::Acquire)) {
+            self.check_series(state, "", &"", metric);
+        }
+    }
+
+    /// Resets this alert, so that it is no longer firing for any series.
+    ///
+    /// The callback is not invoked.
+    pub fn reset(&self) {
+        for state in &self.series {
+            state.release();
+        }
+    }
+
+    fn check_series<M: AlertValue>(
+        &self,
+        state: &SeriesState,
+        family: &str,
+        labels: &dyn fmt::Display,
+        metric: &M,
+    ) {
+        let value = metric.alert_value();
+        let previous = state.previous.swap(value, Ordering::AcqRel);
+
+        let observed = match self.condition {
+            Condition::Above(_) | Condition::Below(_) => value,
+            Condition::RisesBy(_) => value - previous,
+            Condition::FallsBy(_) => previous - value,
+        };
+        // if the change in value can't be computed yet, the comparisons below
+        // are all `false`.
+        let (matches, resolved) = match self.condition {
+            Condition::Above(threshold)
+            | Condition::RisesBy(threshold)
+            | Condition::FallsBy(threshold) => (
+                observed > threshold,
+                observed <= threshold - self.hysteresis,
+            ),
+            Condition::Below(threshold) => (
+                observed < threshold,
+                observed >= threshold + self.hysteresis,
+            ),
+        };
+
+        let firing = state.firing.load(Ordering::Acquire);
+        if !((!firing && matches) || (firing && resolved)) {
+            return;
+        }
+        // if another thread checking the same series toggled the alert first,
+        // it has already invoked the callback.
+        if state
+            .firing
+            .compare_exchange(firing, !firing, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            (self.callback)(&AlertEvent {
+                name: self.name,
+                family,
+                labels,
+                value,
+                firing: !firing,
+            });
+        }
+    }
+
+    /// Returns the state for the series identified by `key`, claiming an
+    /// unused state (checked at `epoch`) if this is the first time it has
+    /// been checked.
+    fn state(&self, key: SeriesKey, epoch: usize) -> Option<&SeriesState> {
+        if let Some(state) = self.find(key) {
+            return Some(state);
+        }
+
+        let claimed = self.series.iter().find(|state| {
+            state
+                .metric
+                .compare_exchange(0, CLAIMING, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        })?;
+        claimed.labels.store(key.labels, Ordering::Release);
+        claimed.family.store(key.family, Ordering::Release);
+        claimed.epoch.store(epoch, Ordering::Release);
+        claimed.metric.store(key.metric, Ordering::Release);
+
+        // a concurrent check of the same series may also have claimed a
+        // state. every check keeps the first of them, and releases the other.
+        match self.find(key) {
+            Some(state) if !ptr::eq(state, claimed) => {
+                claimed.release();
+                Some(state)
+            }
+            _ => Some(claimed),
+        }
+    }
+
+    /// Returns the first state for the series identified by `key`, if there
+    /// is one.
+    fn find(&self, key: SeriesKey) -> Option<&SeriesState> {
+        let state = self.series.iter().find(|state| {
+            let mut metric = state.metric.load(Ordering::Acquire);
+            // another thread is claiming this state, possibly for the same
+            // series, so wait for it to finish.
+            while metric == CLAIMING {
+                hint::spin_loop();
+                metric = state.metric.load(Ordering::Acquire);
+            }
+            metric == key.metric
+        })?;
+
+        if state.labels.swap(key.labels, Ordering::AcqRel) != key.labels {
+            // the series was removed, and a new series has reused its metric,
+            // so the old series' state doesn't apply to it.
+            state.firing.store(false, Ordering::Release);
+            state.previous.store(f64::NAN, Ordering::Release);
+        }
+        Some(state)
+    }
+}
+
+// === impl SeriesState ===
+
+impl SeriesState {
+    /// Marks this state as unused, so that it may be claimed by another
+    /// series.
+    fn release(&self) {
+        self.firing.store(false, Ordering::Release);
+        self.previous.store(f64::NAN, Ordering::Release);
+        self.family.store(0, Ordering::Release);
+        // the metric's address is cleared last, so that the state isn't
+        // claimed until it has been reset.
+        self.metric.store(0, Ordering::Release);
+    }
+}
+
+// === impl HashLabels ===
+
+impl HashLabels {
+    fn hash(labels: &impl FmtLabels) -> usize {
+        let mut hasher = Self(FnvHasher::new());
+        // formatting into a hasher can't fail.
+        let _ = labels.fmt_labels(&mut hasher);
+        // truncating the hash on 32-bit targets is fine.
+        hasher.0.finish() as usize
+    }
+}
+
+impl fmt::Write for HashLabels {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<const SERIES: usize> fmt::Debug for Alert<'_, SERIES> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Alert")
+            .field("name", &self.name)
+            .field("condition", &self.condition)
+            .field("hysteresis", &self.hysteresis)
+            .field("firing", &self.is_firing())
+            .finish_non_exhaustive()
+    }
+}
+
+// === impl AlertEvent ===
+
+impl<'a> AlertEvent<'a> {
+    /// Returns the name of the alert.
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the name of the metric family containing the series, or an
+    /// empty string if the alert was checked using [`Alert::check_metric`].
+    #[must_use]
+    pub fn family(&self) -> &'a str {
+        self.family
+    }
+
+    /// Returns the series' label set, formatted as it would be in the
+    /// OpenMetrics text format (without the surrounding braces).
+    #[must_use]
+    pub fn labels(&self) -> &'a dyn fmt::Display {
+        self.labels
+    }
+
+    /// Returns the metric's value when the alert was checked.
+    #[must_use]
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Returns `true` if the alert started firing, or `false` if it was
+    /// resolved.
+    #[must_use]
+    pub fn is_firing(&self) -> bool {
+        self.firing
+    }
+}
+
+impl fmt::Debug for AlertEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlertEvent")
+            .field("name", &self.name)
+            .field("family", &self.family)
+            .field("labels", &format_args!("{}", self.labels))
+            .field("value", &self.value)
+            .field("firing", &self.firing)
+            .finish()
+    }
+}
+
+// === impl DisplayLabels ===
+
+impl<L: FmtLabels> fmt::Display for DisplayLabels<'_, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_labels(f)
+    }
+}
+
+// === impl AlertValue ===
+
+impl AlertValue for Gauge {
+    fn alert_value(&self) -> f64 {
+        self.value()
+    }
+}
+
+impl AlertValue for Unknown {
+    fn alert_value(&self) -> f64 {
+        self.value()
+    }
+}
+
+impl AlertValue for Counter {
+    fn alert_value(&self) -> f64 {
+        self.value() as f64
+    }
+}
+
+impl AlertValue for IntGauge {
+    fn alert_value(&self) -> f64 {
+        self.value() as f64
+    }
+}
+
+impl AlertValue for IsizeGauge {
+    fn alert_value(&self) -> f64 {
+        self.value() as f64
+    }
+}
+
+impl<const SCALE: u32> AlertValue for FixedGauge<SCALE> {
+    fn alert_value(&self) -> f64 {
+        self.value_f64()
+    }
+}
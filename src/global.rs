@@ -0,0 +1,146 @@
+//! A global set of metric families, collected from across crates.
+//!
+//! Metric families which are defined in `static`s may be added to the global
+//! set in one of two ways:
+//!
+//! - When the "linkme" feature flag is enabled, a `static` annotated with the
+//!   [`#[tinymetrics::export]`][export] attribute is collected at link time,
+//!   using the [`linkme`] crate's linker-section based distributed slices.
+//!   This allows metric families defined in any crate in the dependency graph
+//!   to be exposed by the top-level binary, without the binary having to list
+//!   every family. The "linkme" feature only works on targets whose linkers
+//!   `linkme` supports; see [its documentation][linkme-platforms] for details.
+//! - On other targets, metric families may be added to the global set at
+//!   runtime using the [`register`] function. Up to [`CAPACITY`] families may
+//!   be registered this way.
+//!
+//! When the "linkme" feature is disabled, the `#[export]` attribute only
+//! defines the annotated `static`, so that code which uses it compiles on all
+//! targets; families must then be passed to [`register`] explicitly.
+//!
+//! # Examples
+//!
+//! ```
+//! use tinymetrics::{global, CounterFamily, GaugeFamily, MetricBuilder};
+//!
+//! static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests").build();
+//! static TEMPERATURE: GaugeFamily<'static, 2> = MetricBuilder::new("temperature").build();
+//!
+//! global::register(&REQUESTS).expect("global metric set has capacity");
+//! global::register(&TEMPERATURE).expect("global metric set has capacity");
+//!
+//! let mut exposition = String::new();
+//! global::fmt_metrics(&mut exposition).unwrap();
+//! assert!(exposition.contains("# TYPE requests counter\n"));
+//! assert!(exposition.contains("# TYPE temperature gauge\n"));
+//! assert!(exposition.ends_with("# EOF\n"));
+//! ```
+//!
+//! [export]: crate::export
+//! [`linkme`]: https://docs.rs/linkme
+//! [linkme-platforms]: https://docs.rs/linkme#platform-support
+use crate::{registry::Registry, FmtMetricFamily, Format};
+use core::{fmt, ptr};
+
+/// A reference to a metric family in the global set.
+pub type GlobalFamily = &'static (dyn FmtMetricFamily + Sync);
+
+/// The maximum number of metric families which may be added to the global set
+/// using [`register`].
+///
+/// Metric families collected at link time do not count against this limit.
+pub const CAPACITY: usize = 32;
+
+static REGISTERED: Registry<GlobalFamily, CAPACITY> = Registry::new();
+
+#[cfg(feature = "linkme")]
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static EXPORTED: [GlobalFamily];
+
+/// Adds `family` to the global set of metric families at runtime.
+///
+/// Registering a family which is already in the global set (either because it
+/// was previously registered, or because it was collected at link time) does
+/// nothing. Concurrent calls which register the same family add it to the
+/// global set only once.
+///
+/// # Returns
+///
+/// - [`Ok`]`(())` if the family is in the global set.
+/// - [`Err`]`(family)` if [`CAPACITY`] families have already been registered.
+pub fn register(family: GlobalFamily) -> Result<(), GlobalFamily> {
+    if exported().any(|exported| same_family(exported, family)) {
+        return Ok(());
+    }
+    REGISTERED
+        .try_register_unique(family, |&a, &b| same_family(a, b))
+        .map(|_| ())
+}
+
+/// Returns an iterator over every metric family in the global set.
+///
+/// Families collected at link time are returned first, followed by families
+/// added with [`register`], in the order in which they were registered.
+pub fn families() -> impl Iterator<Item = GlobalFamily> {
+    exported().chain(REGISTERED.iter().copied())
+}
+
+/// Formats every metric family in the global set as a single OpenMetrics
+/// exposition, including the terminating `# EOF` line.
+pub fn fmt_metrics(writer: &mut impl fmt::Write) -> fmt::Result {
+    fmt_metrics_as(Format::OpenMetrics, writer)
+}
+
+/// Formats every metric family in the global set as a single exposition in
+/// the provided text exposition `format`.
+///
+/// In the OpenMetrics format, this includes the terminating `# EOF` line.
+pub fn fmt_metrics_as(format: Format, writer: &mut impl fmt::Write) -> fmt::Result {
+    for family in families() {
+        family.fmt_metric_family_as(format, &[], &[], writer)?;
+    }
+    if format == Format::OpenMetrics {
+        writer.write_str("# EOF\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "linkme")]
+fn exported() -> impl Iterator<Item = GlobalFamily> {
+    EXPORTED.iter().copied()
+}
+
+#[cfg(not(feature = "linkme"))]
+fn exported() -> impl Iterator<Item = GlobalFamily> {
+    core::iter::empty()
+}
+
+/// Compares the data pointers of two families, ignoring their vtables (which
+/// may be duplicated across codegen units).
+fn same_family(a: GlobalFamily, b: GlobalFamily) -> bool {
+    ptr::eq(
+        a as *const (dyn FmtMetricFamily + Sync) as *const (),
+        b as *const (dyn FmtMetricFamily + Sync) as *const (),
+    )
+}
+
+#[cfg(feature = "linkme")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __export_family {
+    ($family:ident) => {
+        const _: () = {
+            #[$crate::__private::linkme::distributed_slice($crate::global::EXPORTED)]
+            #[linkme(crate = $crate::__private::linkme)]
+            static EXPORT: $crate::global::GlobalFamily = &$family;
+        };
+    };
+}
+
+#[cfg(not(feature = "linkme"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __export_family {
+    ($family:ident) => {};
+}
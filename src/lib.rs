@@ -2,11 +2,31 @@
 // #![warn(missing_docs, rustdoc::broken_intra_doc_links)]
 #![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod alert;
+pub mod global;
 mod metric;
+pub mod parse;
 pub mod registry;
+mod set;
+pub mod timer;
 #[cfg(feature = "timestamp")]
 pub(crate) mod timestamp;
 pub use self::metric::*;
+pub use self::set::MetricSet;
+#[cfg(feature = "serde-set")]
+pub use self::set::SerializeMetricFamily;
+
+#[cfg(feature = "derive")]
+pub use tinymetrics_derive::{export, FmtLabels, Metrics};
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "linkme")]
+    pub use linkme;
+}
 
 #[cfg(feature = "timestamp")]
 pub use self::timestamp::UnixTimestamp;
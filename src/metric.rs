@@ -1,34 +1,88 @@
-use crate::registry::RegistryMap;
-use core::fmt;
-use portable_atomic::{AtomicBool, AtomicF64, AtomicUsize, Ordering};
+use crate::registry::{MapRef, Registry, RegistryMap, Storage};
+use core::{fmt, marker::PhantomData};
+use portable_atomic::{AtomicBool, AtomicF64, AtomicIsize, AtomicUsize, Ordering};
 #[cfg(feature = "serde")]
 use serde::{Serialize, Serializer};
 
 #[cfg(feature = "timestamp")]
 use crate::timestamp::{TimestampCell, UnixTimestamp};
 
+mod aggregate;
+mod buckets;
+mod fixed;
+mod format;
+mod group;
+mod histogram;
+mod matcher;
+mod merge;
+mod meter;
+mod snapshot;
 #[cfg(test)]
 mod tests;
 
+pub use self::fixed::FixedGauge;
+pub use self::format::Format;
+pub use self::group::{Group, GroupBy, GroupValue};
+pub use self::histogram::{GaugeHistogram, Histogram};
+pub use self::matcher::{MatchOp, Matcher, ParseMatcherError};
+pub use self::merge::{Instanced, MergeError, MergeMetric};
+pub use self::meter::Meter;
+pub use self::snapshot::CounterSnapshot;
+
 /// A builder for constructing [`MetricFamily`] instances.
 #[derive(Debug)]
 pub struct MetricBuilder<'a> {
     name: &'a str,
     help: &'a str,
     unit: &'a str,
+    buckets: &'a [f64],
+    const_labels: LabelSlice<'a>,
+    overflow: bool,
     #[cfg(feature = "timestamp")]
     timestamp_fn: Option<fn() -> UnixTimestamp>,
+    #[cfg(feature = "timestamp")]
+    expiry_secs: Option<u64>,
 }
 
 /// An OpenMetrics [MetricFamily].
 ///
 /// A MetricFamily is a collection of metrics points with the same name and metadata.
 ///
+/// By default, the metrics in a family are stored in a [`RegistryMap`], which
+/// looks up label sets using a linear scan. Families with many label sets may
+/// use a [`HashRegistryMap`](crate::registry::HashRegistryMap) instead, by
+/// providing it as the `R` type parameter and constructing the family with
+/// [`MetricBuilder::build_with_storage`].
+///
 /// [MetricFamily]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#metricfamily
 #[derive(Debug)]
-pub struct MetricFamily<'a, M, const METRICS: usize, L = LabelSlice<'a>> {
+pub struct MetricFamily<
+    'a,
+    M,
+    const METRICS: usize,
+    L = LabelSlice<'a>,
+    R = RegistryMap<L, M, METRICS>,
+> {
     def: MetricBuilder<'a>,
-    metrics: RegistryMap<L, M, METRICS>,
+    metrics: R,
+    /// The series which label sets are folded into once `metrics` is full, if
+    /// the overflow policy is enabled.
+    overflow: Registry<M, 1>,
+    /// The number of registrations which failed because `metrics` was full.
+    dropped: AtomicUsize,
+    labels: PhantomData<fn(&L)>,
+}
+
+/// A counted reference to a metric in a [`MetricFamily`], returned by
+/// [`MetricFamily::register_ref`].
+///
+/// If the metric is [unregistered](MetricFamily::unregister), its slot in the
+/// family is not reused until every `MetricRef` to it has been dropped.
+pub struct MetricRef<'a, M, L>(MetricRefInner<'a, M, L>);
+
+enum MetricRefInner<'a, M, L> {
+    Series(MapRef<'a, L, M>),
+    Overflow(&'a M),
 }
 
 pub type GaugeFamily<'a, const METRICS: usize, L = LabelSlice<'a>> =
@@ -56,15 +110,144 @@ pub trait FmtLabels {
 pub trait Metric {
     const TYPE: &'static str;
 
+    /// The number of buckets (not including the `+Inf` bucket) of this metric
+    /// type, if it is a histogram.
+    ///
+    /// Families of histogram metrics must be built with exactly this many
+    /// [bucket bounds](MetricBuilder::with_buckets).
+    const BUCKETS: Option<usize> = None;
+
     fn has_been_recorded(&self) -> bool {
         true
     }
 
     fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result;
 
+    /// Formats every [MetricPoint] in the series with the provided `name` and
+    /// `labels`, one sample per line.
+    ///
+    /// By default, this writes a single sample whose value is formatted by
+    /// [`Metric::fmt_metric`]. Metric types which export more than one sample
+    /// per label set override this method.
+    ///
+    /// [MetricPoint]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#metricpoint
+    fn fmt_series<F: fmt::Write>(
+        &self,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        fmt_sample_name(writer, name, "", labels)?;
+        writer.write_char(' ')?;
+        self.fmt_metric(writer)?;
+        writer.write_char('\n')
+    }
+
+    /// Formats the value of this metric (and its timestamp, if it has one) in
+    /// the provided text exposition `format`.
+    ///
+    /// By default, this calls [`Metric::fmt_metric`], ignoring `format`.
+    /// Metric types which record timestamps override this method, so that
+    /// timestamps are formatted in milliseconds in the [Prometheus] format.
+    ///
+    /// [Prometheus]: Format::Prometheus
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        let _ = format;
+        self.fmt_metric(writer)
+    }
+
+    /// Formats every sample in the series with the provided `name` and
+    /// `labels` in the provided text exposition `format`.
+    ///
+    /// In the [OpenMetrics](Format::OpenMetrics) format, this calls
+    /// [`Metric::fmt_series`]. Otherwise, it writes a single sample whose value
+    /// is formatted by [`Metric::fmt_metric_as`]. Metric types which override
+    /// [`Metric::fmt_series`] should also override this method.
+    fn fmt_series_as<F: fmt::Write>(
+        &self,
+        format: Format,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        if format == Format::OpenMetrics {
+            return self.fmt_series(name, labels, writer);
+        }
+        fmt_sample_name(writer, name, format.name_suffix(Self::TYPE, name), labels)?;
+        writer.write_char(' ')?;
+        self.fmt_metric_as(format, writer)?;
+        writer.write_char('\n')
+    }
+
+    /// Returns the time at which this metric was last updated, or `None` if
+    /// it has never been updated or does not record timestamps.
+    ///
+    /// This is used to determine whether a metric has [expired].
+    ///
+    /// [expired]: MetricBuilder::with_expiry
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        None
+    }
+
     fn build(builder: &MetricBuilder<'_>) -> Self;
 }
 
+/// Two label sets, formatted one after the other.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Chain<A, B>(pub(crate) A, pub(crate) B);
+
+/// An object-safe trait implemented by every [`MetricFamily`], regardless of
+/// its metric type, capacity, and label set type.
+///
+/// This allows metric families of different types to be stored together as
+/// trait objects, such as in a [`MetricSet`](crate::MetricSet).
+pub trait FmtMetricFamily {
+    /// Returns the name of this metric family.
+    fn name(&self) -> &str;
+
+    /// Returns the OpenMetrics type of the metrics in this family.
+    fn metric_type(&self) -> &'static str;
+
+    /// Formats this metric family in the OpenMetrics text exposition format.
+    fn fmt_metric_family(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        self.fmt_metric_family_with_labels(&[], writer)
+    }
+
+    /// Formats this metric family in the OpenMetrics text exposition format,
+    /// adding `const_labels` to every series.
+    fn fmt_metric_family_with_labels(
+        &self,
+        const_labels: &[(&str, &str)],
+        writer: &mut dyn fmt::Write,
+    ) -> fmt::Result {
+        self.fmt_metric_family_matching(const_labels, &[], writer)
+    }
+
+    /// Formats this metric family in the OpenMetrics text exposition format,
+    /// adding `const_labels` to every series, and including only the series
+    /// selected by every matcher in `matchers`.
+    fn fmt_metric_family_matching(
+        &self,
+        const_labels: &[(&str, &str)],
+        matchers: &[Matcher<'_>],
+        writer: &mut dyn fmt::Write,
+    ) -> fmt::Result {
+        self.fmt_metric_family_as(Format::OpenMetrics, const_labels, matchers, writer)
+    }
+
+    /// Formats this metric family in the provided text exposition `format`,
+    /// adding `const_labels` to every series, and including only the series
+    /// selected by every matcher in `matchers`.
+    fn fmt_metric_family_as(
+        &self,
+        format: Format,
+        const_labels: &[(&str, &str)],
+        matchers: &[Matcher<'_>],
+        writer: &mut dyn fmt::Write,
+    ) -> fmt::Result;
+}
+
 #[derive(Debug)]
 pub struct Gauge {
     value: AtomicF64,
@@ -90,9 +273,44 @@ pub struct IntGauge {
     timestamp: Option<TimestampCell>,
 }
 
+/// A gauge metric whose value is always a signed integer.
+///
+/// This is similar to the [`IntGauge`] metric type, but its value is
+/// represented by an [`AtomicIsize`], so that it may be negative. Like
+/// [`IntGauge`], it is exported as though it were a standard Gauge metric, and
+/// is intended for use on hardware platforms that lack 64-bit hardware
+/// floating point.
+#[derive(Debug)]
+pub struct IsizeGauge {
+    value: AtomicIsize,
+    recorded: AtomicBool,
+
+    #[cfg(feature = "timestamp")]
+    timestamp: Option<TimestampCell>,
+}
+
+/// An OpenMetrics [Unknown] metric, whose semantics are not known.
+///
+/// This is intended for passing through values from other systems (such as
+/// third-party hardware) without mislabeling them as a Gauge or Counter. Like
+/// a [`Gauge`], its value is an `f64` which may be set arbitrarily.
+///
+/// [Unknown]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#unknown
+#[derive(Debug)]
+pub struct Unknown {
+    value: AtomicF64,
+    recorded: AtomicBool,
+
+    #[cfg(feature = "timestamp")]
+    timestamp: Option<TimestampCell>,
+}
+
 #[derive(Debug)]
 pub struct Counter {
     value: AtomicUsize,
+    /// The number of times this counter has been reset by [`Counter::swap`],
+    /// so that a reset can be told apart from the value wrapping around.
+    resets: AtomicUsize,
 
     #[cfg(feature = "timestamp")]
     timestamp: Option<TimestampCell>,
@@ -126,7 +344,7 @@ impl<L: FmtLabels, const LEN: usize> FmtLabels for [L; LEN] {
     }
 
     fn is_empty(&self) -> bool {
-        LEN > 0
+        LEN == 0
     }
 }
 
@@ -165,6 +383,101 @@ impl<L: FmtLabels> FmtLabels for &'_ L {
     }
 }
 
+impl<A: FmtLabels, B: FmtLabels> FmtLabels for Chain<A, B> {
+    fn fmt_labels(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        let Self(a, b) = self;
+        a.fmt_labels(writer)?;
+        if !a.is_empty() && !b.is_empty() {
+            writer.write_char(',')?;
+        }
+        b.fmt_labels(writer)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty() && self.1.is_empty()
+    }
+}
+
+/// Writes a single label, `key="value"`, escaping the label's value as
+/// required by the OpenMetrics text format.
+///
+/// This is intended for use by hand-written (and derived) implementations of
+/// [`FmtLabels`].
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::fmt_label;
+///
+/// let mut label = String::new();
+/// fmt_label(&mut label, "path", &"C:\\Users\\\"eliza\"").unwrap();
+/// assert_eq!(label, r#"path="C:\\Users\\\"eliza\"""#);
+/// ```
+pub fn fmt_label(
+    writer: &mut impl fmt::Write,
+    key: &str,
+    value: &impl fmt::Display,
+) -> fmt::Result {
+    write!(writer, "{key}=\"")?;
+    fmt::Write::write_fmt(&mut Escape(&mut *writer), format_args!("{value}"))?;
+    writer.write_char('"')
+}
+
+/// A writer which escapes label values.
+struct Escape<W>(W);
+
+impl<W: fmt::Write> fmt::Write for Escape<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(idx) = rest.find(['\\', '"', '\n']) {
+            let (unescaped, escaped) = rest.split_at(idx);
+            self.0.write_str(unescaped)?;
+            match escaped.as_bytes()[0] {
+                b'\\' => self.0.write_str("\\\\")?,
+                b'"' => self.0.write_str("\\\"")?,
+                _ => self.0.write_str("\\n")?,
+            }
+            rest = &escaped[1..];
+        }
+        self.0.write_str(rest)
+    }
+}
+
+/// Formats an `f64` sample value, spelling non-finite values as the
+/// OpenMetrics text format requires (`+Inf`, `-Inf`, and `NaN`), rather than
+/// as Rust's `inf`, `-inf`, and `NaN`.
+pub(crate) struct FmtF64(pub(crate) f64);
+
+impl fmt::Display for FmtF64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            value if value.is_nan() => f.write_str("NaN"),
+            f64::INFINITY => f.write_str("+Inf"),
+            f64::NEG_INFINITY => f.write_str("-Inf"),
+            value => fmt::Display::fmt(&value, f),
+        }
+    }
+}
+
+/// Writes the name of a sample, followed by its label set, if it is not empty.
+pub(crate) fn fmt_sample_name(
+    writer: &mut impl fmt::Write,
+    name: &str,
+    suffix: &str,
+    labels: &impl FmtLabels,
+) -> fmt::Result {
+    writer.write_str(name)?;
+    writer.write_str(suffix)?;
+
+    if !labels.is_empty() {
+        writer.write_char('{')?;
+        labels.fmt_labels(writer)?;
+        writer.write_char('}')?;
+    }
+
+    Ok(())
+}
+
 // === impl MetricBuilder ===
 
 impl<'a> MetricBuilder<'a> {
@@ -173,12 +486,18 @@ impl<'a> MetricBuilder<'a> {
             name,
             help: "",
             unit: "",
+            buckets: &[],
+            const_labels: &[],
+            overflow: false,
 
             #[cfg(all(feature = "std", feature = "timestamp"))]
             timestamp_fn: Some(UnixTimestamp::now),
 
             #[cfg(all(not(feature = "std"), feature = "timestamp"))]
             timestamp_fn: None,
+
+            #[cfg(feature = "timestamp")]
+            expiry_secs: None,
         }
     }
 
@@ -190,6 +509,61 @@ impl<'a> MetricBuilder<'a> {
         Self { unit, ..self }
     }
 
+    /// Sets the upper bounds of the buckets of histogram metrics in this
+    /// family, such as [`Histogram`] and [`GaugeHistogram`].
+    ///
+    /// A final `+Inf` bucket is always included, and need not be provided.
+    ///
+    /// # Panics
+    ///
+    /// If any bound is infinite or NaN, or the bounds are not sorted in
+    /// strictly ascending order. When the family is built, the number of
+    /// bounds must also equal the number of buckets of its metric type. In a
+    /// `static`, these are checked at compile time.
+    pub const fn with_buckets(self, buckets: &'a [f64]) -> Self {
+        let mut i = 0;
+        while i < buckets.len() {
+            // NaN fails both comparisons.
+            assert!(
+                buckets[i] > f64::NEG_INFINITY && buckets[i] < f64::INFINITY,
+                "histogram bucket bounds must be finite"
+            );
+            assert!(
+                i == 0 || buckets[i - 1] < buckets[i],
+                "histogram bucket bounds must be sorted in ascending order"
+            );
+            i += 1;
+        }
+        Self { buckets, ..self }
+    }
+
+    /// Sets labels which are added to every series in this family.
+    ///
+    /// Constant labels are stored once, in the family's definition, rather
+    /// than in each series' label set, and are formatted after each series'
+    /// own labels. When the family is serialized, they are included as a
+    /// separate `const_labels` map.
+    pub const fn with_const_labels(self, const_labels: &'a [(&'a str, &'a str)]) -> Self {
+        Self {
+            const_labels,
+            ..self
+        }
+    }
+
+    /// Enables the overflow policy for this family.
+    ///
+    /// By default, once a family has registered `METRICS` label sets,
+    /// [`MetricFamily::register`] returns `None` for any new label set. With
+    /// the overflow policy enabled, new label sets are instead folded into a
+    /// single reserved series with the label set `{overflow="true"}`, so that
+    /// the values recorded for them are not lost.
+    pub const fn with_overflow(self) -> Self {
+        Self {
+            overflow: true,
+            ..self
+        }
+    }
+
     #[cfg(feature = "timestamp")]
     pub const fn with_timestamp(self, timestamp_fn: fn() -> UnixTimestamp) -> Self {
         Self {
@@ -206,6 +580,38 @@ impl<'a> MetricBuilder<'a> {
         }
     }
 
+    /// Sets an expiry period for metrics in this family.
+    ///
+    /// Once a metric has not been updated for longer than `expiry`, it is no
+    /// longer exported, and its slot may be reclaimed when a new label set is
+    /// registered while the family is full. If an expired metric is updated
+    /// again before its slot is reclaimed, it is exported again.
+    ///
+    /// Expiry is based on the timestamp of each metric's last update, so it
+    /// has no effect if timestamps are disabled. Metrics which have never
+    /// been updated do not expire. Metrics registered using
+    /// [`MetricFamily::register`] are never removed, so their slots are never
+    /// reclaimed; use [`MetricFamily::register_ref`] for metrics which may
+    /// expire.
+    #[cfg(feature = "timestamp")]
+    pub const fn with_expiry(self, expiry: core::time::Duration) -> Self {
+        Self {
+            expiry_secs: Some(expiry.as_secs()),
+            ..self
+        }
+    }
+
+    /// Asserts that this builder has as many bucket bounds as the histogram
+    /// metric type `M` has buckets.
+    const fn check_buckets<M: Metric>(&self) {
+        if let Some(buckets) = M::BUCKETS {
+            assert!(
+                self.buckets.len() == buckets,
+                "the number of bucket bounds must equal the number of histogram buckets"
+            );
+        }
+    }
+
     #[cfg(feature = "timestamp")]
     const fn mk_timestamp(&self) -> Option<TimestampCell> {
         match self.timestamp_fn {
@@ -218,9 +624,13 @@ impl<'a> MetricBuilder<'a> {
     where
         M: Metric,
     {
+        self.check_buckets::<M>();
         MetricFamily {
             def: self,
             metrics: RegistryMap::new(),
+            overflow: Registry::new(),
+            dropped: AtomicUsize::new(0),
+            labels: PhantomData,
         }
     }
 
@@ -229,79 +639,407 @@ impl<'a> MetricBuilder<'a> {
         M: Metric,
         L: FmtLabels + PartialEq,
     {
+        self.check_buckets::<M>();
         MetricFamily {
             def: self,
             metrics: RegistryMap::new(),
+            overflow: Registry::new(),
+            dropped: AtomicUsize::new(0),
+            labels: PhantomData,
+        }
+    }
+
+    /// Builds a metric family whose metrics are stored in `R`, such as a
+    /// [`HashRegistryMap`](crate::registry::HashRegistryMap).
+    ///
+    /// # Panics
+    ///
+    /// If `R`'s [capacity](Storage::CAPACITY) is not `METRICS`.
+    pub const fn build_with_storage<M, L, R, const METRICS: usize>(
+        self,
+    ) -> MetricFamily<'a, M, METRICS, L, R>
+    where
+        M: Metric,
+        L: FmtLabels + PartialEq,
+        R: Storage<L, M>,
+    {
+        assert!(
+            R::CAPACITY == METRICS,
+            "the capacity of a metric family's storage must equal its number of metrics"
+        );
+        self.check_buckets::<M>();
+        MetricFamily {
+            def: self,
+            metrics: R::EMPTY,
+            overflow: Registry::new(),
+            dropped: AtomicUsize::new(0),
+            labels: PhantomData,
         }
     }
 }
 
 // === impl MetricFamily ===
 
-impl<M, const METRICS: usize, L> MetricFamily<'_, M, METRICS, L> {
-    pub fn metrics(&self) -> &RegistryMap<L, M, METRICS> {
+impl<'a, M, const METRICS: usize, L, R> MetricFamily<'a, M, METRICS, L, R> {
+    pub fn metrics(&self) -> &R {
         &self.metrics
     }
+
+    /// Returns the name of this metric family.
+    pub fn name(&self) -> &str {
+        self.def.name
+    }
+
+    /// Returns this metric family's help text.
+    pub fn help(&self) -> &str {
+        self.def.help
+    }
+
+    /// Returns this metric family's unit.
+    pub fn unit(&self) -> &str {
+        self.def.unit
+    }
+
+    /// Returns the [constant labels](MetricBuilder::with_const_labels) added
+    /// to every series in this family.
+    pub fn const_labels(&self) -> &'a [(&'a str, &'a str)] {
+        self.def.const_labels
+    }
+
+    /// Returns the number of times a new label set could not be registered
+    /// because this family was full.
+    ///
+    /// This counts failed calls to [`register`](Self::register) and
+    /// [`register_ref`](Self::register_ref), rather than distinct label sets:
+    /// registering the same label set twice while the family is full counts
+    /// as two dropped registrations. This includes registrations which were
+    /// folded into the overflow series, if the [overflow policy] is enabled.
+    ///
+    /// [overflow policy]: MetricBuilder::with_overflow
+    pub fn dropped_registrations(&self) -> usize {
+        self.dropped.load(Ordering::Acquire)
+    }
+
+    /// Returns the overflow series, if any label sets have been folded into it.
+    pub fn overflow(&self) -> Option<&M> {
+        self.overflow.iter().next()
+    }
 }
 
-impl<M, L, const METRICS: usize> MetricFamily<'_, M, METRICS, L>
+impl<M, L, R, const METRICS: usize> MetricFamily<'_, M, METRICS, L, R>
 where
     M: Metric,
     L: FmtLabels + PartialEq,
+    R: Storage<L, M>,
 {
+    /// Returns the metric with the provided `labels`, registering a new metric
+    /// if none exists.
+    ///
+    /// If this family is full, the registration is counted as dropped, and
+    /// this returns `None`, unless the [overflow policy] is enabled, in which
+    /// case the overflow series is returned. While another thread is creating
+    /// the overflow series, this also returns `None`, rather than waiting.
+    ///
+    /// [overflow policy]: MetricBuilder::with_overflow
     pub fn register(&self, labels: L) -> Option<&M> {
-        self.metrics
-            .get_or_register_with(labels, || M::build(&self.def))
+        match self.get_or_register_series(labels) {
+            Some(entry) => Some(entry.leak().1),
+            None => self.register_overflow(),
+        }
+    }
+
+    /// Returns a [counted reference](MetricRef) to the metric with the provided
+    /// `labels`, registering a new metric if none exists.
+    ///
+    /// Unlike [`register`](Self::register), which returns a reference that may
+    /// live as long as the family itself, the metric's slot in this family may
+    /// be reused once it is [unregistered](Self::unregister) and every
+    /// `MetricRef` to it has been dropped. Metrics whose label sets churn (such
+    /// as per-connection metrics) should be registered using this method.
+    ///
+    /// If this family is full, this behaves like [`register`](Self::register).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::{CounterFamily, MetricBuilder};
+    ///
+    /// static CONN_BYTES: CounterFamily<'static, 1> = MetricBuilder::new("conn_bytes").build();
+    ///
+    /// let conn1 = CONN_BYTES.register_ref(&[("peer", "10.0.0.1")]).unwrap();
+    /// conn1.fetch_add(512);
+    ///
+    /// // the connection closes, so its metric is removed.
+    /// drop(conn1);
+    /// assert!(CONN_BYTES.unregister(&&[("peer", "10.0.0.1")][..]));
+    ///
+    /// // its slot may now be reused by another connection.
+    /// let conn2 = CONN_BYTES.register_ref(&[("peer", "10.0.0.2")]).unwrap();
+    /// assert_eq!(conn2.value(), 0);
+    /// ```
+    pub fn register_ref(&self, labels: L) -> Option<MetricRef<'_, M, L>> {
+        match self.get_or_register_series(labels) {
+            Some(entry) => Some(MetricRef(MetricRefInner::Series(entry))),
+            None => self
+                .register_overflow()
+                .map(|metric| MetricRef(MetricRefInner::Overflow(metric))),
+        }
+    }
+
+    /// Removes the metric with the provided `labels` from this family,
+    /// returning `true` if a metric was removed.
+    ///
+    /// A removed metric is no longer exported. Once every [`MetricRef`] to it
+    /// has been dropped, its slot may be reused by a new label set. However,
+    /// if a plain reference to the metric was ever handed out (such as by
+    /// [`register`](Self::register)), its slot is never reused.
+    pub fn unregister(&self, labels: &L) -> bool {
+        self.metrics.remove(labels)
+    }
+
+    /// Removes every metric which has [expired] from this family.
+    ///
+    /// This is called automatically when a new label set can't be registered
+    /// because the family is full, but may also be called periodically so
+    /// that expired metrics are dropped promptly.
+    ///
+    /// Only metrics registered with [`register_ref`](Self::register_ref) are
+    /// removed. A metric returned by [`register`](Self::register) may be
+    /// updated through its reference at any time, so it is never removed;
+    /// it is only omitted from the exposition while it is expired.
+    ///
+    /// [expired]: MetricBuilder::with_expiry
+    pub fn remove_expired(&self) {
+        for entry in self.metrics.refs() {
+            if self.is_expired(entry.value()) {
+                entry.remove_unpinned();
+            }
+        }
+    }
+
+    /// Returns the metric with the provided `labels`, registering a new metric
+    /// if none exists. If the family is full and has an expiry period, expired
+    /// metrics are removed to make room for the new metric.
+    fn get_or_register_series(&self, labels: L) -> Option<MapRef<'_, L, M>> {
+        let init = || M::build(&self.def);
+        match self.metrics.get_or_try_register_ref_with(labels, init) {
+            Ok(entry) => Some(entry),
+            #[cfg(feature = "timestamp")]
+            Err(labels) if self.def.expiry_secs.is_some() => {
+                self.remove_expired();
+                self.metrics.get_or_try_register_ref_with(labels, init).ok()
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Returns `true` if `metric` has not been updated within this family's
+    /// expiry period.
+    #[cfg_attr(not(feature = "timestamp"), allow(unused_variables))]
+    fn is_expired(&self, metric: &M) -> bool {
+        #[cfg(feature = "timestamp")]
+        if let (Some(expiry_secs), Some(now), Some(updated)) = (
+            self.def.expiry_secs,
+            self.def.timestamp_fn,
+            metric.last_updated(),
+        ) {
+            return now().as_secs().saturating_sub(updated.as_secs()) > expiry_secs;
+        }
+
+        false
+    }
+
+    /// Records a dropped registration, returning the overflow series if the
+    /// overflow policy is enabled.
+    fn register_overflow(&self) -> Option<&M> {
+        self.dropped.fetch_add(1, Ordering::AcqRel);
+        if !self.def.overflow {
+            return None;
+        }
+
+        if let Some(metric) = self.overflow() {
+            return Some(metric);
+        }
+        // if another thread is initializing the overflow series, this
+        // registration is dropped rather than waiting for it.
+        self.overflow.try_register(M::build(&self.def)).ok()
     }
 
     pub fn fmt_metric(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        self.fmt_metric_with_labels(&[], writer)
+    }
+
+    /// Formats this metric family, adding `const_labels` to every series in
+    /// addition to the family's own constant labels.
+    pub fn fmt_metric_with_labels(
+        &self,
+        const_labels: &[(&str, &str)],
+        writer: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        self.fmt_metric_inner(Format::OpenMetrics, const_labels, &[], writer)
+    }
+
+    /// Formats this metric family in the provided text exposition `format`.
+    ///
+    /// See [`Format`] for details.
+    pub fn fmt_metric_as(&self, format: Format, writer: &mut impl fmt::Write) -> fmt::Result {
+        self.fmt_metric_inner(format, &[], &[], writer)
+    }
+
+    /// Formats this metric family, including only the series whose labels
+    /// (including the family's constant labels) are selected by every
+    /// matcher in `matchers`.
+    ///
+    /// The family's metadata is always formatted, even if no series match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::{CounterFamily, Matcher, MetricBuilder};
+    ///
+    /// static RX_BYTES: CounterFamily<'static, 4> = MetricBuilder::new("rx_bytes").build();
+    ///
+    /// RX_BYTES.register(&[("port", "eth0")]).unwrap().fetch_add(1);
+    /// RX_BYTES.register(&[("port", "eth1")]).unwrap().fetch_add(2);
+    ///
+    /// let mut exposition = String::new();
+    /// RX_BYTES
+    ///     .fmt_metric_matching(&[Matcher::equal("port", "eth0")], &mut exposition)
+    ///     .unwrap();
+    /// assert!(exposition.contains("rx_bytes{port=\"eth0\"}"));
+    /// assert!(!exposition.contains("rx_bytes{port=\"eth1\"}"));
+    /// ```
+    pub fn fmt_metric_matching(
+        &self,
+        matchers: &[Matcher<'_>],
+        writer: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        self.fmt_metric_inner(Format::OpenMetrics, &[], matchers, writer)
+    }
+
+    /// Returns an iterator over the metrics in this family whose labels
+    /// (including the family's constant labels) are selected by every matcher
+    /// in `matchers`.
+    pub fn iter_matching<'m>(
+        &'m self,
+        matchers: &'m [Matcher<'m>],
+    ) -> impl Iterator<Item = MapRef<'m, L, M>> + 'm {
+        self.metrics.refs().filter(move |entry| {
+            Matcher::matches_all(matchers, &Chain(entry.key(), self.def.const_labels))
+        })
+    }
+
+    /// Returns an iterator over groups of the recorded series in this family
+    /// which have the same value for the label `key`.
+    ///
+    /// Each [`Group`] provides the same aggregates as the family itself (such
+    /// as [`Group::total`] for counters), computed over only the series in
+    /// that group. Groups are returned in the order in which their first
+    /// series was registered. Series are grouped by their own labels; the
+    /// family's constant labels are not considered.
+    ///
+    /// Grouping does not allocate: the family's recorded series are assigned
+    /// to groups in a single pass when `group_by` is called, using a table of
+    /// up to `METRICS` entries on the stack, and each [`Group`] holds counted
+    /// references to its own series.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::{CounterFamily, MetricBuilder};
+    ///
+    /// static BYTES: CounterFamily<'static, 4> = MetricBuilder::new("bytes").build();
+    ///
+    /// BYTES.register(&[("interface", "eth0"), ("direction", "rx")]).unwrap().fetch_add(1);
+    /// BYTES.register(&[("interface", "eth0"), ("direction", "tx")]).unwrap().fetch_add(2);
+    /// BYTES.register(&[("interface", "eth1"), ("direction", "rx")]).unwrap().fetch_add(4);
+    ///
+    /// let mut totals = BYTES
+    ///     .group_by("interface")
+    ///     .map(|group| (group.value().to_string(), group.total()));
+    /// assert_eq!(totals.next(), Some(("eth0".to_string(), 3)));
+    /// assert_eq!(totals.next(), Some(("eth1".to_string(), 4)));
+    /// assert_eq!(totals.next(), None);
+    /// ```
+    pub fn group_by<'f>(&'f self, key: &'f str) -> GroupBy<'f, M, L, METRICS> {
+        GroupBy::new(self.metrics.refs(), key)
+    }
+
+    fn fmt_metric_inner(
+        &self,
+        format: Format,
+        const_labels: &[(&str, &str)],
+        matchers: &[Matcher<'_>],
+        writer: &mut impl fmt::Write,
+    ) -> fmt::Result {
         let Self {
             metrics,
             def: MetricBuilder {
                 name, help, unit, ..
             },
+            ..
         } = self;
 
-        writeln!(
-            writer,
-            "# TYPE {name} {ty}\n# UNIT {name} {unit}\n# HELP {name} {help}",
-            ty = M::TYPE
-        )?;
+        format.fmt_metadata(writer, (name, ""), M::TYPE, unit, help)?;
 
-        for (labels, metric) in metrics.iter() {
-            if !metric.has_been_recorded() {
+        let const_labels = Chain(self.def.const_labels, const_labels);
+        for entry in metrics.refs() {
+            let metric = entry.value();
+            let labels = Chain(entry.key(), &const_labels);
+            if !metric.has_been_recorded()
+                || self.is_expired(metric)
+                || !Matcher::matches_all(matchers, &labels)
+            {
                 continue;
             }
-            writer.write_str(name)?;
-
-            if !labels.is_empty() {
-                writer.write_char('{')?;
-                labels.fmt_labels(writer)?;
-                writer.write_char('}')?;
+            metric.fmt_series_as(format, name, &labels, writer)?;
+        }
+        if let Some(metric) = self.overflow() {
+            let labels = Chain(("overflow", "true"), &const_labels);
+            if metric.has_been_recorded()
+                && !self.is_expired(metric)
+                && Matcher::matches_all(matchers, &labels)
+            {
+                metric.fmt_series_as(format, name, &labels, writer)?;
             }
-
-            writer.write_char(' ')?;
-            metric.fmt_metric(writer)?;
-            writer.write_char('\n')?;
         }
         writer.write_char('\n')?;
 
+        let dropped = self.dropped_registrations();
+        if (dropped > 0 || self.def.overflow) && Matcher::matches_all(matchers, &const_labels) {
+            format.fmt_metadata(
+                writer,
+                (name, "_dropped_registrations"),
+                Counter::TYPE,
+                "",
+                format_args!("registrations which failed because {name} was full"),
+            )?;
+            fmt_sample_name(writer, name, "_dropped_registrations_total", &const_labels)?;
+            writeln!(writer, " {dropped}\n")?;
+        }
+
         Ok(())
     }
 }
 
-impl<M, L, const METRICS: usize> MetricFamily<'_, M, METRICS, L>
+impl<M, L, R, const METRICS: usize> MetricFamily<'_, M, METRICS, L, R>
 where
     M: Metric,
+    R: Storage<L, M>,
 {
-    fn iter_recorded(&self) -> impl Iterator<Item = (&L, &M)> + '_ {
-        self.metrics.iter().filter(|(_, m)| m.has_been_recorded())
+    fn iter_recorded(&self) -> impl Iterator<Item = MapRef<'_, L, M>> + '_ {
+        self.metrics
+            .refs()
+            .filter(|entry| entry.value().has_been_recorded())
     }
 }
 
-impl<L, const METRICS: usize> MetricFamily<'_, IntGauge, METRICS, L> {
+impl<L, R, const METRICS: usize> MetricFamily<'_, IntGauge, METRICS, L, R>
+where
+    R: Storage<L, IntGauge>,
+{
     fn recorded_values(&self) -> impl Iterator<Item = usize> + '_ {
-        self.iter_recorded().map(|(_, metric)| metric.value())
+        self.iter_recorded().map(|entry| entry.value().value())
     }
 
     #[must_use]
@@ -316,60 +1054,147 @@ impl<L, const METRICS: usize> MetricFamily<'_, IntGauge, METRICS, L> {
 
     #[must_use]
     pub fn mean(&self) -> Option<usize> {
-        let mut recorded = 0;
-        let mut sum = 0;
-        for val in self.recorded_values() {
-            recorded += 1;
-            sum += val;
-        }
+        aggregate::mean_usize(self.recorded_values())
+    }
+}
 
-        if recorded > 0 {
-            Some(sum / recorded)
-        } else {
-            None
-        }
+impl<L, R, const METRICS: usize> MetricFamily<'_, IsizeGauge, METRICS, L, R>
+where
+    R: Storage<L, IsizeGauge>,
+{
+    fn recorded_values(&self) -> impl Iterator<Item = isize> + '_ {
+        self.iter_recorded().map(|entry| entry.value().value())
+    }
+
+    #[must_use]
+    pub fn min_value(&self) -> Option<isize> {
+        self.recorded_values().min()
+    }
+
+    #[must_use]
+    pub fn max_value(&self) -> Option<isize> {
+        self.recorded_values().max()
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<isize> {
+        aggregate::mean_isize(self.recorded_values())
     }
 }
 
-impl<L, const METRICS: usize> MetricFamily<'_, Gauge, METRICS, L> {
+// aggregates over gauges ignore `NaN` values, as if the series holding them
+// had not been recorded.
+impl<L, R, const METRICS: usize> MetricFamily<'_, Gauge, METRICS, L, R>
+where
+    R: Storage<L, Gauge>,
+{
     fn recorded_values(&self) -> impl Iterator<Item = f64> + '_ {
-        self.iter_recorded().map(|(_, metric)| metric.value())
+        self.iter_recorded()
+            .map(|entry| entry.value().value())
+            .filter(|value| !value.is_nan())
     }
 
     #[must_use]
     pub fn min_value(&self) -> Option<f64> {
-        self.recorded_values().reduce(f64::min)
+        aggregate::min_f64(self.recorded_values())
     }
 
     #[must_use]
     pub fn max_value(&self) -> Option<f64> {
-        self.recorded_values().reduce(f64::max)
+        aggregate::max_f64(self.recorded_values())
     }
 
     #[must_use]
     pub fn total(&self) -> f64 {
-        self.recorded_values().sum()
+        aggregate::total_f64(self.recorded_values())
     }
 
     pub fn mean(&self) -> Option<f64> {
-        let mut recorded = 0;
-        let mut sum = 0.0;
-        for (_, metric) in self.iter_recorded() {
-            recorded += 1;
-            sum += metric.value();
-        }
+        aggregate::mean_f64(self.recorded_values())
+    }
 
-        if recorded > 0 {
-            Some(sum / recorded as f64)
-        } else {
-            None
+    /// Returns the median of the recorded values in this family.
+    ///
+    /// This is equivalent to `self.percentile(50.0)`.
+    #[must_use]
+    pub fn median(&self) -> Option<f64> {
+        self.percentile(50.0)
+    }
+
+    /// Returns the `p`th percentile of the recorded values in this family,
+    /// where `p` is between 0 and 100.
+    ///
+    /// If the percentile falls between two values, it is linearly
+    /// interpolated between them. `NaN` values are ignored.
+    ///
+    /// This does not allocate, or copy the values into a buffer: instead, the
+    /// values on either side of the percentile are found by bisection, which
+    /// takes a fixed number of passes over the family's series.
+    ///
+    /// # Returns
+    ///
+    /// - [`Some`]`(f64)` with the percentile, if any values have been
+    ///   recorded.
+    /// - [`None`] if no values have been recorded, or if `p` is not between 0
+    ///   and 100.
+    #[must_use]
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        aggregate::percentile(|| self.recorded_values(), p)
+    }
+
+    /// Returns the population variance of the recorded values in this
+    /// family, ignoring `NaN` values.
+    #[must_use]
+    pub fn variance(&self) -> Option<f64> {
+        aggregate::variance(self.recorded_values())
+    }
+
+    /// Returns the population standard deviation of the recorded values in
+    /// this family.
+    #[must_use]
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(sqrt)
+    }
+}
+
+/// Returns the square root of `x`.
+#[cfg(feature = "std")]
+fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+/// Returns the square root of `x`, using Newton's method, since `f64::sqrt`
+/// is not available without `std`.
+#[cfg(not(feature = "std"))]
+fn sqrt(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 || x.is_infinite() {
+        return x;
+    }
+
+    // start from an estimate which is within a factor of two of the root, by
+    // halving the exponent. after one iteration, the estimate is never less
+    // than the root, and each subsequent iteration decreases it until it
+    // converges.
+    let estimate = f64::from_bits((x.to_bits() >> 1) + (1023 << 51));
+    let mut root = 0.5 * (estimate + x / estimate);
+    loop {
+        let next = 0.5 * (root + x / root);
+        if next >= root {
+            return root;
         }
+        root = next;
     }
 }
 
-impl<L, const METRICS: usize> MetricFamily<'_, Counter, METRICS, L> {
+impl<L, R, const METRICS: usize> MetricFamily<'_, Counter, METRICS, L, R>
+where
+    R: Storage<L, Counter>,
+{
     fn recorded_values(&self) -> impl Iterator<Item = usize> + '_ {
-        self.iter_recorded().map(|(_, metric)| metric.value())
+        self.iter_recorded().map(|entry| entry.value().value())
     }
 
     #[must_use]
@@ -389,35 +1214,160 @@ impl<L, const METRICS: usize> MetricFamily<'_, Counter, METRICS, L> {
 
     #[must_use]
     pub fn mean(&self) -> Option<usize> {
-        let mut recorded = 0;
-        let mut sum = 0;
-        for val in self.recorded_values() {
-            recorded += 1;
-            sum += val;
-        }
-
-        if recorded > 0 {
-            Some(sum / recorded)
-        } else {
-            None
-        }
+        aggregate::mean_usize(self.recorded_values())
     }
 }
 
-impl<M, const METRICS: usize, L> fmt::Display for MetricFamily<'_, M, METRICS, L>
+impl<M, const METRICS: usize, L, R> fmt::Display for MetricFamily<'_, M, METRICS, L, R>
 where
     M: Metric,
     L: FmtLabels + PartialEq,
+    R: Storage<L, M>,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.fmt_metric(f)
     }
 }
 
+impl<M, const METRICS: usize, L, R> FmtMetricFamily for MetricFamily<'_, M, METRICS, L, R>
+where
+    M: Metric,
+    L: FmtLabels + PartialEq,
+    R: Storage<L, M>,
+{
+    fn name(&self) -> &str {
+        self.def.name
+    }
+
+    fn metric_type(&self) -> &'static str {
+        M::TYPE
+    }
+
+    fn fmt_metric_family_as(
+        &self,
+        format: Format,
+        const_labels: &[(&str, &str)],
+        matchers: &[Matcher<'_>],
+        mut writer: &mut dyn fmt::Write,
+    ) -> fmt::Result {
+        self.fmt_metric_inner(format, const_labels, matchers, &mut writer)
+    }
+}
+
+impl fmt::Debug for dyn FmtMetricFamily + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FmtMetricFamily")
+            .field("name", &self.name())
+            .field("metric_type", &self.metric_type())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for dyn FmtMetricFamily + Sync + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self as &dyn FmtMetricFamily).fmt(f)
+    }
+}
+
+// === impl MetricRef ===
+
+impl<M, L> core::ops::Deref for MetricRef<'_, M, L> {
+    type Target = M;
+
+    #[inline]
+    fn deref(&self) -> &M {
+        match self.0 {
+            MetricRefInner::Series(ref entry) => entry.value(),
+            MetricRefInner::Overflow(metric) => metric,
+        }
+    }
+}
+
+impl<M, L> Clone for MetricRef<'_, M, L> {
+    fn clone(&self) -> Self {
+        match self.0 {
+            MetricRefInner::Series(ref entry) => Self(MetricRefInner::Series(entry.clone())),
+            MetricRefInner::Overflow(metric) => Self(MetricRefInner::Overflow(metric)),
+        }
+    }
+}
+
+impl<M: fmt::Debug, L> fmt::Debug for MetricRef<'_, M, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<M, const METRICS: usize, L, R> Serialize for MetricFamily<'_, M, METRICS, L, R>
+where
+    M: Metric + Serialize,
+    R: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.serialize_with_labels(&[], serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<M, const METRICS: usize, L, R> MetricFamily<'_, M, METRICS, L, R>
+where
+    M: Metric + Serialize,
+    R: Serialize,
+{
+    /// Serializes this metric family, adding `const_labels` to the family's
+    /// own constant labels.
+    pub fn serialize_with_labels<S>(
+        &self,
+        const_labels: &[(&str, &str)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::{SerializeMap, SerializeStruct};
+
+        struct ConstLabels<'a>(Chain<LabelSlice<'a>, LabelSlice<'a>>);
+
+        impl Serialize for ConstLabels<'_> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let Chain(a, b) = self.0;
+                let mut map = serializer.serialize_map(Some(a.len() + b.len()))?;
+                for (k, v) in a.iter().chain(b) {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+
+        let const_labels = ConstLabels(Chain(self.def.const_labels, const_labels));
+        let mut family = serializer.serialize_struct("MetricFamily", 6)?;
+        family.serialize_field("name", self.def.name)?;
+        family.serialize_field("type", M::TYPE)?;
+        family.serialize_field("unit", self.def.unit)?;
+        family.serialize_field("help", self.def.help)?;
+        if const_labels.0.is_empty() {
+            family.skip_field("const_labels")?;
+        } else {
+            family.serialize_field("const_labels", &const_labels)?;
+        }
+        family.serialize_field("metrics", &self.metrics)?;
+        family.end()
+    }
+}
+
 // === impl Gauge ===
 
 impl Gauge {
     const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
         Self {
             value: AtomicF64::new(f64::NAN),
             recorded: AtomicBool::new(false),
@@ -426,20 +1376,93 @@ impl Gauge {
         }
     }
 
+    /// Sets this gauge to `value`.
+    ///
+    /// Like relative updates, this is never discarded based on its timestamp,
+    /// so any number of updates in the same second are applied in order.
     pub fn set_value(&self, value: f64) {
-        #[cfg(feature = "timestamp")]
-        if let Some(ref timestamp) = self.timestamp {
-            if !timestamp.update_if_ahead() {
-                return;
-            }
-        }
+        self.update_timestamp();
         self.value.store(value, Ordering::Release);
         self.recorded.store(true, Ordering::Release);
     }
 
+    /// Adds `value` to this gauge.
+    ///
+    /// If the gauge has not yet been recorded, it is treated as though its
+    /// value was 0.
+    pub fn add(&self, value: f64) {
+        self.update_with(|curr| curr + value);
+    }
+
+    /// Subtracts `value` from this gauge.
+    ///
+    /// If the gauge has not yet been recorded, it is treated as though its
+    /// value was 0.
+    pub fn sub(&self, value: f64) {
+        self.update_with(|curr| curr - value);
+    }
+
+    /// Increments this gauge by 1.
+    pub fn inc(&self) {
+        self.add(1.0);
+    }
+
+    /// Decrements this gauge by 1.
+    pub fn dec(&self) {
+        self.sub(1.0);
+    }
+
+    /// Sets this gauge to the maximum of its current value and `value`,
+    /// returning the previous value.
+    ///
+    /// If the gauge has not yet been recorded, it is set to `value`.
+    pub fn fetch_max(&self, value: f64) -> f64 {
+        self.update_timestamp();
+        // N.B. that an unrecorded gauge's value is NaN, and `f64::max`
+        // returns the non-NaN argument.
+        let prev = self.value.fetch_max(value, Ordering::AcqRel);
+        self.recorded.store(true, Ordering::Release);
+        prev
+    }
+
+    /// Sets this gauge to the minimum of its current value and `value`,
+    /// returning the previous value.
+    ///
+    /// If the gauge has not yet been recorded, it is set to `value`.
+    pub fn fetch_min(&self, value: f64) -> f64 {
+        self.update_timestamp();
+        let prev = self.value.fetch_min(value, Ordering::AcqRel);
+        self.recorded.store(true, Ordering::Release);
+        prev
+    }
+
     pub fn value(&self) -> f64 {
         self.value.load(Ordering::Acquire)
     }
+
+    fn update_with(&self, mut f: impl FnMut(f64) -> f64) {
+        self.update_timestamp();
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                // an unrecorded gauge's value is NaN, so start counting from 0.
+                if curr.is_nan() && !self.recorded.load(Ordering::Acquire) {
+                    Some(f(0.0))
+                } else {
+                    Some(f(curr))
+                }
+            });
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    /// Advances this gauge's timestamp to the current time, if it is later.
+    #[inline]
+    fn update_timestamp(&self) {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            timestamp.update_max();
+        }
+    }
 }
 
 impl Metric for Gauge {
@@ -450,16 +1473,25 @@ impl Metric for Gauge {
     }
 
     fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
-        write!(writer, "{}", self.value())?;
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        write!(writer, "{}", FmtF64(self.value()))?;
 
         #[cfg(feature = "timestamp")]
-        if let Some(now) = self.timestamp.as_ref().map(TimestampCell::timestamp) {
-            write!(writer, " {now}",)?;
-        }
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
 
         Ok(())
     }
 
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
     fn build(builder: &MetricBuilder<'_>) -> Self {
         Self::from_builder(builder)
     }
@@ -483,8 +1515,11 @@ impl Serialize for Gauge {
 
 impl Counter {
     const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
         Self {
             value: AtomicUsize::new(0),
+            resets: AtomicUsize::new(0),
 
             #[cfg(feature = "timestamp")]
             timestamp: builder.mk_timestamp(),
@@ -499,25 +1534,63 @@ impl Counter {
         self.value.fetch_add(value, Ordering::Release)
     }
 
+    /// Sets the value of this counter to `value`, returning the previous
+    /// value.
+    ///
+    /// This is intended for push-based exporters which report the change in
+    /// a counter's value since the last push, rather than its total value.
+    pub fn swap(&self, value: usize) -> usize {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            timestamp.update_max();
+        }
+        let prev = self.value.swap(value, Ordering::AcqRel);
+        // the reset is counted after the value is replaced, so that anything
+        // which observes the new count also observes the new value.
+        self.resets.fetch_add(1, Ordering::Release);
+        prev
+    }
+
+    /// Resets this counter to zero, returning its previous value.
+    ///
+    /// This is equivalent to `self.swap(0)`.
+    pub fn take(&self) -> usize {
+        self.swap(0)
+    }
+
     pub fn value(&self) -> usize {
         self.value.load(Ordering::Acquire)
     }
+
+    /// Returns the number of times this counter has been reset.
+    pub(crate) fn resets(&self) -> usize {
+        self.resets.load(Ordering::Acquire)
+    }
 }
 
 impl Metric for Counter {
     const TYPE: &'static str = "counter";
 
     fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
         write!(writer, "{}", self.value())?;
 
         #[cfg(feature = "timestamp")]
-        if let Some(now) = self.timestamp.as_ref().map(TimestampCell::timestamp) {
-            write!(writer, " {now}")?;
-        }
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
 
         Ok(())
     }
 
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
     fn build(builder: &MetricBuilder<'_>) -> Self {
         Self::from_builder(builder)
     }
@@ -537,6 +1610,8 @@ impl Serialize for Counter {
 
 impl IntGauge {
     const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
         Self {
             value: AtomicUsize::new(0),
             recorded: AtomicBool::new(false),
@@ -545,23 +1620,255 @@ impl IntGauge {
         }
     }
 
+    /// Sets this gauge to `value`.
+    ///
+    /// Like relative updates, this is never discarded based on its timestamp,
+    /// so any number of updates in the same second are applied in order.
     pub fn set_value(&self, value: usize) {
+        self.update_timestamp();
+        self.value.store(value, Ordering::Release);
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    /// Adds `value` to this gauge, saturating at [`usize::MAX`].
+    ///
+    /// If the gauge has not yet been recorded, it is treated as though its
+    /// value was 0.
+    pub fn add(&self, value: usize) {
+        self.update_with(|curr| curr.saturating_add(value));
+    }
+
+    /// Subtracts `value` from this gauge, saturating at 0.
+    ///
+    /// Because an `IntGauge` is unsigned, it can never go below zero:
+    /// subtracting more than its current value sets it to 0, rather than
+    /// wrapping. Use an [`IsizeGauge`] for values which may be negative.
+    pub fn sub(&self, value: usize) {
+        self.update_with(|curr| curr.saturating_sub(value));
+    }
+
+    /// Increments this gauge by 1.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Decrements this gauge by 1, saturating at 0.
+    pub fn dec(&self) {
+        self.sub(1);
+    }
+
+    /// Sets this gauge to the maximum of its current value and `value`,
+    /// returning the previous value.
+    ///
+    /// If the gauge has not yet been recorded, it is set to `value`.
+    pub fn fetch_max(&self, value: usize) -> usize {
+        self.update_timestamp();
+        // an unrecorded `IntGauge` is 0, which is already the minimum `usize`.
+        let prev = self.value.fetch_max(value, Ordering::AcqRel);
+        self.recorded.store(true, Ordering::Release);
+        prev
+    }
+
+    /// Sets this gauge to the minimum of its current value and `value`,
+    /// returning the previous value.
+    ///
+    /// If the gauge has not yet been recorded, it is set to `value`.
+    pub fn fetch_min(&self, value: usize) -> usize {
+        self.update_timestamp();
+        // an unrecorded gauge is 0, but that's not a real value, so don't let
+        // it win. claim the first update before touching the value, so that
+        // exactly one caller treats the initial 0 as unrecorded.
+        let first = !self.recorded.swap(true, Ordering::AcqRel);
+        self.value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                if first && curr == 0 {
+                    Some(value)
+                } else {
+                    Some(curr.min(value))
+                }
+            })
+            .unwrap_or_else(|curr| curr)
+    }
+
+    pub fn value(&self) -> usize {
+        self.value.load(Ordering::Acquire)
+    }
+
+    fn update_with(&self, mut f: impl FnMut(usize) -> usize) {
+        self.update_timestamp();
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| Some(f(curr)));
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    /// Advances this gauge's timestamp to the current time, if it is later.
+    #[inline]
+    fn update_timestamp(&self) {
         #[cfg(feature = "timestamp")]
         if let Some(ref timestamp) = self.timestamp {
-            if !timestamp.update_if_ahead() {
-                return;
-            }
+            timestamp.update_max();
         }
+    }
+}
+
+impl Metric for IntGauge {
+    const TYPE: &'static str = "gauge";
+
+    fn has_been_recorded(&self) -> bool {
+        self.value() != 0 || self.recorded.load(Ordering::Acquire)
+    }
+
+    fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        write!(writer, "{}", self.value())?;
+
+        #[cfg(feature = "timestamp")]
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
+    fn build(builder: &MetricBuilder<'_>) -> Self {
+        Self::from_builder(builder)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for IntGauge {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.has_been_recorded() {
+            serializer.serialize_some(&self.value())
+        } else {
+            serializer.serialize_none()
+        }
+    }
+}
+
+// === impl IsizeGauge ===
+
+impl IsizeGauge {
+    const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
+        Self {
+            value: AtomicIsize::new(0),
+            recorded: AtomicBool::new(false),
+            #[cfg(feature = "timestamp")]
+            timestamp: builder.mk_timestamp(),
+        }
+    }
+
+    /// Sets this gauge to `value`.
+    ///
+    /// Like relative updates, this is never discarded based on its timestamp,
+    /// so any number of updates in the same second are applied in order.
+    pub fn set_value(&self, value: isize) {
+        self.update_timestamp();
         self.value.store(value, Ordering::Release);
         self.recorded.store(true, Ordering::Release);
     }
 
-    pub fn value(&self) -> usize {
+    /// Adds `value` to this gauge, saturating at the numeric bounds of
+    /// `isize`.
+    ///
+    /// If the gauge has not yet been recorded, it is treated as though its
+    /// value was 0.
+    pub fn add(&self, value: isize) {
+        self.update_with(|curr| curr.saturating_add(value));
+    }
+
+    /// Subtracts `value` from this gauge, saturating at the numeric bounds of
+    /// `isize`.
+    ///
+    /// If the gauge has not yet been recorded, it is treated as though its
+    /// value was 0.
+    pub fn sub(&self, value: isize) {
+        self.update_with(|curr| curr.saturating_sub(value));
+    }
+
+    /// Increments this gauge by 1.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Decrements this gauge by 1.
+    pub fn dec(&self) {
+        self.sub(1);
+    }
+
+    /// Sets this gauge to the maximum of its current value and `value`,
+    /// returning the previous value.
+    ///
+    /// If the gauge has not yet been recorded, it is set to `value`.
+    pub fn fetch_max(&self, value: isize) -> isize {
+        self.fetch_update_first(value, isize::max)
+    }
+
+    /// Sets this gauge to the minimum of its current value and `value`,
+    /// returning the previous value.
+    ///
+    /// If the gauge has not yet been recorded, it is set to `value`.
+    pub fn fetch_min(&self, value: isize) -> isize {
+        self.fetch_update_first(value, isize::min)
+    }
+
+    pub fn value(&self) -> isize {
         self.value.load(Ordering::Acquire)
     }
+
+    fn update_with(&self, mut f: impl FnMut(isize) -> isize) {
+        self.update_timestamp();
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| Some(f(curr)));
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    /// Combines `value` with the current value using `f`, unless this is the
+    /// first update to the gauge, in which case it is set to `value`,
+    /// returning the previous value.
+    fn fetch_update_first(&self, value: isize, f: impl Fn(isize, isize) -> isize) -> isize {
+        self.update_timestamp();
+        // an unrecorded gauge is 0, but that's not a real value, so don't let
+        // it win. claim the first update before touching the value, so that
+        // exactly one caller treats the initial 0 as unrecorded.
+        let first = !self.recorded.swap(true, Ordering::AcqRel);
+        self.value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                if first && curr == 0 {
+                    Some(value)
+                } else {
+                    Some(f(curr, value))
+                }
+            })
+            .unwrap_or_else(|curr| curr)
+    }
+
+    /// Advances this gauge's timestamp to the current time, if it is later.
+    #[inline]
+    fn update_timestamp(&self) {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            timestamp.update_max();
+        }
+    }
 }
 
-impl Metric for IntGauge {
+impl Metric for IsizeGauge {
     const TYPE: &'static str = "gauge";
 
     fn has_been_recorded(&self) -> bool {
@@ -569,23 +1876,108 @@ impl Metric for IntGauge {
     }
 
     fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
         write!(writer, "{}", self.value())?;
 
         #[cfg(feature = "timestamp")]
-        if let Some(now) = self.timestamp.as_ref().map(TimestampCell::timestamp) {
-            write!(writer, " {now}",)?;
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
+    fn build(builder: &MetricBuilder<'_>) -> Self {
+        Self::from_builder(builder)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for IsizeGauge {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.has_been_recorded() {
+            serializer.serialize_some(&self.value())
+        } else {
+            serializer.serialize_none()
         }
+    }
+}
+
+// === impl Unknown ===
+
+impl Unknown {
+    const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
+        Self {
+            value: AtomicF64::new(f64::NAN),
+            recorded: AtomicBool::new(false),
+            #[cfg(feature = "timestamp")]
+            timestamp: builder.mk_timestamp(),
+        }
+    }
+
+    pub fn set_value(&self, value: f64) {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            if !timestamp.update_if_ahead() {
+                return;
+            }
+        }
+        self.value.store(value, Ordering::Release);
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value.load(Ordering::Acquire)
+    }
+}
+
+impl Metric for Unknown {
+    const TYPE: &'static str = "unknown";
+
+    fn has_been_recorded(&self) -> bool {
+        !self.value().is_nan() || self.recorded.load(Ordering::Acquire)
+    }
+
+    fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        write!(writer, "{}", FmtF64(self.value()))?;
+
+        #[cfg(feature = "timestamp")]
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
 
         Ok(())
     }
 
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
     fn build(builder: &MetricBuilder<'_>) -> Self {
         Self::from_builder(builder)
     }
 }
 
 #[cfg(feature = "serde")]
-impl Serialize for IntGauge {
+impl Serialize for Unknown {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
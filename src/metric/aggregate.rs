@@ -0,0 +1,149 @@
+//! Aggregates over the recorded values of a set of series, shared by
+//! [`MetricFamily`](super::MetricFamily) and [`Group`](super::Group).
+
+pub(super) fn mean_usize(values: impl Iterator<Item = usize>) -> Option<usize> {
+    let mut recorded = 0;
+    let mut sum = 0;
+    for val in values {
+        recorded += 1;
+        sum += val;
+    }
+
+    sum.checked_div(recorded)
+}
+
+pub(super) fn mean_isize(values: impl Iterator<Item = isize>) -> Option<isize> {
+    let mut recorded = 0;
+    let mut sum = 0;
+    for val in values {
+        recorded += 1;
+        sum += val;
+    }
+
+    sum.checked_div(recorded)
+}
+
+pub(super) fn min_f64(values: impl Iterator<Item = f64>) -> Option<f64> {
+    values.reduce(f64::min)
+}
+
+pub(super) fn max_f64(values: impl Iterator<Item = f64>) -> Option<f64> {
+    values.reduce(f64::max)
+}
+
+pub(super) fn total_f64(values: impl Iterator<Item = f64>) -> f64 {
+    values.sum()
+}
+
+pub(super) fn mean_f64(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut recorded = 0;
+    let mut sum = 0.0;
+    for val in values {
+        recorded += 1;
+        sum += val;
+    }
+
+    if recorded > 0 {
+        Some(sum / recorded as f64)
+    } else {
+        None
+    }
+}
+
+/// Returns the `p`th percentile of the values returned by `values`, which
+/// must not be `NaN`.
+///
+/// Rather than copying the values into a buffer to sort them, the values on
+/// either side of the percentile are found by [`nth_smallest`], so `values`
+/// is called once for each pass over the values.
+pub(super) fn percentile<I>(values: impl Fn() -> I, p: f64) -> Option<f64>
+where
+    I: Iterator<Item = f64>,
+{
+    if !(0.0..=100.0).contains(&p) {
+        return None;
+    }
+
+    let len = values().count();
+    if len == 0 {
+        return None;
+    }
+
+    let rank = p / 100.0 * (len - 1) as f64;
+    let lower = rank as usize;
+    let low = nth_smallest(&values, lower)?;
+    let fraction = rank - lower as f64;
+    if fraction == 0.0 {
+        return Some(low);
+    }
+    let high = nth_smallest(&values, lower + 1)?;
+    Some(low + (high - low) * fraction)
+}
+
+/// Returns the `n`th smallest (counting from zero) of the values returned by
+/// `values`, which must not be `NaN`.
+///
+/// This bisects the space of [`order_key`]s, counting the values at or below
+/// the midpoint on each step, so it takes 64 passes over the values
+/// regardless of how many there are. Returns `None` if there are not more
+/// than `n` values, which may happen if values are recorded concurrently.
+fn nth_smallest<I>(values: impl Fn() -> I, n: usize) -> Option<f64>
+where
+    I: Iterator<Item = f64>,
+{
+    let (mut lo, mut hi) = (0, u64::MAX);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let at_or_below = values().filter(|&value| order_key(value) <= mid).count();
+        if at_or_below > n {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    let value = from_order_key(lo);
+    if value.is_nan() {
+        return None;
+    }
+    Some(value)
+}
+
+/// Maps `value` to an integer which sorts in the same order as `value`.
+fn order_key(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
+
+/// The inverse of [`order_key`].
+fn from_order_key(key: u64) -> f64 {
+    if key >> 63 == 1 {
+        f64::from_bits(key & !(1 << 63))
+    } else {
+        f64::from_bits(!key)
+    }
+}
+
+/// Returns the population variance of `values`.
+pub(super) fn variance(values: impl Iterator<Item = f64>) -> Option<f64> {
+    // Welford's algorithm, which avoids the loss of precision of
+    // subtracting the squares of large values.
+    let mut recorded = 0;
+    let mut mean = 0.0;
+    let mut sum_sq = 0.0;
+    for value in values {
+        recorded += 1;
+        let delta = value - mean;
+        mean += delta / recorded as f64;
+        sum_sq += delta * (value - mean);
+    }
+
+    if recorded > 0 {
+        Some(sum_sq / recorded as f64)
+    } else {
+        None
+    }
+}
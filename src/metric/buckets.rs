@@ -0,0 +1,103 @@
+//! Bucket layouts shared by histogram metric types.
+use core::fmt;
+use portable_atomic::{AtomicUsize, Ordering};
+
+/// A fixed-size set of `BUCKETS` histogram buckets, plus an implicit `+Inf`
+/// bucket.
+///
+/// Each bucket stores the number of observations which fell into _that_
+/// bucket (i.e. counts are not cumulative). The count of the `+Inf` bucket is
+/// not stored here, since it is always equal to the histogram's total count.
+#[derive(Debug)]
+pub(crate) struct Buckets<const BUCKETS: usize> {
+    bounds: [f64; BUCKETS],
+    counts: [AtomicUsize; BUCKETS],
+}
+
+/// Formats a bucket's upper bound as the value of an `le` label.
+pub(crate) struct Bound(pub(crate) f64);
+
+impl<const BUCKETS: usize> Buckets<BUCKETS> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+    /// Returns a new set of buckets with the provided upper `bounds`.
+    ///
+    /// # Panics
+    ///
+    /// If the number of `bounds` is not `BUCKETS`. This is checked when a
+    /// family is built, by [`MetricBuilder::build`](super::MetricBuilder::build).
+    pub(crate) fn new(bounds: &[f64]) -> Self {
+        assert_eq!(
+            bounds.len(),
+            BUCKETS,
+            "a histogram with {BUCKETS} buckets requires {BUCKETS} bucket bounds"
+        );
+        let mut bucket_bounds = [f64::INFINITY; BUCKETS];
+        bucket_bounds.copy_from_slice(bounds);
+        Self {
+            bounds: bucket_bounds,
+            counts: [Self::ZERO; BUCKETS],
+        }
+    }
+
+    /// Returns the bucket containing `value`, or `None` if it is only
+    /// contained by the `+Inf` bucket.
+    fn bucket(&self, value: f64) -> Option<&AtomicUsize> {
+        let idx = self.bounds.iter().position(|&bound| value <= bound)?;
+        Some(&self.counts[idx])
+    }
+
+    /// Adds a single observation of `value` to the bucket containing it.
+    pub(crate) fn observe(&self, value: f64) {
+        if let Some(bucket) = self.bucket(value) {
+            bucket.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Removes a single observation of `value` from the bucket containing it.
+    ///
+    /// Returns whether the observation was removed, or `None` if `value` is
+    /// only contained by the `+Inf` bucket. If the bucket is already empty, it
+    /// is left at zero.
+    pub(crate) fn remove(&self, value: f64) -> Option<bool> {
+        let bucket = self.bucket(value)?;
+        let removed = bucket.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+            count.checked_sub(1)
+        });
+        Some(removed.is_ok())
+    }
+
+    /// Returns the total number of observations in these buckets, not
+    /// including those only in the `+Inf` bucket.
+    pub(crate) fn total(&self) -> usize {
+        self.counts
+            .iter()
+            .map(|count| count.load(Ordering::Acquire))
+            .sum()
+    }
+
+    /// Returns an iterator over each bucket's upper bound and _cumulative_
+    /// count, not including the `+Inf` bucket.
+    pub(crate) fn cumulative(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        self.bounds
+            .iter()
+            .zip(&self.counts)
+            .scan(0, |cumulative, (&bound, count)| {
+                *cumulative += count.load(Ordering::Acquire);
+                Some((bound, *cumulative))
+            })
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == f64::INFINITY {
+            f.write_str("+Inf")
+        } else {
+            // the `Debug` format always includes a decimal point, as the
+            // OpenMetrics canonical form for `le` label values requires.
+            fmt::Debug::fmt(&self.0, f)
+        }
+    }
+}
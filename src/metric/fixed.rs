@@ -0,0 +1,182 @@
+use super::{merge::MergeMetric, Format, Metric, MetricBuilder};
+use core::fmt;
+use portable_atomic::{AtomicBool, AtomicIsize, Ordering};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+#[cfg(feature = "timestamp")]
+use crate::timestamp::{TimestampCell, UnixTimestamp};
+
+/// A gauge metric whose value is a fixed-point decimal number with `SCALE`
+/// digits after the decimal point.
+///
+/// The value of a `FixedGauge` is stored as an integer number of
+/// 10<sup>-`SCALE`</sup> units. For example, a `FixedGauge<3>` whose unit is
+/// volts stores its value in millivolts, and a value of `1234` is exported as
+/// `1.234`. The value is formatted as a decimal using only integer arithmetic,
+/// so that the exported value matches the unit set by
+/// [`MetricBuilder::with_unit`] without requiring floating point hardware.
+///
+/// Like [`IntGauge`](super::IntGauge), this is not a standardized OpenMetrics
+/// metric type, but it is exported as though it were a standard Gauge metric.
+#[derive(Debug)]
+pub struct FixedGauge<const SCALE: u32> {
+    value: AtomicIsize,
+    recorded: AtomicBool,
+
+    #[cfg(feature = "timestamp")]
+    timestamp: Option<TimestampCell>,
+}
+
+/// Formats a scaled integer as a decimal number.
+struct Decimal<const SCALE: u32>(isize);
+
+// === impl FixedGauge ===
+
+impl<const SCALE: u32> FixedGauge<SCALE> {
+    /// The number of scaled units in one whole unit.
+    ///
+    /// If `SCALE` is too large for this to fit in a `usize`, using the
+    /// `FixedGauge` type will fail to compile.
+    pub const DIVISOR: usize = 10usize.pow(SCALE);
+
+    const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
+        Self {
+            value: AtomicIsize::new(0),
+            recorded: AtomicBool::new(false),
+            #[cfg(feature = "timestamp")]
+            timestamp: builder.mk_timestamp(),
+        }
+    }
+
+    /// Sets the value of this gauge to `value` scaled units (e.g. millivolts
+    /// for a `FixedGauge<3>` measuring volts).
+    ///
+    /// Like relative updates, this is never discarded based on its timestamp,
+    /// so any number of updates in the same second are applied in order.
+    pub fn set_value(&self, value: isize) {
+        self.update_timestamp();
+        self.value.store(value, Ordering::Release);
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    /// Adds `value` scaled units to this gauge, saturating at the numeric
+    /// bounds of `isize`.
+    pub fn add(&self, value: isize) {
+        self.update_with(|curr| curr.saturating_add(value));
+    }
+
+    /// Subtracts `value` scaled units from this gauge, saturating at the
+    /// numeric bounds of `isize`.
+    pub fn sub(&self, value: isize) {
+        self.update_with(|curr| curr.saturating_sub(value));
+    }
+
+    /// Returns the current value of this gauge, in scaled units.
+    pub fn value(&self) -> isize {
+        self.value.load(Ordering::Acquire)
+    }
+
+    /// Returns the current value of this gauge, in whole units, as an `f64`.
+    pub fn value_f64(&self) -> f64 {
+        self.value() as f64 / Self::DIVISOR as f64
+    }
+
+    fn update_with(&self, mut f: impl FnMut(isize) -> isize) {
+        self.update_timestamp();
+        let _ = self
+            .value
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| Some(f(curr)));
+        self.recorded.store(true, Ordering::Release);
+    }
+
+    /// Advances this gauge's timestamp to the current time, if it is later.
+    #[inline]
+    fn update_timestamp(&self) {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            timestamp.update_max();
+        }
+    }
+}
+
+impl<const SCALE: u32> Metric for FixedGauge<SCALE> {
+    const TYPE: &'static str = "gauge";
+
+    fn has_been_recorded(&self) -> bool {
+        self.value() != 0 || self.recorded.load(Ordering::Acquire)
+    }
+
+    fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        write!(writer, "{}", Decimal::<SCALE>(self.value()))?;
+
+        #[cfg(feature = "timestamp")]
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
+    fn build(builder: &MetricBuilder<'_>) -> Self {
+        Self::from_builder(builder)
+    }
+}
+
+impl<const SCALE: u32> MergeMetric for FixedGauge<SCALE> {
+    fn merge(&self, other: &Self) {
+        if !other.has_been_recorded() {
+            return;
+        }
+        #[cfg(feature = "timestamp")]
+        if !super::merge::merge_timestamp(&self.timestamp, other.last_updated()) {
+            return;
+        }
+        self.value.store(other.value(), Ordering::Release);
+        self.recorded.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const SCALE: u32> Serialize for FixedGauge<SCALE> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.has_been_recorded() {
+            serializer.serialize_some(&self.value_f64())
+        } else {
+            serializer.serialize_none()
+        }
+    }
+}
+
+// === impl Decimal ===
+
+impl<const SCALE: u32> fmt::Display for Decimal<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let divisor = FixedGauge::<SCALE>::DIVISOR;
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        // N.B. that `unsigned_abs` is used so that `isize::MIN` doesn't
+        // overflow.
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}", abs / divisor)?;
+        if SCALE > 0 {
+            write!(f, ".{:0width$}", abs % divisor, width = SCALE as usize)?;
+        }
+        Ok(())
+    }
+}
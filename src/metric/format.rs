@@ -0,0 +1,247 @@
+use core::fmt;
+
+#[cfg(feature = "timestamp")]
+use crate::timestamp::TimestampCell;
+
+/// A text exposition format in which metrics may be formatted.
+///
+/// By default, metrics are formatted in the [OpenMetrics] text format. Older
+/// Prometheus servers (and other scrapers, such as Telegraf) may instead
+/// require the classic [Prometheus 0.0.4] text format, which may be selected
+/// using [`MetricFamily::fmt_metric_as`](super::MetricFamily::fmt_metric_as)
+/// or [`MetricSet::with_format`](crate::MetricSet::with_format).
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::{CounterFamily, Format, MetricBuilder};
+///
+/// static REQUESTS: CounterFamily<'static, 2> = MetricBuilder::new("requests")
+///     .with_help("requests handled")
+///     .with_unit("requests")
+///     .build();
+///
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(1);
+///
+/// let mut exposition = String::new();
+/// REQUESTS.fmt_metric_as(Format::Prometheus, &mut exposition).unwrap();
+/// assert!(exposition.starts_with("# HELP requests_total requests handled\n"));
+/// assert!(exposition.contains("# TYPE requests_total counter\n"));
+/// assert!(exposition.contains("requests_total{path=\"/\"} 1"));
+/// assert!(!exposition.contains("# UNIT"));
+/// ```
+///
+/// [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#text-format
+/// [Prometheus 0.0.4]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// The OpenMetrics text format.
+    OpenMetrics,
+    /// The Prometheus 0.0.4 text format.
+    ///
+    /// This differs from the OpenMetrics text format as follows:
+    ///
+    /// - `# UNIT` lines and the terminating `# EOF` line are omitted, and
+    ///   `# HELP` lines precede `# TYPE` lines.
+    /// - The names of counter families and samples end in `_total`.
+    /// - Timestamps are integer milliseconds, rather than seconds.
+    /// - Unknown metrics and gauge histograms have the type `untyped`. Gauge
+    ///   histograms keep their `_bucket`, `_gcount`, and `_gsum` samples,
+    ///   since a histogram's `_count` and `_sum` imply monotonic counters.
+    Prometheus,
+}
+
+// === impl Format ===
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::OpenMetrics
+    }
+}
+
+impl Format {
+    /// Returns the `Content-Type` header value for an exposition in this
+    /// format.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            Format::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+        }
+    }
+
+    /// Chooses the best format for a scrape request with the provided
+    /// `Accept` header value.
+    ///
+    /// The header's media ranges are matched against the `Content-Type` of
+    /// each format as described in [RFC 9110], so that each format is given
+    /// the quality (`q`) value of the most specific media range which matches
+    /// it. The format with the highest quality is returned, preferring
+    /// [`Format::Prometheus`] if both formats are equally acceptable.
+    ///
+    /// Formats which this crate does not implement (such as the Prometheus
+    /// protobuf format) are never chosen. If neither format is acceptable, or
+    /// the header is empty, this returns [`Format::Prometheus`], which all
+    /// Prometheus-compatible scrapers accept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::Format;
+    ///
+    /// // the `Accept` header sent by Prometheus 2.x:
+    /// let accept = "application/openmetrics-text;version=1.0.0,\
+    ///     application/openmetrics-text;version=0.0.1;q=0.75,\
+    ///     text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+    /// assert_eq!(Format::negotiate(accept), Format::OpenMetrics);
+    ///
+    /// // protobuf is not supported, so the text format is chosen instead:
+    /// let accept = "application/vnd.google.protobuf;\
+    ///     proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,\
+    ///     text/plain;version=0.0.4;q=0.3,*/*;q=0.2";
+    /// assert_eq!(Format::negotiate(accept), Format::Prometheus);
+    /// assert_eq!(
+    ///     Format::negotiate(accept).content_type(),
+    ///     "text/plain; version=0.0.4; charset=utf-8",
+    /// );
+    /// ```
+    ///
+    /// [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#name-accept
+    #[must_use]
+    pub fn negotiate(accept: &str) -> Self {
+        // the (specificity, quality) of the best match for each format.
+        let mut openmetrics = None;
+        let mut prometheus = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let mut version = None;
+            let mut quality = 1.0;
+            for param in params {
+                let (name, value) = match param.split_once('=') {
+                    Some((name, value)) => (name.trim(), value.trim().trim_matches('"')),
+                    None => continue,
+                };
+                if name.eq_ignore_ascii_case("q") {
+                    quality = match value.parse::<f32>() {
+                        Ok(q) if (0.0..=1.0).contains(&q) => q,
+                        _ => 0.0,
+                    };
+                } else if name.eq_ignore_ascii_case("version") {
+                    version = Some(value);
+                }
+            }
+
+            for (format, best) in [
+                (Format::OpenMetrics, &mut openmetrics),
+                (Format::Prometheus, &mut prometheus),
+            ] {
+                if let Some(specificity) = format.matches(media_type, version) {
+                    match best {
+                        Some((best, _)) if *best >= specificity => {}
+                        _ => *best = Some((specificity, quality)),
+                    }
+                }
+            }
+        }
+
+        let quality = |best: Option<(u8, f32)>| best.map_or(0.0, |(_, quality)| quality);
+        if quality(openmetrics) > quality(prometheus) {
+            Format::OpenMetrics
+        } else {
+            Format::Prometheus
+        }
+    }
+
+    /// If the media range `media_type` (with the `version` parameter, if it
+    /// has one) matches this format, returns how specific the match is.
+    fn matches(self, media_type: &str, version: Option<&str>) -> Option<u8> {
+        let (ty, subtype) = media_type.split_once('/')?;
+        let (expected_ty, expected_subtype, versions): (_, _, &[&str]) = match self {
+            Format::OpenMetrics => ("application", "openmetrics-text", &["1.0.0", "0.0.1"]),
+            Format::Prometheus => ("text", "plain", &["0.0.4"]),
+        };
+        if ty == "*" && subtype == "*" {
+            return Some(0);
+        }
+        if !ty.eq_ignore_ascii_case(expected_ty) {
+            return None;
+        }
+        if subtype == "*" {
+            return Some(1);
+        }
+        if !subtype.eq_ignore_ascii_case(expected_subtype) {
+            return None;
+        }
+        match version {
+            None => Some(2),
+            Some(version) if versions.contains(&version) => Some(3),
+            Some(_) => None,
+        }
+    }
+
+    /// Returns the name of the metric type `ty` in this format.
+    pub(crate) fn metric_type(self, ty: &'static str) -> &'static str {
+        match (self, ty) {
+            (Format::Prometheus, "unknown") => "untyped",
+            (Format::Prometheus, "gaugehistogram") => "untyped",
+            _ => ty,
+        }
+    }
+
+    /// Returns the suffix added to the name of a family of type `ty`, and to
+    /// the names of its samples, in this format.
+    pub(crate) fn name_suffix(self, ty: &str, name: &str) -> &'static str {
+        if self == Format::Prometheus && ty == "counter" && !name.ends_with("_total") {
+            "_total"
+        } else {
+            ""
+        }
+    }
+
+    /// Writes the metadata lines for a metric family named `name` followed by
+    /// `suffix`.
+    pub(crate) fn fmt_metadata(
+        self,
+        writer: &mut impl fmt::Write,
+        (name, suffix): (&str, &str),
+        ty: &'static str,
+        unit: &str,
+        help: impl fmt::Display,
+    ) -> fmt::Result {
+        match self {
+            Format::OpenMetrics => writeln!(
+                writer,
+                "# TYPE {name}{suffix} {ty}\n\
+                # UNIT {name}{suffix} {unit}\n\
+                # HELP {name}{suffix} {help}"
+            ),
+            Format::Prometheus => {
+                let total = self.name_suffix(ty, if suffix.is_empty() { name } else { suffix });
+                writeln!(
+                    writer,
+                    "# HELP {name}{suffix}{total} {help}\n# TYPE {name}{suffix}{total} {ty}",
+                    ty = self.metric_type(ty),
+                )
+            }
+        }
+    }
+
+    /// Writes the timestamp of a sample, preceded by a space, if the metric
+    /// records timestamps.
+    #[cfg(feature = "timestamp")]
+    pub(crate) fn fmt_timestamp(
+        self,
+        writer: &mut impl fmt::Write,
+        timestamp: Option<&TimestampCell>,
+    ) -> fmt::Result {
+        let now = match timestamp {
+            Some(timestamp) => timestamp.timestamp(),
+            None => return Ok(()),
+        };
+        match self {
+            Format::OpenMetrics => write!(writer, " {now}"),
+            Format::Prometheus => write!(writer, " {}", now.as_secs().saturating_mul(1000)),
+        }
+    }
+}
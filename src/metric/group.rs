@@ -0,0 +1,366 @@
+use super::{
+    aggregate, fmt_label, matcher::find_label, Counter, FmtLabels, Gauge, IntGauge, IsizeGauge,
+    Metric,
+};
+use crate::registry::{FnvHasher, MapRef, MapRefs};
+use core::{fmt, hash::Hasher, str};
+
+/// An iterator over the groups of series in a
+/// [`MetricFamily`](super::MetricFamily) which have the same value for a label,
+/// returned by [`MetricFamily::group_by`](super::MetricFamily::group_by).
+pub struct GroupBy<'f, M, L, const METRICS: usize> {
+    key: &'f str,
+    /// The family's recorded series, each with the index of the group it
+    /// belongs to. Series are moved into their [`Group`] when it is returned.
+    series: [Option<(MapRef<'f, L, M>, usize)>; METRICS],
+    /// The number of groups.
+    groups: usize,
+    /// The index of the next group to return.
+    next: usize,
+}
+
+/// A group of series in a [`MetricFamily`](super::MetricFamily) which have
+/// the same value for a label.
+///
+/// Aggregates over the series in a group are provided for the same metric
+/// types as the aggregates over a whole `MetricFamily`. A `Group` also
+/// implements [`FmtLabels`], formatting the single label its series were
+/// grouped by, so that it may be used as the label set of an aggregated
+/// series.
+pub struct Group<'f, M, L, const METRICS: usize> {
+    key: &'f str,
+    /// The series in the group. The first slot always holds the group's
+    /// first series.
+    series: [Option<MapRef<'f, L, M>>; METRICS],
+}
+
+/// The value of the label a [`Group`]'s series were grouped by, returned by
+/// [`Group::value`].
+#[derive(Clone, Copy)]
+pub struct GroupValue<'g, L> {
+    labels: &'g L,
+    key: &'g str,
+}
+
+/// Returns the hash of the value of the label `key` in `labels`.
+fn hash_value(labels: &impl FmtLabels, key: &str) -> u64 {
+    let mut hasher = FnvHasher::new();
+    let _ = find_label(labels, key, |byte| hasher.write_u8(byte));
+    hasher.finish()
+}
+
+/// Returns `true` if the label `key` has the same value in both `a` and `b`.
+///
+/// Label values are compared in fixed-size chunks, so that they may be
+/// compared without allocating.
+fn same_value(a: &impl FmtLabels, b: &impl FmtLabels, key: &str) -> bool {
+    const CHUNK: usize = 32;
+
+    fn chunk(labels: &impl FmtLabels, key: &str, offset: usize) -> ([u8; CHUNK], usize) {
+        let mut chunk = [0; CHUNK];
+        let mut len = 0usize;
+        let _ = find_label(labels, key, |byte| {
+            if let Some(slot) = len.checked_sub(offset).and_then(|i| chunk.get_mut(i)) {
+                *slot = byte;
+            }
+            len += 1;
+        });
+        (chunk, len)
+    }
+
+    let mut offset = 0;
+    loop {
+        let (chunk_a, len_a) = chunk(a, key, offset);
+        let (chunk_b, len_b) = chunk(b, key, offset);
+        if len_a != len_b {
+            return false;
+        }
+        let end = CHUNK.min(len_a - offset);
+        if chunk_a[..end] != chunk_b[..end] {
+            return false;
+        }
+        offset += CHUNK;
+        if offset >= len_a {
+            return true;
+        }
+    }
+}
+
+// === impl GroupBy ===
+
+impl<'f, M, L, const METRICS: usize> GroupBy<'f, M, L, METRICS>
+where
+    M: Metric,
+    L: FmtLabels,
+{
+    const EMPTY: Option<(MapRef<'f, L, M>, usize)> = None;
+
+    /// Assigns each recorded series in `entries` to a group, in a single pass.
+    ///
+    /// Each series' label value is hashed once; label values are only
+    /// compared when a series' hash matches that of an existing group.
+    pub(super) fn new(entries: MapRefs<'f, L, M>, key: &'f str) -> Self {
+        let mut series = [Self::EMPTY; METRICS];
+        // the hash of each group's label value, and the index of its first
+        // series.
+        let mut groups = [(0, 0); METRICS];
+        let mut num_groups = 0;
+        let recorded = entries.filter(|entry| entry.value().has_been_recorded());
+        for (idx, entry) in recorded.take(METRICS).enumerate() {
+            let hash = hash_value(entry.key(), key);
+            let existing = groups[..num_groups]
+                .iter()
+                .position(|&(group_hash, first)| {
+                    group_hash == hash
+                        && series[first].as_ref().map_or(false, |(first, _)| {
+                            same_value(first.key(), entry.key(), key)
+                        })
+                });
+            let group = match existing {
+                Some(group) => group,
+                None => {
+                    groups[num_groups] = (hash, idx);
+                    num_groups += 1;
+                    num_groups - 1
+                }
+            };
+            series[idx] = Some((entry, group));
+        }
+
+        Self {
+            key,
+            series,
+            groups: num_groups,
+            next: 0,
+        }
+    }
+}
+
+impl<'f, M, L, const METRICS: usize> Iterator for GroupBy<'f, M, L, METRICS> {
+    type Item = Group<'f, M, L, METRICS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.groups {
+            return None;
+        }
+        let group = self.next;
+        self.next += 1;
+
+        let mut series = [Group::<M, L, METRICS>::EMPTY; METRICS];
+        let members = self
+            .series
+            .iter_mut()
+            .filter(|slot| matches!(slot, Some((_, g)) if *g == group));
+        for (slot, member) in series.iter_mut().zip(members) {
+            *slot = member.take().map(|(entry, _)| entry);
+        }
+        Some(Group {
+            key: self.key,
+            series,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.groups - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<M, L, const METRICS: usize> fmt::Debug for GroupBy<'_, M, L, METRICS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GroupBy")
+            .field("key", &self.key)
+            .field("groups", &self.groups)
+            .finish_non_exhaustive()
+    }
+}
+
+// === impl Group ===
+
+impl<'f, M, L, const METRICS: usize> Group<'f, M, L, METRICS> {
+    const EMPTY: Option<MapRef<'f, L, M>> = None;
+
+    /// Returns the name of the label this group's series were grouped by.
+    #[must_use]
+    pub fn key(&self) -> &'f str {
+        self.key
+    }
+
+    /// Returns the value of the label this group's series were grouped by.
+    ///
+    /// Series which do not have the label are grouped together, with the
+    /// empty string as their value.
+    #[must_use]
+    pub fn value(&self) -> GroupValue<'_, L> {
+        let first = self.series[0]
+            .as_ref()
+            .expect("a group always has a first series");
+        GroupValue {
+            labels: first.key(),
+            key: self.key,
+        }
+    }
+
+    /// Returns an iterator over the recorded series in this group.
+    pub fn series(&self) -> impl Iterator<Item = &MapRef<'f, L, M>> + '_ {
+        self.series.iter().flatten()
+    }
+}
+
+impl<L, const METRICS: usize> Group<'_, IntGauge, L, METRICS> {
+    fn recorded_values(&self) -> impl Iterator<Item = usize> + '_ {
+        self.series().map(|entry| entry.value().value())
+    }
+
+    #[must_use]
+    pub fn min_value(&self) -> Option<usize> {
+        self.recorded_values().min()
+    }
+
+    #[must_use]
+    pub fn max_value(&self) -> Option<usize> {
+        self.recorded_values().max()
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<usize> {
+        aggregate::mean_usize(self.recorded_values())
+    }
+}
+
+impl<L, const METRICS: usize> Group<'_, IsizeGauge, L, METRICS> {
+    fn recorded_values(&self) -> impl Iterator<Item = isize> + '_ {
+        self.series().map(|entry| entry.value().value())
+    }
+
+    #[must_use]
+    pub fn min_value(&self) -> Option<isize> {
+        self.recorded_values().min()
+    }
+
+    #[must_use]
+    pub fn max_value(&self) -> Option<isize> {
+        self.recorded_values().max()
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<isize> {
+        aggregate::mean_isize(self.recorded_values())
+    }
+}
+
+// as with gauge families, aggregates ignore `NaN` values.
+impl<L, const METRICS: usize> Group<'_, Gauge, L, METRICS> {
+    fn recorded_values(&self) -> impl Iterator<Item = f64> + '_ {
+        self.series()
+            .map(|entry| entry.value().value())
+            .filter(|value| !value.is_nan())
+    }
+
+    #[must_use]
+    pub fn min_value(&self) -> Option<f64> {
+        aggregate::min_f64(self.recorded_values())
+    }
+
+    #[must_use]
+    pub fn max_value(&self) -> Option<f64> {
+        aggregate::max_f64(self.recorded_values())
+    }
+
+    #[must_use]
+    pub fn total(&self) -> f64 {
+        aggregate::total_f64(self.recorded_values())
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<f64> {
+        aggregate::mean_f64(self.recorded_values())
+    }
+}
+
+impl<L, const METRICS: usize> Group<'_, Counter, L, METRICS> {
+    fn recorded_values(&self) -> impl Iterator<Item = usize> + '_ {
+        self.series().map(|entry| entry.value().value())
+    }
+
+    #[must_use]
+    pub fn min_value(&self) -> Option<usize> {
+        self.recorded_values().min()
+    }
+
+    #[must_use]
+    pub fn max_value(&self) -> Option<usize> {
+        self.recorded_values().max()
+    }
+
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.recorded_values().sum()
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<usize> {
+        aggregate::mean_usize(self.recorded_values())
+    }
+}
+
+impl<M, L: FmtLabels, const METRICS: usize> FmtLabels for Group<'_, M, L, METRICS> {
+    fn fmt_labels(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        fmt_label(writer, self.key, &self.value())
+    }
+}
+
+impl<M, L: FmtLabels, const METRICS: usize> fmt::Debug for Group<'_, M, L, METRICS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Group")
+            .field("key", &self.key)
+            .field("value", &format_args!("{}", self.value()))
+            .finish_non_exhaustive()
+    }
+}
+
+// === impl GroupValue ===
+
+impl<L: FmtLabels> fmt::Display for GroupValue<'_, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // label values are written one byte at a time, so buffer them until
+        // they form complete UTF-8 characters.
+        let mut buf = [0; 32];
+        let mut len = 0;
+        let mut result = Ok(());
+        let _ = find_label(self.labels, self.key, |byte| {
+            if result.is_err() {
+                return;
+            }
+            buf[len] = byte;
+            len += 1;
+            if len == buf.len() {
+                result = flush_utf8(f, &mut buf, &mut len);
+            }
+        });
+        result?;
+        flush_utf8(f, &mut buf, &mut len)
+    }
+}
+
+impl<L: FmtLabels> fmt::Debug for GroupValue<'_, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("GroupValue")
+            .field(&format_args!("{}", self))
+            .finish()
+    }
+}
+
+/// Writes the complete UTF-8 characters in `buf[..*len]`, moving any
+/// incomplete character to the start of `buf`.
+fn flush_utf8(f: &mut fmt::Formatter<'_>, buf: &mut [u8], len: &mut usize) -> fmt::Result {
+    let valid = match str::from_utf8(&buf[..*len]) {
+        Ok(s) => s.len(),
+        Err(error) => error.valid_up_to(),
+    };
+    // label values are always valid UTF-8, since they are written as `str`s.
+    f.write_str(str::from_utf8(&buf[..valid]).unwrap_or_default())?;
+    buf.copy_within(valid..*len, 0);
+    *len -= valid;
+    Ok(())
+}
@@ -0,0 +1,416 @@
+use super::{
+    buckets::{Bound, Buckets},
+    fmt_sample_name, Chain, FmtF64, FmtLabels, Format, Metric, MetricBuilder,
+};
+use crate::timer::RecordDuration;
+use core::{fmt, time::Duration};
+use portable_atomic::{AtomicF64, AtomicUsize, Ordering};
+#[cfg(feature = "serde")]
+use serde::{
+    ser::{SerializeSeq, SerializeStruct},
+    Serialize, Serializer,
+};
+
+#[cfg(feature = "timestamp")]
+use crate::timestamp::{TimestampCell, UnixTimestamp};
+
+/// An OpenMetrics [Histogram].
+///
+/// A histogram measures the distribution of a set of observed values, such as
+/// request latencies, by counting the number of observations which fall into
+/// each of a set of buckets. Like a [`Counter`](crate::Counter), its bucket
+/// counts only ever go up.
+///
+/// A `Histogram` has `BUCKETS` buckets, plus a `+Inf` bucket. The upper bounds
+/// of the buckets are set using [`MetricBuilder::with_buckets`].
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::{Histogram, MetricBuilder, MetricFamily};
+///
+/// static LATENCY: MetricFamily<'static, Histogram<2>, 4> = MetricBuilder::new("latency")
+///     .with_unit("seconds")
+///     .with_buckets(&[0.1, 1.0])
+///     .build();
+///
+/// let metric = LATENCY.register(&[("path", "/")]).unwrap();
+/// metric.observe(0.05);
+/// metric.observe(0.5);
+/// assert_eq!(metric.count(), 2);
+/// ```
+///
+/// [Histogram]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#histogram
+#[derive(Debug)]
+pub struct Histogram<const BUCKETS: usize> {
+    core: Core<BUCKETS>,
+}
+
+/// An OpenMetrics [GaugeHistogram].
+///
+/// A gauge histogram measures the current distribution of a set of values,
+/// such as how long the items _currently_ in a queue have been waiting. Unlike
+/// a [`Histogram`], observations may be [removed](Self::remove), so bucket
+/// counts may go down.
+///
+/// A `GaugeHistogram` has `BUCKETS` buckets, plus a `+Inf` bucket. The upper
+/// bounds of the buckets are set using [`MetricBuilder::with_buckets`].
+///
+/// [GaugeHistogram]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#gauge-histogram
+#[derive(Debug)]
+pub struct GaugeHistogram<const BUCKETS: usize> {
+    core: Core<BUCKETS>,
+}
+
+/// The buckets, count, and sum shared by both histogram types.
+#[derive(Debug)]
+struct Core<const BUCKETS: usize> {
+    buckets: Buckets<BUCKETS>,
+    count: AtomicUsize,
+    sum: AtomicF64,
+
+    #[cfg(feature = "timestamp")]
+    timestamp: Option<TimestampCell>,
+}
+
+// === impl Histogram ===
+
+impl<const BUCKETS: usize> Histogram<BUCKETS> {
+    /// Adds an observation of `value` to this histogram.
+    pub fn observe(&self, value: f64) {
+        self.core.observe(value);
+    }
+
+    /// Returns the number of observations in this histogram.
+    pub fn count(&self) -> usize {
+        self.core.count()
+    }
+
+    /// Returns the sum of the observations in this histogram.
+    pub fn sum(&self) -> f64 {
+        self.core.sum()
+    }
+
+    /// Returns an iterator over the upper bound and the _cumulative_ count of
+    /// each bucket in this histogram, including the final `+Inf` bucket.
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        self.core.buckets()
+    }
+}
+
+impl<const BUCKETS: usize> Metric for Histogram<BUCKETS> {
+    const TYPE: &'static str = "histogram";
+    const BUCKETS: Option<usize> = Some(BUCKETS);
+
+    /// Formats the count of this histogram.
+    fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        self.core.fmt_metric_as(format, writer)
+    }
+
+    fn fmt_series<F: fmt::Write>(
+        &self,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        self.fmt_series_as(Format::OpenMetrics, name, labels, writer)
+    }
+
+    fn fmt_series_as<F: fmt::Write>(
+        &self,
+        format: Format,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        self.core
+            .fmt_series_as(format, name, labels, ("_count", "_sum"), writer)
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.core.last_updated()
+    }
+
+    fn build(builder: &MetricBuilder<'_>) -> Self {
+        Self {
+            core: Core::from_builder(builder),
+        }
+    }
+}
+
+/// Observes the duration in seconds.
+impl<const BUCKETS: usize> RecordDuration for Histogram<BUCKETS> {
+    fn record_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const BUCKETS: usize> Serialize for Histogram<BUCKETS> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.core
+            .serialize("Histogram", ("count", "sum"), serializer)
+    }
+}
+
+// === impl GaugeHistogram ===
+
+impl<const BUCKETS: usize> GaugeHistogram<BUCKETS> {
+    /// Adds an observation of `value` to this histogram.
+    pub fn observe(&self, value: f64) {
+        self.core.observe(value);
+    }
+
+    /// Removes a previous observation of `value` from this histogram.
+    ///
+    /// The `value` must be the same value that was previously passed to
+    /// [`observe`](Self::observe), so that it is removed from the same bucket.
+    /// If the bucket containing `value` is already empty, this does nothing,
+    /// so that no bucket's count ever exceeds the count of the histogram.
+    pub fn remove(&self, value: f64) {
+        let core = &self.core;
+        let finite = match core.buckets.remove(value) {
+            Some(false) => return,
+            Some(true) => true,
+            None => false,
+        };
+        let removed = core
+            .count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                // the count is the `+Inf` bucket, which must not fall below
+                // the finite buckets.
+                if finite || count > core.buckets.total() {
+                    count.checked_sub(1)
+                } else {
+                    None
+                }
+            });
+        if removed.is_err() {
+            if finite {
+                core.buckets.observe(value);
+            }
+            return;
+        }
+        core.update_timestamp();
+        core.sum.fetch_sub(value, Ordering::AcqRel);
+    }
+
+    /// Returns the number of observations currently in this histogram.
+    pub fn gcount(&self) -> usize {
+        self.core.count()
+    }
+
+    /// Returns the sum of the observations currently in this histogram.
+    pub fn gsum(&self) -> f64 {
+        self.core.sum()
+    }
+
+    /// Returns an iterator over the upper bound and the _cumulative_ count of
+    /// each bucket in this histogram, including the final `+Inf` bucket.
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        self.core.buckets()
+    }
+}
+
+impl<const BUCKETS: usize> Metric for GaugeHistogram<BUCKETS> {
+    const TYPE: &'static str = "gaugehistogram";
+    const BUCKETS: Option<usize> = Some(BUCKETS);
+
+    /// Formats the current count of this histogram.
+    fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        self.core.fmt_metric_as(format, writer)
+    }
+
+    fn fmt_series<F: fmt::Write>(
+        &self,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        self.fmt_series_as(Format::OpenMetrics, name, labels, writer)
+    }
+
+    /// The [Prometheus](Format::Prometheus) format has no gauge histogram
+    /// type, so the family is typed as `untyped` and keeps the `_gcount` and
+    /// `_gsum` sample names, rather than posing as a histogram whose `_count`
+    /// and `_sum` may decrease.
+    fn fmt_series_as<F: fmt::Write>(
+        &self,
+        format: Format,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        self.core
+            .fmt_series_as(format, name, labels, ("_gcount", "_gsum"), writer)
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.core.last_updated()
+    }
+
+    fn build(builder: &MetricBuilder<'_>) -> Self {
+        Self {
+            core: Core::from_builder(builder),
+        }
+    }
+}
+
+/// Observes the duration in seconds.
+impl<const BUCKETS: usize> RecordDuration for GaugeHistogram<BUCKETS> {
+    fn record_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const BUCKETS: usize> Serialize for GaugeHistogram<BUCKETS> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.core
+            .serialize("GaugeHistogram", ("gcount", "gsum"), serializer)
+    }
+}
+
+// === impl Core ===
+
+impl<const BUCKETS: usize> Core<BUCKETS> {
+    fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        Self {
+            buckets: Buckets::new(builder.buckets),
+            count: AtomicUsize::new(0),
+            sum: AtomicF64::new(0.0),
+            #[cfg(feature = "timestamp")]
+            timestamp: builder.mk_timestamp(),
+        }
+    }
+
+    fn observe(&self, value: f64) {
+        self.update_timestamp();
+        self.buckets.observe(value);
+        self.count.fetch_add(1, Ordering::AcqRel);
+        self.sum.fetch_add(value, Ordering::AcqRel);
+    }
+
+    fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    fn sum(&self) -> f64 {
+        self.sum.load(Ordering::Acquire)
+    }
+
+    fn buckets(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        self.buckets
+            .cumulative()
+            .chain(Some((f64::INFINITY, self.count())))
+    }
+
+    #[inline]
+    fn update_timestamp(&self) {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            timestamp.update_max();
+        }
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        write!(writer, "{}", self.count())?;
+
+        #[cfg(feature = "timestamp")]
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+        Ok(())
+    }
+
+    /// Formats a `_bucket` sample for each bucket, followed by the count and
+    /// sum samples, with the provided `suffixes`.
+    fn fmt_series_as<F: fmt::Write>(
+        &self,
+        format: Format,
+        name: &str,
+        labels: &impl FmtLabels,
+        (count, sum): (&str, &str),
+        writer: &mut F,
+    ) -> fmt::Result {
+        for (bound, bucket) in self.buckets() {
+            let labels = Chain(labels, ("le", Bound(bound)));
+            self.fmt_sample(format, writer, (name, "_bucket"), &labels, bucket)?;
+        }
+        self.fmt_sample(format, writer, (name, count), labels, self.count())?;
+        self.fmt_sample(format, writer, (name, sum), labels, FmtF64(self.sum()))
+    }
+
+    fn fmt_sample<F: fmt::Write>(
+        &self,
+        format: Format,
+        writer: &mut F,
+        (name, suffix): (&str, &str),
+        labels: &impl FmtLabels,
+        value: impl fmt::Display,
+    ) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        fmt_sample_name(writer, name, suffix, labels)?;
+        write!(writer, " {value}")?;
+
+        #[cfg(feature = "timestamp")]
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+        writer.write_char('\n')
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize<S>(
+        &self,
+        name: &'static str,
+        (count, sum): (&'static str, &'static str),
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        struct SerializeBuckets<'a, const BUCKETS: usize>(&'a Core<BUCKETS>);
+
+        impl<const BUCKETS: usize> Serialize for SerializeBuckets<'_, BUCKETS> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let mut seq = serializer.serialize_seq(None)?;
+                for bucket in self.0.buckets() {
+                    seq.serialize_element(&bucket)?;
+                }
+                seq.end()
+            }
+        }
+
+        let mut histogram = serializer.serialize_struct(name, 3)?;
+        histogram.serialize_field("buckets", &SerializeBuckets(self))?;
+        histogram.serialize_field(count, &self.count())?;
+        histogram.serialize_field(sum, &self.sum())?;
+        histogram.end()
+    }
+}
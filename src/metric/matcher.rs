@@ -0,0 +1,328 @@
+use super::FmtLabels;
+use core::fmt;
+
+/// Selects series by the value of one of their labels.
+///
+/// A `Matcher` tests a label set formatted by a [`FmtLabels`] implementation,
+/// without allocating: the label set is parsed as it is written. As in
+/// Prometheus, a label which is not present in a label set is treated as
+/// though its value were the empty string.
+///
+/// Matchers may be used to expose only some of the series in a metric family,
+/// using [`MetricFamily::fmt_metric_matching`](super::MetricFamily::fmt_metric_matching)
+/// or [`MetricSet::fmt_metrics_matching`](crate::MetricSet::fmt_metrics_matching).
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::Matcher;
+///
+/// let labels = [("port", "eth0"), ("direction", "rx")];
+///
+/// assert!(Matcher::equal("port", "eth0").matches(&labels));
+/// assert!(Matcher::not_equal("port", "eth1").matches(&labels));
+/// assert!(Matcher::prefix("port", "eth").matches(&labels));
+/// assert!(!Matcher::equal("direction", "tx").matches(&labels));
+///
+/// // matchers may also be parsed from PromQL-style selectors:
+/// let matcher = Matcher::parse("port=\"eth0\"").unwrap();
+/// assert_eq!(matcher, Matcher::equal("port", "eth0"));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Matcher<'a> {
+    name: &'a str,
+    op: MatchOp,
+    value: &'a str,
+}
+
+/// The comparison performed by a [`Matcher`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MatchOp {
+    /// The label's value is equal to the matcher's value (`name="value"`).
+    Equal,
+    /// The label's value is not equal to the matcher's value
+    /// (`name!="value"`).
+    NotEqual,
+    /// The label's value starts with the matcher's value
+    /// (`name=~"value.*"`).
+    Prefix,
+}
+
+/// An error returned by [`Matcher::parse`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseMatcherError(&'static str);
+
+/// A [`fmt::Write`] implementation which parses a formatted label set,
+/// passing each byte of the value of the label named `name` to `on_value`.
+struct FindLabel<'n, F> {
+    name: &'n [u8],
+    on_value: F,
+    state: State,
+    /// Whether the bytes of the current label's name seen so far match
+    /// `name`, and how many have been seen.
+    name_ok: bool,
+    name_pos: usize,
+    /// Whether the current label is the one being searched for.
+    in_label: bool,
+    /// Whether the label has been found.
+    found: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Name,
+    OpenQuote,
+    Value,
+    Escape,
+    Separator,
+}
+
+/// Finds the label named `name` in `labels`, passing each (unescaped) byte of
+/// its value to `on_value`.
+///
+/// Returns `Ok(true)` if the label was found, `Ok(false)` if it was not, or an
+/// error if `labels` could not be parsed. If the label occurs more than once,
+/// only its first value is passed to `on_value`.
+pub(super) fn find_label(
+    labels: &impl FmtLabels,
+    name: &str,
+    on_value: impl FnMut(u8),
+) -> Result<bool, fmt::Error> {
+    let mut parser = FindLabel {
+        name: name.as_bytes(),
+        on_value,
+        state: State::Name,
+        name_ok: true,
+        name_pos: 0,
+        in_label: false,
+        found: false,
+    };
+    labels.fmt_labels(&mut parser)?;
+    match parser.state {
+        // the label set is empty, or ended after a complete label.
+        State::Separator => Ok(parser.found),
+        State::Name if parser.name_pos == 0 => Ok(parser.found),
+        // the label set ended in the middle of a label.
+        _ => Err(fmt::Error),
+    }
+}
+
+// === impl Matcher ===
+
+impl<'a> Matcher<'a> {
+    /// Returns a matcher which selects label sets where the label `name` has
+    /// the value `value`.
+    #[must_use]
+    pub const fn equal(name: &'a str, value: &'a str) -> Self {
+        Self {
+            name,
+            op: MatchOp::Equal,
+            value,
+        }
+    }
+
+    /// Returns a matcher which selects label sets where the label `name` does
+    /// not have the value `value`.
+    #[must_use]
+    pub const fn not_equal(name: &'a str, value: &'a str) -> Self {
+        Self {
+            name,
+            op: MatchOp::NotEqual,
+            value,
+        }
+    }
+
+    /// Returns a matcher which selects label sets where the value of the
+    /// label `name` starts with `prefix`.
+    #[must_use]
+    pub const fn prefix(name: &'a str, prefix: &'a str) -> Self {
+        Self {
+            name,
+            op: MatchOp::Prefix,
+            value: prefix,
+        }
+    }
+
+    /// Parses a matcher from a PromQL-style label selector, such as
+    /// `port="eth0"`.
+    ///
+    /// The following forms are accepted:
+    ///
+    /// - `name="value"`: an [equality](MatchOp::Equal) matcher.
+    /// - `name!="value"`: an [inequality](MatchOp::NotEqual) matcher.
+    /// - `name=~"value.*"`: a [prefix](MatchOp::Prefix) matcher. Other
+    ///   regular expressions are not supported.
+    ///
+    /// Since the returned matcher borrows the input, values may not contain
+    /// escape sequences.
+    pub fn parse(s: &'a str) -> Result<Self, ParseMatcherError> {
+        let name_end = s
+            .find(|c| c == '=' || c == '!')
+            .ok_or(ParseMatcherError("expected `=`, `!=`, or `=~`"))?;
+        let (name, rest) = s.split_at(name_end);
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ParseMatcherError("missing label name"));
+        }
+
+        let (op, rest) = if let Some(rest) = rest.strip_prefix("!=") {
+            (MatchOp::NotEqual, rest)
+        } else if let Some(rest) = rest.strip_prefix("=~") {
+            (MatchOp::Prefix, rest)
+        } else if let Some(rest) = rest.strip_prefix('=') {
+            (MatchOp::Equal, rest)
+        } else {
+            return Err(ParseMatcherError("expected `=`, `!=`, or `=~`"));
+        };
+
+        let value = rest
+            .trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or(ParseMatcherError("label value must be quoted"))?;
+        if value.contains(['\\', '"']) {
+            return Err(ParseMatcherError(
+                "label value may not contain escape sequences",
+            ));
+        }
+
+        let value = match op {
+            MatchOp::Prefix => {
+                let prefix = value.strip_suffix(".*").ok_or(ParseMatcherError(
+                    "only prefix regular expressions are supported",
+                ))?;
+                if prefix.contains(|c| ".*+?()[]{}|^$".contains(c)) {
+                    return Err(ParseMatcherError(
+                        "only prefix regular expressions are supported",
+                    ));
+                }
+                prefix
+            }
+            _ => value,
+        };
+
+        Ok(Self { name, op, value })
+    }
+
+    /// Returns the name of the label this matcher tests.
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the comparison this matcher performs.
+    #[must_use]
+    pub fn op(&self) -> MatchOp {
+        self.op
+    }
+
+    /// Returns the value this matcher compares the label's value to.
+    #[must_use]
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+
+    /// Returns `true` if `labels` are selected by this matcher.
+    ///
+    /// If `labels` cannot be parsed (such as if a label value contains an
+    /// unescaped `"`), this returns `false`.
+    pub fn matches(&self, labels: &impl FmtLabels) -> bool {
+        let expected = self.value.as_bytes();
+        let prefix = self.op == MatchOp::Prefix;
+        let mut pos = 0;
+        let mut value_ok = true;
+        let found = find_label(labels, self.name, |byte| {
+            // extra bytes are permitted after a prefix.
+            value_ok = value_ok && expected.get(pos).map_or(prefix, |&b| b == byte);
+            pos += 1;
+        });
+
+        let matched = match found {
+            Ok(true) => value_ok && pos >= expected.len(),
+            // labels which are not present match the empty string.
+            Ok(false) => expected.is_empty(),
+            Err(_) => return false,
+        };
+        match self.op {
+            MatchOp::NotEqual => !matched,
+            MatchOp::Equal | MatchOp::Prefix => matched,
+        }
+    }
+
+    /// Returns `true` if `labels` are selected by every matcher in
+    /// `matchers`.
+    ///
+    /// An empty slice of matchers selects every label set.
+    pub fn matches_all(matchers: &[Matcher<'_>], labels: &impl FmtLabels) -> bool {
+        matchers.iter().all(|matcher| matcher.matches(labels))
+    }
+}
+
+impl fmt::Display for Matcher<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { name, value, .. } = self;
+        match self.op {
+            MatchOp::Equal => write!(f, "{name}=\"{value}\""),
+            MatchOp::NotEqual => write!(f, "{name}!=\"{value}\""),
+            MatchOp::Prefix => write!(f, "{name}=~\"{value}.*\""),
+        }
+    }
+}
+
+// === impl ParseMatcherError ===
+
+impl fmt::Display for ParseMatcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid label matcher: {}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseMatcherError {}
+
+// === impl FindLabel ===
+
+impl<F: FnMut(u8)> fmt::Write for FindLabel<'_, F> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.state = match (self.state, byte) {
+                (State::Name, b'=') => {
+                    self.in_label = !self.found && self.name_ok && self.name_pos == self.name.len();
+                    State::OpenQuote
+                }
+                (State::Name, _) => {
+                    self.name_ok = self.name_ok && self.name.get(self.name_pos) == Some(&byte);
+                    self.name_pos += 1;
+                    State::Name
+                }
+                (State::OpenQuote, b'"') => State::Value,
+                (State::Value, b'\\') => State::Escape,
+                (State::Value, b'"') => {
+                    self.found |= self.in_label;
+                    self.in_label = false;
+                    State::Separator
+                }
+                (State::Value, _) => {
+                    if self.in_label {
+                        (self.on_value)(byte);
+                    }
+                    State::Value
+                }
+                (State::Escape, _) => {
+                    if self.in_label {
+                        (self.on_value)(if byte == b'n' { b'\n' } else { byte });
+                    }
+                    State::Value
+                }
+                (State::Separator, b',') => {
+                    self.name_ok = true;
+                    self.name_pos = 0;
+                    State::Name
+                }
+                // the label set is malformed.
+                (State::OpenQuote, _) | (State::Separator, _) => return Err(fmt::Error),
+            };
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+use super::{
+    fmt_label, Counter, FmtLabels, Gauge, IntGauge, IsizeGauge, Metric, MetricFamily, Unknown,
+};
+use crate::registry::Storage;
+use core::{fmt, ptr};
+use portable_atomic::Ordering;
+
+#[cfg(feature = "timestamp")]
+use crate::timestamp::{TimestampCell, UnixTimestamp};
+
+/// Trait implemented by metrics whose values may be combined with those of
+/// another metric of the same type, when [merging] metric families.
+///
+/// - [`Counter`]s are summed.
+/// - Gauges and [`Unknown`] metrics keep the value of the most recently
+///   updated metric. If neither metric records timestamps, the value being
+///   merged in is kept.
+///
+/// [merging]: MetricFamily::merge_from
+pub trait MergeMetric: Metric {
+    /// Merges the value of `other` into this metric.
+    fn merge(&self, other: &Self);
+
+    /// Replaces the value of this metric with the value of `other`.
+    ///
+    /// By default, this is the same as [`merge`](Self::merge), which is
+    /// correct for metrics which keep the most recently updated value.
+    fn replace(&self, other: &Self) {
+        self.merge(other);
+    }
+}
+
+/// A label set with an additional label identifying the source it was merged
+/// from, such as `instance="sensor-1"`.
+///
+/// The additional label is formatted first, followed by the source family's
+/// [constant labels](Instanced::with_const_labels), if any, and then the rest
+/// of the label set. This is the label set type of families built by
+/// [`MetricFamily::merge_instance`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Instanced<'a, L> {
+    name: &'a str,
+    value: &'a str,
+    const_labels: &'a [(&'a str, &'a str)],
+    labels: L,
+}
+
+/// An error returned when merging two [`MetricFamily`]s whose metadata
+/// differs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeError {
+    field: &'static str,
+}
+
+// === impl MetricFamily ===
+
+impl<M, L, R, const METRICS: usize> MetricFamily<'_, M, METRICS, L, R>
+where
+    M: MergeMetric,
+    L: FmtLabels + PartialEq,
+    R: Storage<L, M>,
+{
+    /// Merges every recorded series in `source` into this family, returning
+    /// the number of series which were merged.
+    ///
+    /// The label set of each series in `source` is converted to a label set
+    /// for this family by `map_labels`, which is passed the source family's
+    /// [constant labels](MetricFamily::const_labels) along with the series'
+    /// label set, so that they may be folded into the new label set. The
+    /// series with that label set is registered if it does not already
+    /// exist, and its value is [replaced] with that of the source series.
+    /// Source series which map to the same label set in a single call are
+    /// [merged] together, so that, for example, counters may be summed by
+    /// mapping them all to the same label set.
+    ///
+    /// If this family is full, source series which would be registered in it
+    /// are merged into the overflow series (if the [overflow policy] is
+    /// enabled) or dropped. The overflow series of `source` is not merged.
+    ///
+    /// Since each call replaces the values merged by the previous call, the
+    /// same source may be merged into a family periodically to keep it up to
+    /// date. Series which are removed from `source` are not removed from
+    /// this family.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, without merging any series, if `source` does not have
+    /// the same name, unit, and help text as this family.
+    ///
+    /// [replaced]: MergeMetric::replace
+    /// [merged]: MergeMetric::merge
+    /// [overflow policy]: super::MetricBuilder::with_overflow
+    pub fn merge_from<'s, L2, R2, const METRICS2: usize>(
+        &self,
+        source: &MetricFamily<'s, M, METRICS2, L2, R2>,
+        mut map_labels: impl FnMut(&'s [(&'s str, &'s str)], &L2) -> L,
+    ) -> Result<usize, MergeError>
+    where
+        R2: Storage<L2, M>,
+    {
+        if self.name() != source.name() {
+            return Err(MergeError { field: "name" });
+        }
+        if self.unit() != source.unit() {
+            return Err(MergeError { field: "unit" });
+        }
+        if self.help() != source.help() {
+            return Err(MergeError { field: "help text" });
+        }
+
+        // the series merged into by this call, so that the first source series
+        // merged into each of them replaces its value, and any others are
+        // merged with it.
+        let mut dests = [ptr::null::<M>(); METRICS];
+        let mut num_dests = 0;
+        let mut overflow_merged = false;
+
+        let mut merged = 0;
+        for entry in source.metrics().refs() {
+            let metric = entry.value();
+            if !metric.has_been_recorded() {
+                continue;
+            }
+            let dest = match self.register_ref(map_labels(source.const_labels(), entry.key())) {
+                Some(dest) => dest,
+                None => continue,
+            };
+
+            let dest_ptr: *const M = &*dest;
+            let first = if self
+                .overflow()
+                .map_or(false, |overflow| ptr::eq(overflow, dest_ptr))
+            {
+                !core::mem::replace(&mut overflow_merged, true)
+            } else if dests[..num_dests].contains(&dest_ptr) {
+                false
+            } else {
+                // every series other than the overflow series is stored in
+                // this family, which holds at most `METRICS` series.
+                if let Some(slot) = dests.get_mut(num_dests) {
+                    *slot = dest_ptr;
+                    num_dests += 1;
+                }
+                true
+            };
+            if first {
+                dest.replace(metric);
+            } else {
+                dest.merge(metric);
+            }
+            merged += 1;
+        }
+        Ok(merged)
+    }
+}
+
+impl<'i, M, L, R, const METRICS: usize> MetricFamily<'_, M, METRICS, Instanced<'i, L>, R>
+where
+    M: MergeMetric,
+    L: FmtLabels + PartialEq + Clone,
+    R: Storage<Instanced<'i, L>, M>,
+{
+    /// Merges every recorded series in `source` into this family, adding the
+    /// label `name="value"` to each series to distinguish it from the series
+    /// merged from other sources.
+    ///
+    /// This is equivalent to calling [`merge_from`](Self::merge_from) with a
+    /// function which wraps each label set, and the source family's constant
+    /// labels, in an [`Instanced`] label set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::{CounterFamily, Instanced, MetricBuilder, MetricFamily, Counter};
+    ///
+    /// type Labels = &'static [(&'static str, &'static str)];
+    ///
+    /// static DEVICE_1: CounterFamily<'static, 4> = MetricBuilder::new("packets").build();
+    /// static DEVICE_2: CounterFamily<'static, 4> = MetricBuilder::new("packets").build();
+    /// static MERGED: MetricFamily<'static, Counter, 8, Instanced<'static, Labels>> =
+    ///     MetricBuilder::new("packets").build_labeled();
+    ///
+    /// DEVICE_1.register(&[("port", "eth0")]).unwrap().fetch_add(3);
+    /// DEVICE_2.register(&[("port", "eth0")]).unwrap().fetch_add(5);
+    ///
+    /// MERGED.merge_instance(("instance", "device-1"), &DEVICE_1).unwrap();
+    /// MERGED.merge_instance(("instance", "device-2"), &DEVICE_2).unwrap();
+    ///
+    /// let exposition = MERGED.to_string();
+    /// assert!(exposition.contains("packets{instance=\"device-1\",port=\"eth0\"} 3"));
+    /// assert!(exposition.contains("packets{instance=\"device-2\",port=\"eth0\"} 5"));
+    /// ```
+    pub fn merge_instance<R2, const METRICS2: usize>(
+        &self,
+        (name, value): (&'i str, &'i str),
+        source: &MetricFamily<'i, M, METRICS2, L, R2>,
+    ) -> Result<usize, MergeError>
+    where
+        R2: Storage<L, M>,
+    {
+        self.merge_from(source, |const_labels, labels| {
+            Instanced::new(name, value, labels.clone()).with_const_labels(const_labels)
+        })
+    }
+}
+
+// === impl Instanced ===
+
+impl<'a, L> Instanced<'a, L> {
+    /// Returns a new label set which adds the label `name="value"` to
+    /// `labels`.
+    #[must_use]
+    pub const fn new(name: &'a str, value: &'a str, labels: L) -> Self {
+        Self {
+            name,
+            value,
+            const_labels: &[],
+            labels,
+        }
+    }
+
+    /// Adds `const_labels`, such as the constant labels of the family a
+    /// label set was merged from, after the added label.
+    #[must_use]
+    pub fn with_const_labels(self, const_labels: &'a [(&'a str, &'a str)]) -> Self {
+        Self {
+            const_labels,
+            ..self
+        }
+    }
+
+    /// Returns the name of the added label.
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the value of the added label.
+    #[must_use]
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+
+    /// Returns the constant labels added after the added label.
+    #[must_use]
+    pub fn const_labels(&self) -> &'a [(&'a str, &'a str)] {
+        self.const_labels
+    }
+
+    /// Returns the label set the label was added to.
+    #[must_use]
+    pub fn labels(&self) -> &L {
+        &self.labels
+    }
+}
+
+impl<L: FmtLabels> FmtLabels for Instanced<'_, L> {
+    fn fmt_labels(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        fmt_label(writer, self.name, &self.value)?;
+        for (name, value) in self.const_labels {
+            writer.write_char(',')?;
+            fmt_label(writer, name, value)?;
+        }
+        if !self.labels.is_empty() {
+            writer.write_char(',')?;
+            self.labels.fmt_labels(writer)?;
+        }
+        Ok(())
+    }
+}
+
+// === impl MergeError ===
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot merge metric families with different {}s",
+            self.field
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MergeError {}
+
+// === impl MergeMetric ===
+
+impl MergeMetric for Counter {
+    fn merge(&self, other: &Self) {
+        self.value.fetch_add(other.value(), Ordering::AcqRel);
+        #[cfg(feature = "timestamp")]
+        merge_timestamp(&self.timestamp, other.last_updated());
+    }
+
+    fn replace(&self, other: &Self) {
+        self.value.store(other.value(), Ordering::Release);
+        #[cfg(feature = "timestamp")]
+        merge_timestamp(&self.timestamp, other.last_updated());
+    }
+}
+
+macro_rules! impl_keep_last {
+    ($($metric:ty),+) => {
+        $(
+            impl MergeMetric for $metric {
+                fn merge(&self, other: &Self) {
+                    if !other.has_been_recorded() {
+                        return;
+                    }
+                    #[cfg(feature = "timestamp")]
+                    if !merge_timestamp(&self.timestamp, other.last_updated()) {
+                        return;
+                    }
+                    self.value.store(other.value(), Ordering::Release);
+                    self.recorded.store(true, Ordering::Release);
+                }
+            }
+        )+
+    };
+}
+
+impl_keep_last!(Gauge, IntGauge, IsizeGauge, Unknown);
+
+/// Advances a merged metric's timestamp to that of the metric merged into it,
+/// returning `false` if the merged metric was updated more recently, in which
+/// case a gauge should keep its own value.
+#[cfg(feature = "timestamp")]
+pub(super) fn merge_timestamp(
+    timestamp: &Option<TimestampCell>,
+    other: Option<UnixTimestamp>,
+) -> bool {
+    let timestamp = match timestamp {
+        Some(timestamp) => timestamp,
+        None => return true,
+    };
+    if let (Some(curr), Some(other)) = (timestamp.last_updated(), other) {
+        if curr.as_secs() > other.as_secs() {
+            return false;
+        }
+    }
+    timestamp.update_to(other);
+    true
+}
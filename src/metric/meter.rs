@@ -0,0 +1,237 @@
+use super::{fmt_sample_name, Chain, FmtLabels, Format, Metric, MetricBuilder, MetricFamily};
+use crate::registry::Storage;
+use core::{fmt, time::Duration};
+use portable_atomic::{AtomicBool, AtomicF64, AtomicUsize, Ordering};
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+#[cfg(feature = "timestamp")]
+use crate::timestamp::{TimestampCell, UnixTimestamp};
+
+/// A metric which counts events and tracks the rate at which they occur.
+///
+/// A `Meter` tracks a total count of events, and exponentially-weighted moving
+/// averages of the rate of events (per second) over the last one, five, and
+/// fifteen minutes, similarly to the Unix load average. The moving averages
+/// are updated by calling [`Meter::tick`] (or [`MetricFamily::tick`]) every
+/// [`Meter::TICK_INTERVAL`].
+///
+/// This is not a standardized OpenMetrics metric type. Instead, each series
+/// in a `Meter` family is exported as three Gauge samples, one for each rate,
+/// distinguished by a `window` label whose value is `"1m"`, `"5m"`, or
+/// `"15m"`.
+#[derive(Debug)]
+pub struct Meter {
+    count: AtomicUsize,
+    uncounted: AtomicUsize,
+    rates: [Ewma; 3],
+
+    #[cfg(feature = "timestamp")]
+    timestamp: Option<TimestampCell>,
+}
+
+#[derive(Debug)]
+struct Ewma {
+    rate: AtomicF64,
+    initialized: AtomicBool,
+    alpha: f64,
+    window: &'static str,
+}
+
+// === impl Meter ===
+
+impl Meter {
+    /// The interval at which [`Meter::tick`] must be called in order for the
+    /// moving averages to be accurate.
+    pub const TICK_INTERVAL: Duration = Duration::from_secs(TICK_INTERVAL_SECS as u64);
+
+    const fn from_builder(builder: &MetricBuilder<'_>) -> Self {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = builder;
+        Self {
+            count: AtomicUsize::new(0),
+            uncounted: AtomicUsize::new(0),
+            // The smoothing factor for each moving average is
+            // `1 - exp(-TICK_INTERVAL / window)`. These are precomputed, since
+            // `exp` is not available in `core`.
+            rates: [
+                Ewma::new(0.07995558537067671, "1m"),
+                Ewma::new(0.01652854617838251, "5m"),
+                Ewma::new(0.005540151995103271, "15m"),
+            ],
+            #[cfg(feature = "timestamp")]
+            timestamp: builder.mk_timestamp(),
+        }
+    }
+
+    /// Records a single event.
+    pub fn mark(&self) {
+        self.mark_n(1);
+    }
+
+    /// Records `n` events.
+    pub fn mark_n(&self, n: usize) {
+        #[cfg(feature = "timestamp")]
+        if let Some(ref timestamp) = self.timestamp {
+            timestamp.update_max();
+        }
+        self.uncounted.fetch_add(n, Ordering::AcqRel);
+        self.count.fetch_add(n, Ordering::Release);
+    }
+
+    /// Updates the moving averages with the events recorded since the last
+    /// tick.
+    ///
+    /// This must be called every [`Meter::TICK_INTERVAL`].
+    pub fn tick(&self) {
+        let count = self.uncounted.swap(0, Ordering::AcqRel);
+        let instant_rate = count as f64 / TICK_INTERVAL_SECS;
+        for ewma in &self.rates {
+            ewma.tick(instant_rate);
+        }
+    }
+
+    /// Returns the total number of events recorded by this meter.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Returns the moving average of the rate of events per second over the
+    /// last minute.
+    pub fn one_minute_rate(&self) -> f64 {
+        self.rates[0].rate()
+    }
+
+    /// Returns the moving average of the rate of events per second over the
+    /// last five minutes.
+    pub fn five_minute_rate(&self) -> f64 {
+        self.rates[1].rate()
+    }
+
+    /// Returns the moving average of the rate of events per second over the
+    /// last fifteen minutes.
+    pub fn fifteen_minute_rate(&self) -> f64 {
+        self.rates[2].rate()
+    }
+}
+
+const TICK_INTERVAL_SECS: f64 = 5.0;
+
+impl Metric for Meter {
+    const TYPE: &'static str = "gauge";
+
+    /// Formats the one-minute rate of this meter.
+    fn fmt_metric<F: fmt::Write>(&self, writer: &mut F) -> fmt::Result {
+        self.fmt_metric_as(Format::OpenMetrics, writer)
+    }
+
+    fn fmt_metric_as<F: fmt::Write>(&self, format: Format, writer: &mut F) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        write!(writer, "{}", self.one_minute_rate())?;
+
+        #[cfg(feature = "timestamp")]
+        format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+        Ok(())
+    }
+
+    fn fmt_series<F: fmt::Write>(
+        &self,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        self.fmt_series_as(Format::OpenMetrics, name, labels, writer)
+    }
+
+    fn fmt_series_as<F: fmt::Write>(
+        &self,
+        format: Format,
+        name: &str,
+        labels: &impl FmtLabels,
+        writer: &mut F,
+    ) -> fmt::Result {
+        #[cfg(not(feature = "timestamp"))]
+        let _ = format;
+        for ewma in &self.rates {
+            fmt_sample_name(writer, name, "", &Chain(labels, ("window", ewma.window)))?;
+            write!(writer, " {}", ewma.rate())?;
+
+            #[cfg(feature = "timestamp")]
+            format.fmt_timestamp(writer, self.timestamp.as_ref())?;
+
+            writer.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "timestamp")]
+    fn last_updated(&self) -> Option<UnixTimestamp> {
+        self.timestamp.as_ref()?.last_updated()
+    }
+
+    fn build(builder: &MetricBuilder<'_>) -> Self {
+        Self::from_builder(builder)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Meter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut meter = serializer.serialize_struct("Meter", 4)?;
+        meter.serialize_field("count", &self.count())?;
+        meter.serialize_field("1m", &self.one_minute_rate())?;
+        meter.serialize_field("5m", &self.five_minute_rate())?;
+        meter.serialize_field("15m", &self.fifteen_minute_rate())?;
+        meter.end()
+    }
+}
+
+impl<L, R, const METRICS: usize> MetricFamily<'_, Meter, METRICS, L, R>
+where
+    R: Storage<L, Meter>,
+{
+    /// [Ticks](Meter::tick) every meter in this family.
+    ///
+    /// This must be called every [`Meter::TICK_INTERVAL`].
+    pub fn tick(&self) {
+        for entry in self.metrics.refs() {
+            entry.value().tick();
+        }
+    }
+}
+
+// === impl Ewma ===
+
+impl Ewma {
+    const fn new(alpha: f64, window: &'static str) -> Self {
+        Self {
+            rate: AtomicF64::new(0.0),
+            initialized: AtomicBool::new(false),
+            alpha,
+            window,
+        }
+    }
+
+    fn tick(&self, instant_rate: f64) {
+        if self.initialized.swap(true, Ordering::AcqRel) {
+            let _ = self
+                .rate
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |rate| {
+                    Some(rate + self.alpha * (instant_rate - rate))
+                });
+        } else {
+            // the first tick sets the initial rate.
+            self.rate.store(instant_rate, Ordering::Release);
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        self.rate.load(Ordering::Acquire)
+    }
+}
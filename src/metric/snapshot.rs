@@ -0,0 +1,165 @@
+use super::{Counter, MetricFamily};
+use crate::registry::{MapRef, Storage};
+use core::{fmt, ptr};
+
+/// A copy of the values of the series in a counter [`MetricFamily`], used to
+/// compute the change in each series' value since the snapshot was taken.
+///
+/// A `CounterSnapshot` is a fixed-size array of up to `METRICS` series, which
+/// is owned by the caller, so that it may be stored on the stack or in a
+/// `static` without allocating. It is populated by
+/// [`MetricFamily::snapshot`], and updated by [`MetricFamily::deltas`].
+///
+/// A snapshot holds a [counted reference](MapRef) to each series it
+/// contains. This ensures that a series which is
+/// [unregistered](MetricFamily::unregister) after the snapshot is taken is not
+/// confused with a new series which reuses its slot: the slot is not reused
+/// until the snapshot is next updated.
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::{CounterFamily, CounterSnapshot, MetricBuilder};
+///
+/// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests").build();
+///
+/// let mut snapshot = CounterSnapshot::new();
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(2);
+/// REQUESTS.snapshot(&mut snapshot);
+///
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(3);
+/// REQUESTS.register(&[("path", "/health")]).unwrap().fetch_add(1);
+///
+/// let mut deltas = Vec::new();
+/// REQUESTS.deltas(&mut snapshot, |labels, delta| deltas.push((labels[0].1, delta)));
+/// assert_eq!(deltas, [("/", 3), ("/health", 1)]);
+/// ```
+pub struct CounterSnapshot<'f, L, const METRICS: usize> {
+    series: [Option<Series<'f, L>>; METRICS],
+}
+
+/// A series in a [`CounterSnapshot`].
+struct Series<'f, L> {
+    entry: MapRef<'f, L, Counter>,
+    value: usize,
+    /// The number of times the counter had been reset when the snapshot was
+    /// taken.
+    resets: usize,
+}
+
+// === impl CounterSnapshot ===
+
+impl<'f, L, const METRICS: usize> CounterSnapshot<'f, L, METRICS> {
+    const EMPTY: Option<Series<'f, L>> = None;
+
+    /// Returns a new, empty snapshot.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            series: [Self::EMPTY; METRICS],
+        }
+    }
+
+    /// Returns an iterator over the label set and value of each series in
+    /// this snapshot.
+    pub fn iter(&self) -> impl Iterator<Item = (&L, usize)> + '_ {
+        self.series
+            .iter()
+            .flatten()
+            .map(|series| (series.entry.key(), series.value))
+    }
+
+    /// Returns the number of series in this snapshot.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.series.iter().flatten().count()
+    }
+
+    /// Returns `true` if this snapshot contains no series.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.series.iter().all(Option::is_none)
+    }
+
+    /// Removes every series from this snapshot.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Removes the series whose metric is `metric` from this snapshot.
+    fn take(&mut self, metric: &Counter) -> Option<Series<'f, L>> {
+        self.series
+            .iter_mut()
+            .find(|series| matches!(series, Some(series) if ptr::eq(series.entry.value(), metric)))
+            .and_then(Option::take)
+    }
+}
+
+impl<L, const METRICS: usize> Default for CounterSnapshot<'_, L, METRICS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: fmt::Debug, const METRICS: usize> fmt::Debug for CounterSnapshot<'_, L, METRICS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// === impl MetricFamily ===
+
+impl<L, R, const METRICS: usize> MetricFamily<'_, Counter, METRICS, L, R>
+where
+    R: Storage<L, Counter>,
+{
+    /// Replaces the contents of `snapshot` with the current value of every
+    /// series in this family.
+    pub fn snapshot<'f>(&'f self, snapshot: &mut CounterSnapshot<'f, L, METRICS>) {
+        self.deltas(snapshot, |_, _| {});
+    }
+
+    /// Calls `f` with the label set of every series in this family, and the
+    /// amount by which its value has increased since `snapshot` was taken,
+    /// then updates `snapshot` with the current values.
+    ///
+    /// - Series which were registered after the snapshot was taken are
+    ///   reported with their entire current value as the delta.
+    /// - If a series has been reset by [`Counter::swap`] or [`Counter::take`]
+    ///   since the snapshot was taken, its entire current value is reported as
+    ///   the delta, as for a new series.
+    /// - Otherwise, if a series' value is less than its value in the
+    ///   snapshot, the counter has wrapped around past [`usize::MAX`], and the
+    ///   delta is computed with wrapping arithmetic.
+    /// - Series which have been removed from the family since the snapshot
+    ///   was taken are removed from the snapshot.
+    ///
+    /// The [overflow series](MetricFamily::overflow) is not included.
+    pub fn deltas<'f>(
+        &'f self,
+        snapshot: &mut CounterSnapshot<'f, L, METRICS>,
+        mut f: impl FnMut(&L, usize),
+    ) {
+        let mut next = CounterSnapshot::new();
+        // the family's storage has a capacity of `METRICS`, so the snapshot
+        // has a slot for every series in it.
+        for (slot, entry) in next.series.iter_mut().zip(self.metrics.refs()) {
+            // read the number of resets first, so that a reset which has been
+            // counted is always reflected in the value.
+            let resets = entry.value().resets();
+            let value = entry.value().value();
+            let delta = match snapshot.take(entry.value()) {
+                Some(previous) if previous.resets == resets => value.wrapping_sub(previous.value),
+                // the series is new, or the counter was reset.
+                _ => value,
+            };
+            f(entry.key(), delta);
+            *slot = Some(Series {
+                entry,
+                value,
+                resets,
+            });
+        }
+        *snapshot = next;
+    }
+}
@@ -112,6 +112,40 @@ fn gauges_dont_start_at_0_if_unrecorded() {
     assert_str_eq!(family.to_string(), expected);
 }
 
+#[test]
+fn array_labels() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, [(&str, &str); 2], 1>()
+    };
+    family
+        .register([("metric", "1"), ("label2", "foo")])
+        .expect("metric 1 must register")
+        .fetch_add(1);
+    assert_str_eq!(
+        family.to_string(),
+        "# TYPE test_counter counter\n# UNIT test_counter \n# HELP test_counter \ntest_counter{metric=\"1\",label2=\"foo\"} 1\n\n"
+    );
+
+    // an empty array of labels is formatted without braces.
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, [(&str, &str); 0], 1>()
+    };
+    family
+        .register([])
+        .expect("metric must register")
+        .fetch_add(1);
+    assert_str_eq!(
+        family.to_string(),
+        "# TYPE test_counter counter\n# UNIT test_counter \n# HELP test_counter \ntest_counter 1\n\n"
+    );
+}
+
 #[test]
 #[cfg(feature = "timestamp")]
 fn gauge_timestamped() {
@@ -301,6 +335,307 @@ fn gauge_mean() {
     assert_eq!(family.mean(), Some(7.0));
 }
 
+#[test]
+fn gauge_percentile() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Gauge, _, 10>()
+    };
+    assert_eq!(family.median(), None);
+
+    // unrecorded metrics are not included.
+    family.register(("unrecorded", 0)).unwrap();
+    for (i, value) in [9.0, 4.0, 5.0, 2.0, 4.0, 7.0, 5.0, 4.0]
+        .into_iter()
+        .enumerate()
+    {
+        family
+            .register(("metric", i))
+            .expect("metric must register")
+            .set_value(value);
+    }
+
+    assert_eq!(family.percentile(0.0), Some(2.0));
+    assert_eq!(family.percentile(25.0), Some(4.0));
+    assert_eq!(family.median(), Some(4.5));
+    assert_eq!(family.percentile(100.0), Some(9.0));
+    let p90 = family.percentile(90.0).unwrap();
+    assert!((p90 - 7.6).abs() < 1e-9, "{p90}");
+    assert_eq!(family.percentile(-1.0), None);
+    assert_eq!(family.percentile(100.5), None);
+    assert_eq!(family.percentile(f64::NAN), None);
+
+    // NaN values are ignored.
+    family.register(("metric", 8)).unwrap().set_value(f64::NAN);
+    assert_eq!(family.median(), Some(4.5));
+    assert_eq!(family.mean(), Some(5.0));
+    assert_eq!(family.total(), 40.0);
+
+    family.register(("metric", 0)).unwrap().set_value(-3.5);
+    assert_eq!(family.percentile(0.0), Some(-3.5));
+    assert_eq!(family.median(), Some(4.0));
+}
+
+#[test]
+fn gauge_variance() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Gauge, _, 8>()
+    };
+    assert_eq!(family.variance(), None);
+    assert_eq!(family.stddev(), None);
+
+    family.register(("metric", 0)).unwrap().set_value(3.0);
+    assert_eq!(family.variance(), Some(0.0));
+    assert_eq!(family.stddev(), Some(0.0));
+
+    for (i, value) in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+        .into_iter()
+        .enumerate()
+    {
+        family.register(("metric", i)).unwrap().set_value(value);
+    }
+    assert_eq!(family.variance(), Some(4.0));
+    assert_eq!(family.stddev(), Some(2.0));
+
+    family.register(("metric", 0)).unwrap().set_value(1e9 + 2.0);
+    for i in 1..8 {
+        family.register(("metric", i)).unwrap().set_value(1e9);
+    }
+    let stddev = family.stddev().unwrap();
+    assert!((stddev - 7f64.sqrt() / 4.0).abs() < 1e-6, "{stddev}");
+    // NaN values are ignored.
+    family.register(("metric", 0)).unwrap().set_value(f64::NAN);
+    assert_eq!(family.variance(), Some(0.0));
+}
+
+#[test]
+fn counter_swap() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 1>()
+    };
+    let metric = family.register(&[]).expect("metric must register");
+    metric.fetch_add(5);
+    assert_eq!(metric.swap(2), 5);
+    assert_eq!(metric.value(), 2);
+    metric.fetch_add(1);
+    assert_eq!(metric.take(), 3);
+    assert_eq!(metric.value(), 0);
+}
+
+#[test]
+fn counter_deltas() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, _, 2>()
+    };
+    let mut snapshot = CounterSnapshot::new();
+    let deltas = |snapshot: &mut CounterSnapshot<'_, _, 2>| {
+        let mut deltas = Vec::new();
+        family.deltas(snapshot, |&(_, peer), delta| deltas.push((peer, delta)));
+        deltas
+    };
+
+    family
+        .register_ref(("peer", "1"))
+        .unwrap()
+        .fetch_add(usize::MAX - 1);
+    family.snapshot(&mut snapshot);
+    assert_eq!(
+        snapshot.iter().collect::<Vec<_>>(),
+        [(&("peer", "1"), usize::MAX - 1)]
+    );
+    assert_eq!(deltas(&mut snapshot), [("1", 0)]);
+
+    // the counter wraps around past `usize::MAX`.
+    family.register_ref(("peer", "1")).unwrap().fetch_add(3);
+    assert_eq!(family.register_ref(("peer", "1")).unwrap().value(), 1);
+    assert_eq!(deltas(&mut snapshot), [("1", 3)]);
+
+    // the counter is reset, and a new series is registered. the reset is
+    // detected even though the new value is greater than the old one.
+    let peer1 = family.register_ref(("peer", "1")).unwrap();
+    peer1.take();
+    peer1.fetch_add(3);
+    drop(peer1);
+    family.register_ref(("peer", "2")).unwrap().fetch_add(4);
+    assert_eq!(deltas(&mut snapshot), [("1", 3), ("2", 4)]);
+
+    // a series is unregistered, and a new series takes its place. the new
+    // series' slot can't be reused until the snapshot releases the old one.
+    assert!(family.unregister(&("peer", "1")));
+    assert!(family.register_ref(("peer", "3")).is_none());
+    assert_eq!(deltas(&mut snapshot), [("2", 0)]);
+    assert_eq!(snapshot.len(), 1);
+    family.register_ref(("peer", "3")).unwrap().fetch_add(5);
+    assert_eq!(deltas(&mut snapshot), [("3", 5), ("2", 0)]);
+
+    snapshot.clear();
+    assert!(snapshot.is_empty());
+    assert_eq!(deltas(&mut snapshot), [("3", 5), ("2", 4)]);
+}
+
+#[test]
+fn alerts() {
+    use crate::alert::{Alert, AlertEvent, Condition};
+    use std::sync::Mutex;
+
+    static EVENTS: Mutex<Vec<(String, f64, bool)>> = Mutex::new(Vec::new());
+    fn record(event: &AlertEvent<'_>) {
+        assert_eq!(event.name(), "low_battery");
+        assert_eq!(event.family(), "test_gauge");
+        EVENTS
+            .lock()
+            .unwrap()
+            .push((event.labels().to_string(), event.value(), event.is_firing()));
+    }
+    let take_events = || std::mem::take(&mut *EVENTS.lock().unwrap());
+
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<IsizeGauge, _, 4>()
+    };
+    let alert: Alert<'_, 2> =
+        Alert::new("low_battery", Condition::Below(20.0), record).with_hysteresis(5.0);
+
+    // unrecorded metrics are not checked.
+    let battery1 = family.register(("battery", "1")).unwrap();
+    alert.check(&family);
+    assert!(take_events().is_empty());
+
+    battery1.set_value(19);
+    alert.check(&family);
+    assert_eq!(take_events(), [("battery=\"1\"".to_string(), 19.0, true)]);
+    alert.check(&family);
+    assert!(take_events().is_empty());
+
+    battery1.set_value(24);
+    let battery2 = family.register(("battery", "2")).unwrap();
+    battery2.set_value(10);
+    alert.check(&family);
+    assert_eq!(take_events(), [("battery=\"2\"".to_string(), 10.0, true)]);
+    assert!(alert.is_firing());
+
+    battery1.set_value(25);
+    battery2.set_value(30);
+    // the alert only tracks two series.
+    family.register(("battery", "3")).unwrap().set_value(0);
+    alert.check(&family);
+    assert_eq!(
+        take_events(),
+        [
+            ("battery=\"1\"".to_string(), 25.0, false),
+            ("battery=\"2\"".to_string(), 30.0, false),
+        ]
+    );
+    assert!(!alert.is_firing());
+
+    // once a series is removed, its state is released for another series.
+    assert!(family.unregister(&("battery", "1")));
+    alert.check(&family);
+    assert!(take_events().is_empty());
+    alert.check(&family);
+    assert_eq!(take_events(), [("battery=\"3\"".to_string(), 0.0, true)]);
+}
+
+#[test]
+fn alert_reused_slot() {
+    use crate::alert::{Alert, AlertEvent, Condition};
+    use std::sync::Mutex;
+
+    static EVENTS: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+    fn record(event: &AlertEvent<'_>) {
+        EVENTS
+            .lock()
+            .unwrap()
+            .push((event.labels().to_string(), event.is_firing()));
+    }
+    let take_events = || std::mem::take(&mut *EVENTS.lock().unwrap());
+
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<IsizeGauge, _, 1>()
+    };
+    let alert: Alert<'_, 1> = Alert::new("low_battery", Condition::Below(20.0), record);
+
+    let battery1 = family.register_ref(("battery", "1")).unwrap();
+    battery1.set_value(10);
+    let addr = &*battery1 as *const IsizeGauge;
+    drop(battery1);
+    alert.check(&family);
+    assert_eq!(take_events(), [("battery=\"1\"".to_string(), true)]);
+
+    // a new series reuses the removed series' metric before the alert is
+    // checked again, and doesn't inherit its state.
+    assert!(family.unregister(&("battery", "1")));
+    let battery2 = family.register_ref(("battery", "2")).unwrap();
+    assert_eq!(&*battery2 as *const IsizeGauge, addr);
+    battery2.set_value(50);
+    drop(battery2);
+    alert.check(&family);
+    assert!(take_events().is_empty());
+    assert!(!alert.is_firing());
+}
+
+#[test]
+fn rate_alerts() {
+    use crate::alert::{Alert, AlertEvent, Condition};
+    use portable_atomic::AtomicIsize;
+
+    static FIRING: AtomicIsize = AtomicIsize::new(0);
+    fn record(event: &AlertEvent<'_>) {
+        assert_eq!(event.family(), "");
+        let delta = if event.is_firing() { 1 } else { -1 };
+        FIRING.fetch_add(delta, Ordering::Relaxed);
+    }
+    static ALERT: Alert<'static> = Alert::new("request_spike", Condition::RisesBy(10.0), record);
+
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 1>()
+    };
+    let requests = family.register(&[]).unwrap();
+
+    // the first check only records the metric's value.
+    requests.fetch_add(100);
+    ALERT.check_metric(requests);
+    assert_eq!(FIRING.load(Ordering::Relaxed), 0);
+
+    requests.fetch_add(10);
+    ALERT.check_metric(requests);
+    assert!(!ALERT.is_firing());
+
+    requests.fetch_add(11);
+    ALERT.check_metric(requests);
+    assert!(ALERT.is_firing());
+    assert_eq!(FIRING.load(Ordering::Relaxed), 1);
+
+    ALERT.check_metric(requests);
+    assert!(!ALERT.is_firing());
+    assert_eq!(FIRING.load(Ordering::Relaxed), 0);
+
+    requests.fetch_add(20);
+    ALERT.check_metric(requests);
+    assert!(ALERT.is_firing());
+    ALERT.reset();
+    assert!(!ALERT.is_firing());
+}
+
 #[test]
 fn counter_min() {
     let family = {
@@ -588,3 +923,1756 @@ fn counter_serializes() {
         serde_json::from_str::<serde_json::Value>(&json).expect("metrics must deserialize");
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn gauge_add_sub() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Gauge, 2>()
+    };
+    let metric = family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register");
+    assert!(!metric.has_been_recorded());
+
+    // an unrecorded gauge starts at 0, rather than NaN.
+    metric.inc();
+    assert!(metric.has_been_recorded());
+    assert_eq!(metric.value(), 1.0);
+
+    metric.add(4.5);
+    assert_eq!(metric.value(), 5.5);
+
+    metric.sub(2.5);
+    assert_eq!(metric.value(), 3.0);
+
+    metric.dec();
+    metric.dec();
+    metric.dec();
+    metric.dec();
+    assert_eq!(metric.value(), -1.0);
+}
+
+#[test]
+fn gauge_fetch_max_min() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Gauge, 2>()
+    };
+    let max = family
+        .register(&[("metric", "max")])
+        .expect("metric 1 must register");
+    assert!(max.fetch_max(-5.0).is_nan());
+    assert_eq!(max.value(), -5.0);
+    assert_eq!(max.fetch_max(10.0), -5.0);
+    assert_eq!(max.fetch_max(3.0), 10.0);
+    assert_eq!(max.value(), 10.0);
+
+    let min = family
+        .register(&[("metric", "min")])
+        .expect("metric 2 must register");
+    assert!(min.fetch_min(5.0).is_nan());
+    assert_eq!(min.value(), 5.0);
+    assert_eq!(min.fetch_min(10.0), 5.0);
+    assert_eq!(min.fetch_min(-3.0), 5.0);
+    assert_eq!(min.value(), -3.0);
+}
+
+#[test]
+fn int_gauge_add_sub() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<IntGauge, 2>()
+    };
+    let metric = family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register");
+    assert!(!metric.has_been_recorded());
+
+    metric.inc();
+    assert_eq!(metric.value(), 1);
+    metric.add(4);
+    assert_eq!(metric.value(), 5);
+    metric.sub(3);
+    assert_eq!(metric.value(), 2);
+
+    // decrementing past zero saturates.
+    metric.dec();
+    metric.dec();
+    metric.dec();
+    assert_eq!(metric.value(), 0);
+    // even though the value is 0, it has been recorded.
+    assert!(metric.has_been_recorded());
+    assert_str_eq!(
+        family.to_string(),
+        "# TYPE test_gauge gauge\n# UNIT test_gauge \n# HELP test_gauge \ntest_gauge{metric=\"1\"} 0\n\n"
+    );
+}
+
+#[test]
+fn int_gauge_fetch_max_min() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<IntGauge, 2>()
+    };
+    let max = family
+        .register(&[("metric", "max")])
+        .expect("metric 1 must register");
+    assert_eq!(max.fetch_max(5), 0);
+    assert_eq!(max.fetch_max(3), 5);
+    assert_eq!(max.value(), 5);
+
+    let min = family
+        .register(&[("metric", "min")])
+        .expect("metric 2 must register");
+    // an unrecorded gauge's initial 0 doesn't count as the minimum.
+    min.fetch_min(5);
+    assert_eq!(min.value(), 5);
+    assert_eq!(min.fetch_min(10), 5);
+    assert_eq!(min.fetch_min(2), 5);
+    assert_eq!(min.value(), 2);
+    // once the gauge has been recorded, 0 is a real value.
+    min.sub(2);
+    assert_eq!(min.fetch_min(7), 0);
+    assert_eq!(min.value(), 0);
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn gauge_add_timestamped() {
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    let family = MetricBuilder::new("test_gauge")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .build::<Gauge, 1>();
+    let metric = family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register");
+    metric.set_value(1.0);
+    // relative updates in the same second are not discarded.
+    metric.inc();
+    metric.inc();
+    assert_eq!(metric.value(), 3.0);
+
+    NOW.store(200, Ordering::SeqCst);
+    metric.dec();
+
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge \n\
+    test_gauge{metric=\"1\"} 2 200\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn gauge_set_after_add_timestamped() {
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    let family = MetricBuilder::new("test_gauge")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .build::<Gauge, 1>();
+    let metric = family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register");
+    // absolute updates after relative updates in the same second are not
+    // discarded either.
+    metric.inc();
+    metric.inc();
+    metric.set_value(0.0);
+    assert_eq!(metric.value(), 0.0);
+    metric.set_value(5.0);
+    metric.fetch_max(4.0);
+    assert_eq!(metric.value(), 5.0);
+
+    let family = MetricBuilder::new("test_int_gauge")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .build::<IntGauge, 1>();
+    let metric = family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register");
+    metric.inc();
+    metric.inc();
+    metric.set_value(0);
+    assert_eq!(metric.value(), 0);
+
+    NOW.store(200, Ordering::SeqCst);
+    metric.set_value(7);
+    metric.dec();
+
+    let expected = "\
+    # TYPE test_int_gauge gauge\n\
+    # UNIT test_int_gauge \n\
+    # HELP test_int_gauge \n\
+    test_int_gauge{metric=\"1\"} 6 200\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+fn isize_gauge() {
+    let family = {
+        let builder = MetricBuilder::new("test_temperature")
+            .with_help("a test gauge")
+            .with_unit("celsius");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<IsizeGauge, 3>()
+    };
+    let metric1 = family
+        .register(&[("sensor", "1")])
+        .expect("metric 1 must register");
+    metric1.set_value(-10);
+
+    let metric2 = family
+        .register(&[("sensor", "2")])
+        .expect("metric 2 must register");
+    metric2.set_value(22);
+
+    // register an unrecorded metric to ensure that it doesn't get included in
+    // aggregates.
+    let metric3 = family
+        .register(&[("sensor", "3")])
+        .expect("metric 3 must register");
+
+    let expected = "\
+    # TYPE test_temperature gauge\n\
+    # UNIT test_temperature celsius\n\
+    # HELP test_temperature a test gauge\n\
+    test_temperature{sensor=\"1\"} -10\n\
+    test_temperature{sensor=\"2\"} 22\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    assert_eq!(family.min_value(), Some(-10));
+    assert_eq!(family.max_value(), Some(22));
+    assert_eq!(family.mean(), Some(6));
+
+    // an unrecorded signed gauge's initial 0 doesn't win either comparison.
+    metric3.fetch_max(-20);
+    assert_eq!(metric3.value(), -20);
+    metric3.fetch_min(-5);
+    assert_eq!(metric3.value(), -20);
+    metric3.add(-4);
+    assert_eq!(metric3.value(), -24);
+    assert_eq!(family.min_value(), Some(-24));
+
+    // once the gauge has been recorded, 0 is a real value.
+    metric3.add(24);
+    assert_eq!(metric3.fetch_max(-3), 0);
+    assert_eq!(metric3.value(), 0);
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn isize_gauge_set_after_add_timestamped() {
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    let family = MetricBuilder::new("test_temperature")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .build::<IsizeGauge, 1>();
+    let metric = family
+        .register(&[("sensor", "1")])
+        .expect("metric 1 must register");
+    metric.dec();
+    metric.dec();
+    metric.set_value(3);
+    assert_eq!(metric.value(), 3);
+
+    NOW.store(200, Ordering::SeqCst);
+    metric.sub(5);
+
+    let expected = "\
+    # TYPE test_temperature gauge\n\
+    # UNIT test_temperature \n\
+    # HELP test_temperature \n\
+    test_temperature{sensor=\"1\"} -2 200\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn isize_gauge_serializes() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge")
+            .with_help("a test gauge")
+            .with_unit("tests");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<IsizeGauge, _, 3>()
+    };
+
+    let metric1 = family
+        .register(SerdeLabels("1"))
+        .expect("metric 1 must register");
+    metric1.set_value(-10);
+
+    let _metric2 = family
+        .register(SerdeLabels("2"))
+        .expect("metric 2 must register");
+
+    let expected = serde_json::json!({
+        "1": -10,
+        "2": Option::<isize>::None,
+    });
+    let json =
+        dbg!(serde_json::to_string_pretty(&family.metrics())).expect("metrics must serialize");
+    let actual =
+        serde_json::from_str::<serde_json::Value>(&json).expect("metrics must deserialize");
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn fixed_gauge() {
+    let family = {
+        let builder = MetricBuilder::new("test_voltage")
+            .with_help("a test gauge")
+            .with_unit("volts");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<FixedGauge<3>, 5>()
+    };
+    let rails: [(&[(&str, &str)], isize); 5] = [
+        (&[("rail", "a")], 1234),
+        (&[("rail", "b")], -5),
+        (&[("rail", "c")], 3300),
+        (&[("rail", "d")], 0),
+        (&[("rail", "e")], -12050),
+    ];
+    for (labels, millivolts) in rails {
+        family
+            .register(labels)
+            .expect("metric must register")
+            .set_value(millivolts);
+    }
+
+    let expected = "\
+    # TYPE test_voltage gauge\n\
+    # UNIT test_voltage volts\n\
+    # HELP test_voltage a test gauge\n\
+    test_voltage{rail=\"a\"} 1.234\n\
+    test_voltage{rail=\"b\"} -0.005\n\
+    test_voltage{rail=\"c\"} 3.300\n\
+    test_voltage{rail=\"d\"} 0.000\n\
+    test_voltage{rail=\"e\"} -12.050\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+fn fixed_gauge_extremes() {
+    let family = {
+        let builder = MetricBuilder::new("test_fixed");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<FixedGauge<0>, 1>()
+    };
+    let metric = family.register(&[]).expect("metric must register");
+    metric.set_value(-42);
+    assert_str_eq!(
+        family.to_string(),
+        "# TYPE test_fixed gauge\n# UNIT test_fixed \n# HELP test_fixed \ntest_fixed -42\n\n"
+    );
+
+    let family = {
+        let builder = MetricBuilder::new("test_fixed");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<FixedGauge<2>, 1>()
+    };
+    let metric = family.register(&[]).expect("metric must register");
+    metric.set_value(isize::MIN);
+    let expected = format!(
+        "# TYPE test_fixed gauge\n# UNIT test_fixed \n# HELP test_fixed \ntest_fixed -{}.{:02}\n\n",
+        isize::MIN.unsigned_abs() / 100,
+        isize::MIN.unsigned_abs() % 100,
+    );
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn fixed_gauge_set_after_add_timestamped() {
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    let family = MetricBuilder::new("test_fixed")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .build::<FixedGauge<1>, 1>();
+    let metric = family.register(&[]).expect("metric must register");
+    metric.add(15);
+    metric.set_value(-5);
+    metric.set_value(25);
+    assert_eq!(metric.value(), 25);
+
+    NOW.store(200, Ordering::SeqCst);
+    metric.sub(10);
+
+    assert_str_eq!(
+        family.to_string(),
+        "# TYPE test_fixed gauge\n# UNIT test_fixed \n# HELP test_fixed \ntest_fixed 1.5 200\n\n"
+    );
+}
+
+#[test]
+fn timer_records_duration() {
+    use crate::timer::RecordDuration;
+    use core::time::Duration;
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW_MS: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> Duration {
+        Duration::from_millis(NOW_MS.load(Ordering::SeqCst))
+    }
+
+    let family = {
+        let builder = MetricBuilder::new("test_time").with_unit("seconds");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<FixedGauge<3>, 3>()
+    };
+
+    let fixed = family
+        .register(&[("timer", "dropped")])
+        .expect("metric must register");
+    {
+        let _timer = fixed.start_timer_with(clock);
+        NOW_MS.fetch_add(1500, Ordering::SeqCst);
+    }
+    assert_eq!(fixed.value(), 1500);
+
+    let stopped = family
+        .register(&[("timer", "stopped")])
+        .expect("metric must register");
+    let timer = stopped.start_timer_with(clock);
+    NOW_MS.fetch_add(25, Ordering::SeqCst);
+    assert_eq!(timer.stop(), Duration::from_millis(25));
+    assert_eq!(stopped.value(), 25);
+
+    let canceled = family
+        .register(&[("timer", "canceled")])
+        .expect("metric must register");
+    let timer = canceled.start_timer_with(clock);
+    NOW_MS.fetch_add(25, Ordering::SeqCst);
+    timer.cancel();
+    assert!(!canceled.has_been_recorded());
+
+    let expected = "\
+    # TYPE test_time gauge\n\
+    # UNIT test_time seconds\n\
+    # HELP test_time \n\
+    test_time{timer=\"dropped\"} 1.500\n\
+    test_time{timer=\"stopped\"} 0.025\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn timer_records_duration_timestamped() {
+    use crate::timer::RecordDuration;
+    use core::time::Duration;
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW_MS: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> Duration {
+        Duration::from_millis(NOW_MS.load(Ordering::SeqCst))
+    }
+    // every timer below finishes in the same second.
+    fn now() -> crate::UnixTimestamp {
+        crate::UnixTimestamp::from_secs(100)
+    }
+    fn time(metric: &impl RecordDuration, millis: u64) {
+        let _timer = metric.start_timer_with(clock);
+        NOW_MS.fetch_add(millis, Ordering::SeqCst);
+    }
+
+    let gauges = MetricBuilder::new("test_time")
+        .with_timestamp(now)
+        .build::<Gauge, 1>();
+    let gauge = gauges.register(&[]).expect("metric must register");
+    let fixed_gauges = MetricBuilder::new("test_time")
+        .with_timestamp(now)
+        .build::<FixedGauge<3>, 1>();
+    let fixed_gauge = fixed_gauges.register(&[]).expect("metric must register");
+    for millis in [5, 50, 100] {
+        time(gauge, millis);
+        time(fixed_gauge, millis);
+    }
+    // the last timer's duration is recorded, not the first.
+    assert_eq!(gauge.value(), 0.1);
+    assert_eq!(fixed_gauge.value(), 100);
+
+    let counters = MetricBuilder::new("test_time")
+        .with_timestamp(now)
+        .build::<Counter, 1>();
+    let counter = counters.register(&[]).expect("metric must register");
+    for millis in [1500, 2500, 999] {
+        time(counter, millis);
+    }
+    // fractional seconds are discarded.
+    assert_eq!(counter.value(), 3);
+
+    let histograms = MetricBuilder::new("test_time")
+        .with_timestamp(now)
+        .with_buckets(&[0.01, 0.1])
+        .build::<Histogram<2>, 1>();
+    let histogram = histograms.register(&[]).expect("metric must register");
+    let gauge_histograms = MetricBuilder::new("test_time")
+        .with_timestamp(now)
+        .with_buckets(&[0.01, 0.1])
+        .build::<GaugeHistogram<2>, 1>();
+    let gauge_histogram = gauge_histograms
+        .register(&[])
+        .expect("metric must register");
+    for millis in [5, 50, 100, 250] {
+        time(histogram, millis);
+        time(gauge_histogram, millis);
+    }
+    assert_eq!(histogram.count(), 4);
+    assert_eq!(
+        histogram.buckets().collect::<Vec<_>>(),
+        [(0.01, 1), (0.1, 3), (f64::INFINITY, 4)]
+    );
+    assert_eq!(gauge_histogram.gcount(), 4);
+    assert_eq!(
+        gauge_histogram.buckets().collect::<Vec<_>>(),
+        [(0.01, 1), (0.1, 3), (f64::INFINITY, 4)]
+    );
+}
+
+#[test]
+fn meter() {
+    let family = {
+        let builder = MetricBuilder::new("test_requests")
+            .with_help("a test meter")
+            .with_unit("requests");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Meter, 2>()
+    };
+    let metric1 = family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register");
+    let metric2 = family
+        .register(&[("metric", "2")])
+        .expect("metric 2 must register");
+
+    // 10 events in the first tick interval is 2 events per second.
+    metric1.mark_n(10);
+    family.tick();
+    assert_eq!(metric1.count(), 10);
+    assert_eq!(metric1.one_minute_rate(), 2.0);
+    assert_eq!(metric1.five_minute_rate(), 2.0);
+    assert_eq!(metric1.fifteen_minute_rate(), 2.0);
+
+    let expected = "\
+    # TYPE test_requests gauge\n\
+    # UNIT test_requests requests\n\
+    # HELP test_requests a test meter\n\
+    test_requests{metric=\"1\",window=\"1m\"} 2\n\
+    test_requests{metric=\"1\",window=\"5m\"} 2\n\
+    test_requests{metric=\"1\",window=\"15m\"} 2\n\
+    test_requests{metric=\"2\",window=\"1m\"} 0\n\
+    test_requests{metric=\"2\",window=\"5m\"} 0\n\
+    test_requests{metric=\"2\",window=\"15m\"} 0\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // with no further events, the rates decay, and shorter windows decay
+    // faster.
+    metric2.mark();
+    for _ in 0..12 {
+        family.tick();
+    }
+    assert_eq!(metric1.count(), 10);
+    assert_eq!(metric2.count(), 1);
+    let (m1, m5, m15) = (
+        metric1.one_minute_rate(),
+        metric1.five_minute_rate(),
+        metric1.fifteen_minute_rate(),
+    );
+    assert!(m1 < m5 && m5 < m15 && m15 < 2.0, "{m1} {m5} {m15}");
+    // after one minute, the one-minute rate has decayed by a factor of e.
+    assert!((m1 - 2.0 / core::f64::consts::E).abs() < 1e-9, "{m1}");
+}
+
+#[test]
+fn gauge_histogram() {
+    let family = {
+        let builder = MetricBuilder::new("test_queue_wait")
+            .with_help("a test gauge histogram")
+            .with_unit("seconds")
+            .with_buckets(&[0.5, 1.0, 5.0]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<GaugeHistogram<3>, 2>()
+    };
+    let metric = family
+        .register(&[("queue", "1")])
+        .expect("metric 1 must register");
+    for value in [0.25, 0.75, 1.0, 2.5, 10.0] {
+        metric.observe(value);
+    }
+
+    let expected = "\
+    # TYPE test_queue_wait gaugehistogram\n\
+    # UNIT test_queue_wait seconds\n\
+    # HELP test_queue_wait a test gauge histogram\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"0.5\"} 1\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"1.0\"} 3\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"5.0\"} 4\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"+Inf\"} 5\n\
+    test_queue_wait_gcount{queue=\"1\"} 5\n\
+    test_queue_wait_gsum{queue=\"1\"} 14.5\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // bucket counts go down as values are removed.
+    metric.remove(0.75);
+    metric.remove(10.0);
+
+    let expected = "\
+    # TYPE test_queue_wait gaugehistogram\n\
+    # UNIT test_queue_wait seconds\n\
+    # HELP test_queue_wait a test gauge histogram\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"0.5\"} 1\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"1.0\"} 2\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"5.0\"} 3\n\
+    test_queue_wait_bucket{queue=\"1\",le=\"+Inf\"} 3\n\
+    test_queue_wait_gcount{queue=\"1\"} 3\n\
+    test_queue_wait_gsum{queue=\"1\"} 3.75\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+fn gauge_histogram_remove_unobserved() {
+    let family = {
+        let builder = MetricBuilder::new("test_histogram").with_buckets(&[1.0, 2.0]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<GaugeHistogram<2>, 1>()
+    };
+    let metric = family.register(&[]).expect("metric must register");
+    metric.observe(1.5);
+
+    // removing values which were never observed leaves every bucket, the
+    // count, and the sum unchanged, so that the buckets stay monotonic.
+    metric.remove(0.5);
+    metric.remove(3.0);
+    assert_eq!(
+        metric.buckets().collect::<Vec<_>>(),
+        [(1.0, 0), (2.0, 1), (f64::INFINITY, 1)]
+    );
+    assert_eq!(metric.gsum(), 1.5);
+
+    metric.remove(1.5);
+    metric.remove(1.5);
+    assert_eq!(
+        metric.buckets().collect::<Vec<_>>(),
+        [(1.0, 0), (2.0, 0), (f64::INFINITY, 0)]
+    );
+    assert_eq!(metric.gsum(), 0.0);
+}
+
+#[test]
+fn gauge_histogram_unlabeled() {
+    let family = {
+        let builder = MetricBuilder::new("test_histogram").with_buckets(&[1.0, 2.0]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<GaugeHistogram<2>, 1>()
+    };
+    let metric = family.register(&[]).expect("metric must register");
+    metric.observe(2.5);
+
+    let expected = "\
+    # TYPE test_histogram gaugehistogram\n\
+    # UNIT test_histogram \n\
+    # HELP test_histogram \n\
+    test_histogram_bucket{le=\"1.0\"} 0\n\
+    test_histogram_bucket{le=\"2.0\"} 0\n\
+    test_histogram_bucket{le=\"+Inf\"} 1\n\
+    test_histogram_gcount 1\n\
+    test_histogram_gsum 2.5\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+#[should_panic = "the number of bucket bounds must equal the number of histogram buckets"]
+fn histogram_bucket_count_mismatch() {
+    let _ = MetricBuilder::new("test_histogram")
+        .with_buckets(&[1.0, 2.0, 3.0])
+        .build::<GaugeHistogram<2>, 1>();
+}
+
+#[test]
+#[should_panic = "histogram bucket bounds must be sorted in ascending order"]
+fn histogram_buckets_unsorted() {
+    let _ = MetricBuilder::new("test_histogram").with_buckets(&[1.0, 3.0, 2.0]);
+}
+
+#[test]
+#[should_panic = "histogram bucket bounds must be finite"]
+fn histogram_buckets_infinite() {
+    let _ = MetricBuilder::new("test_histogram").with_buckets(&[1.0, f64::INFINITY]);
+}
+
+#[test]
+#[should_panic = "histogram bucket bounds must be finite"]
+fn histogram_buckets_nan() {
+    let _ = MetricBuilder::new("test_histogram").with_buckets(&[f64::NAN]);
+}
+
+#[test]
+fn histogram() {
+    let family = {
+        let builder = MetricBuilder::new("test_latency")
+            .with_help("a test histogram")
+            .with_unit("seconds")
+            .with_buckets(&[0.5, 1.0]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Histogram<2>, 2>()
+    };
+    let metric = family
+        .register(&[("path", "/")])
+        .expect("metric 1 must register");
+    for value in [0.25, 0.75, 1.0, 2.5] {
+        metric.observe(value);
+    }
+    assert_eq!(metric.count(), 4);
+    assert_eq!(metric.sum(), 4.5);
+
+    let expected = "\
+    # TYPE test_latency histogram\n\
+    # UNIT test_latency seconds\n\
+    # HELP test_latency a test histogram\n\
+    test_latency_bucket{path=\"/\",le=\"0.5\"} 1\n\
+    test_latency_bucket{path=\"/\",le=\"1.0\"} 3\n\
+    test_latency_bucket{path=\"/\",le=\"+Inf\"} 4\n\
+    test_latency_count{path=\"/\"} 4\n\
+    test_latency_sum{path=\"/\"} 4.5\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // the Prometheus format is the same, apart from its metadata.
+    let mut exposition = String::new();
+    family
+        .fmt_metric_as(Format::Prometheus, &mut exposition)
+        .unwrap();
+    assert_str_eq!(
+        exposition,
+        expected.replace(
+            "# TYPE test_latency histogram\n# UNIT test_latency seconds\n# HELP test_latency a test histogram\n",
+            "# HELP test_latency a test histogram\n# TYPE test_latency histogram\n",
+        )
+    );
+}
+
+#[test]
+fn unknown() {
+    let family = {
+        let builder = MetricBuilder::new("test_bridged")
+            .with_help("a test unknown metric")
+            .with_unit("things");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Unknown, 2>()
+    };
+    let metric1 = family
+        .register(&[("chip", "1")])
+        .expect("metric 1 must register");
+    metric1.set_value(-3.5);
+
+    // unrecorded metrics are not exported.
+    let _metric2 = family
+        .register(&[("chip", "2")])
+        .expect("metric 2 must register");
+
+    let expected = "\
+    # TYPE test_bridged unknown\n\
+    # UNIT test_bridged things\n\
+    # HELP test_bridged a test unknown metric\n\
+    test_bridged{chip=\"1\"} -3.5\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // non-finite values are formatted as the OpenMetrics text format requires.
+    let mut values = [f64::INFINITY, f64::NEG_INFINITY, f64::NAN]
+        .into_iter()
+        .map(|value| {
+            metric1.set_value(value);
+            family.to_string()
+        });
+    for expected in ["+Inf", "-Inf", "NaN"] {
+        let exposition = values.next().unwrap();
+        let sample = format!("test_bridged{{chip=\"1\"}} {expected}\n");
+        assert!(exposition.contains(&sample), "{exposition}");
+    }
+}
+
+#[test]
+fn metric_set() {
+    let gauges = {
+        let builder = MetricBuilder::new("test_gauge").with_help("a test gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Gauge, 2>()
+    };
+    let counters = {
+        let builder = MetricBuilder::new("test_counter").with_unit("tests");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 4>()
+    };
+    gauges
+        .register(&[("metric", "1")])
+        .expect("gauge must register")
+        .set_value(1.5);
+    counters
+        .register(&[("metric", "2")])
+        .expect("counter must register")
+        .fetch_add(2);
+
+    let families: [&(dyn FmtMetricFamily + Sync); 2] = [&gauges, &counters];
+    let set = crate::MetricSet::new(&families);
+    assert_eq!(set.iter().count(), 2);
+    assert_eq!(
+        set.get("test_counter").map(FmtMetricFamily::metric_type),
+        Some("counter")
+    );
+    assert!(set.get("test_meter").is_none());
+
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge a test gauge\n\
+    test_gauge{metric=\"1\"} 1.5\n\n\
+    # TYPE test_counter counter\n\
+    # UNIT test_counter tests\n\
+    # HELP test_counter \n\
+    test_counter{metric=\"2\"} 2\n\n\
+    # EOF\n\
+    ";
+    assert_str_eq!(set.to_string(), expected);
+}
+
+#[test]
+#[cfg(feature = "serde-set")]
+fn metric_set_serializes() {
+    let gauges = {
+        let builder = MetricBuilder::new("test_gauge").with_help("a test gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Gauge, _, 2>()
+    };
+    let counters = {
+        let builder = MetricBuilder::new("test_counter").with_unit("tests");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, _, 2>()
+    };
+    gauges
+        .register(SerdeLabels("1"))
+        .expect("gauge must register")
+        .set_value(1.5);
+    counters
+        .register(SerdeLabels("2"))
+        .expect("counter must register")
+        .fetch_add(2);
+
+    let families: [&(dyn crate::SerializeMetricFamily + Sync); 2] = [&gauges, &counters];
+    let set = crate::MetricSet::new(&families);
+
+    let expected = serde_json::json!([
+        {
+            "name": "test_gauge",
+            "type": "gauge",
+            "unit": "",
+            "help": "a test gauge",
+            "metrics": { "1": 1.5 },
+        },
+        {
+            "name": "test_counter",
+            "type": "counter",
+            "unit": "tests",
+            "help": "",
+            "metrics": { "2": 2 },
+        },
+    ]);
+    let json = serde_json::to_string_pretty(&set).expect("metric set must serialize");
+    let actual =
+        serde_json::from_str::<serde_json::Value>(&json).expect("metric set must deserialize");
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn dropped_registrations() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Gauge, 1>()
+    };
+    family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register")
+        .set_value(1.0);
+    assert!(family.register(&[("metric", "2")]).is_none());
+    assert!(family.register(&[("metric", "3")]).is_none());
+    assert_eq!(family.dropped_registrations(), 2);
+    assert!(family.overflow().is_none());
+    assert_eq!(family.metrics().len(), 1);
+    assert_eq!(family.metrics().remaining_capacity(), 0);
+
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge \n\
+    test_gauge{metric=\"1\"} 1\n\n\
+    # TYPE test_gauge_dropped_registrations counter\n\
+    # UNIT test_gauge_dropped_registrations \n\
+    # HELP test_gauge_dropped_registrations registrations which failed because test_gauge was full\n\
+    test_gauge_dropped_registrations_total 2\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+fn overflow() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter").with_overflow();
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 2>()
+    };
+
+    // the dropped registrations counter is exported even if no registrations
+    // have been dropped, when the overflow policy is enabled.
+    let expected = "\
+    # TYPE test_counter counter\n\
+    # UNIT test_counter \n\
+    # HELP test_counter \n\n\
+    # TYPE test_counter_dropped_registrations counter\n\
+    # UNIT test_counter_dropped_registrations \n\
+    # HELP test_counter_dropped_registrations registrations which failed because test_counter was full\n\
+    test_counter_dropped_registrations_total 0\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    let labels: [&[(&str, &str)]; 4] = [
+        &[("metric", "1")],
+        &[("metric", "2")],
+        &[("metric", "3")],
+        &[("metric", "4")],
+    ];
+    for (i, labels) in labels.into_iter().enumerate() {
+        family
+            .register(labels)
+            .expect("overflowing families always register")
+            .fetch_add(i + 1);
+    }
+    assert_eq!(family.dropped_registrations(), 2);
+    assert_eq!(family.overflow().map(Counter::value), Some(7));
+    // subsequent registrations of existing label sets are not dropped.
+    family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must already be registered")
+        .fetch_add(1);
+    assert_eq!(family.dropped_registrations(), 2);
+
+    let expected = "\
+    # TYPE test_counter counter\n\
+    # UNIT test_counter \n\
+    # HELP test_counter \n\
+    test_counter{metric=\"1\"} 2\n\
+    test_counter{metric=\"2\"} 2\n\
+    test_counter{overflow=\"true\"} 7\n\n\
+    # TYPE test_counter_dropped_registrations counter\n\
+    # UNIT test_counter_dropped_registrations \n\
+    # HELP test_counter_dropped_registrations registrations which failed because test_counter was full\n\
+    test_counter_dropped_registrations_total 2\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+fn unregister() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, _, 2>()
+    };
+
+    let conn1 = family
+        .register_ref(("peer", "1"))
+        .expect("metric 1 must register");
+    conn1.fetch_add(1);
+    let conn2 = family
+        .register_ref(("peer", "2"))
+        .expect("metric 2 must register");
+    conn2.fetch_add(2);
+    assert!(family.register_ref(("peer", "3")).is_none());
+
+    // unregistered metrics are no longer exported, but their slots can't be
+    // reused while they're still referenced.
+    assert!(family.unregister(&("peer", "1")));
+    assert!(!family.unregister(&("peer", "1")));
+    assert_eq!(conn1.value(), 1);
+    assert!(family.register_ref(("peer", "3")).is_none());
+
+    let expected = "\
+    # TYPE test_counter counter\n\
+    # UNIT test_counter \n\
+    # HELP test_counter \n\
+    test_counter{peer=\"2\"} 2\n\n\
+    # TYPE test_counter_dropped_registrations counter\n\
+    # UNIT test_counter_dropped_registrations \n\
+    # HELP test_counter_dropped_registrations registrations which failed because test_counter was full\n\
+    test_counter_dropped_registrations_total 2\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // once the last reference is dropped, the slot is reused.
+    drop(conn1);
+    let conn3 = family
+        .register_ref(("peer", "3"))
+        .expect("metric 3 must reuse metric 1's slot");
+    assert_eq!(conn3.value(), 0);
+    conn3.fetch_add(3);
+
+    // re-registering an unregistered label set creates a new metric.
+    assert!(family.unregister(&("peer", "2")));
+    let conn2_again = family.register_ref(("peer", "2"));
+    assert!(conn2_again.is_none(), "metric 2's slot is still referenced");
+    drop(conn2);
+    let conn2_again = family
+        .register_ref(("peer", "2"))
+        .expect("metric 2 must register again");
+    assert_eq!(conn2_again.value(), 0);
+}
+
+#[test]
+fn unregister_pinned() {
+    let family = {
+        let builder = MetricBuilder::new("test_gauge");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Gauge, _, 1>()
+    };
+
+    // `register` returns a plain reference, so the metric's slot is pinned.
+    let metric = family
+        .register(("metric", "1"))
+        .expect("metric 1 must register");
+    metric.set_value(1.0);
+    assert!(family.unregister(&("metric", "1")));
+
+    // the metric is no longer exported, but remains valid.
+    assert!(!family.to_string().contains("test_gauge{"));
+    assert_eq!(metric.value(), 1.0);
+    assert!(family.register_ref(("metric", "2")).is_none());
+}
+
+#[test]
+fn const_labels() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter")
+            .with_const_labels(&[("device_id", "1234"), ("region", "eu")])
+            .with_overflow();
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 1>()
+    };
+
+    family
+        .register(&[("metric", "1")])
+        .expect("metric 1 must register")
+        .fetch_add(1);
+    family
+        .register(&[])
+        .expect("overflowing families always register")
+        .fetch_add(2);
+
+    let expected = "\
+    # TYPE test_counter counter\n\
+    # UNIT test_counter \n\
+    # HELP test_counter \n\
+    test_counter{metric=\"1\",device_id=\"1234\",region=\"eu\"} 1\n\
+    test_counter{overflow=\"true\",device_id=\"1234\",region=\"eu\"} 2\n\n\
+    # TYPE test_counter_dropped_registrations counter\n\
+    # UNIT test_counter_dropped_registrations \n\
+    # HELP test_counter_dropped_registrations registrations which failed because test_counter was full\n\
+    test_counter_dropped_registrations_total{device_id=\"1234\",region=\"eu\"} 1\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // labels added by a `MetricSet` follow the family's own constant labels.
+    let families: [&(dyn FmtMetricFamily + Sync); 1] = [&family];
+    let set = crate::MetricSet::new(&families).with_const_labels(&[("host", "a")]);
+    let exposition = set.to_string();
+    assert!(exposition
+        .contains("test_counter{metric=\"1\",device_id=\"1234\",region=\"eu\",host=\"a\"} 1\n"));
+    assert!(exposition.contains(
+        "test_counter_dropped_registrations_total{device_id=\"1234\",region=\"eu\",host=\"a\"} 1\n"
+    ));
+}
+
+#[test]
+#[cfg(feature = "serde-set")]
+fn const_labels_serialize() {
+    let counters = {
+        let builder = MetricBuilder::new("test_counter").with_const_labels(&[("region", "eu")]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, _, 2>()
+    };
+    counters
+        .register(SerdeLabels("1"))
+        .expect("counter must register")
+        .fetch_add(1);
+
+    let families: [&(dyn crate::SerializeMetricFamily + Sync); 1] = [&counters];
+    let set = crate::MetricSet::new(&families).with_const_labels(&[("device_id", "1234")]);
+
+    let expected = serde_json::json!([
+        {
+            "name": "test_counter",
+            "type": "counter",
+            "unit": "",
+            "help": "",
+            "const_labels": { "region": "eu", "device_id": "1234" },
+            "metrics": { "1": 1 },
+        },
+    ]);
+    let json = serde_json::to_string_pretty(&set).expect("metric set must serialize");
+    let actual =
+        serde_json::from_str::<serde_json::Value>(&json).expect("metric set must deserialize");
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn matchers() {
+    /// Writes its label in several chunks, with escaping.
+    struct Chunked(&'static str);
+
+    impl FmtLabels for Chunked {
+        fn fmt_labels(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+            writer.write_str("po")?;
+            writer.write_str("rt=\"eth0\",")?;
+            fmt_label(writer, "path", &self.0)
+        }
+    }
+
+    let labels = [("port", "eth0"), ("direction", "rx")];
+    assert!(Matcher::equal("port", "eth0").matches(&labels));
+    assert!(!Matcher::equal("port", "eth").matches(&labels));
+    assert!(!Matcher::equal("port", "eth00").matches(&labels));
+    assert!(!Matcher::equal("por", "eth0").matches(&labels));
+    assert!(Matcher::not_equal("port", "eth1").matches(&labels));
+    assert!(!Matcher::not_equal("direction", "rx").matches(&labels));
+    assert!(Matcher::prefix("port", "eth").matches(&labels));
+    assert!(Matcher::prefix("port", "").matches(&labels));
+    assert!(!Matcher::prefix("port", "eth01").matches(&labels));
+
+    // absent labels match the empty string.
+    assert!(Matcher::equal("vlan", "").matches(&labels));
+    assert!(!Matcher::equal("vlan", "1").matches(&labels));
+    assert!(Matcher::not_equal("vlan", "1").matches(&labels));
+    assert!(Matcher::equal("vlan", "").matches(&()));
+
+    let chunked = Chunked("C:\\\"x\"");
+    assert!(Matcher::equal("port", "eth0").matches(&chunked));
+    assert!(Matcher::equal("path", "C:\\\"x\"").matches(&chunked));
+    assert!(Matcher::prefix("path", "C:\\").matches(&chunked));
+
+    assert!(Matcher::matches_all(&[], &labels));
+    assert!(!Matcher::matches_all(
+        &[
+            Matcher::equal("port", "eth0"),
+            Matcher::equal("direction", "tx")
+        ],
+        &labels
+    ));
+}
+
+#[test]
+fn parse_matchers() {
+    for (input, expected) in [
+        ("port=\"eth0\"", Matcher::equal("port", "eth0")),
+        ("port != \"eth0\"", Matcher::not_equal("port", "eth0")),
+        ("port=~\"eth.*\"", Matcher::prefix("port", "eth")),
+        ("port=\"\"", Matcher::equal("port", "")),
+    ] {
+        assert_eq!(Matcher::parse(input), Ok(expected), "{input}");
+        assert_eq!(Matcher::parse(&expected.to_string()), Ok(expected));
+    }
+
+    for input in [
+        "port",
+        "=\"eth0\"",
+        "port=eth0",
+        "port=\"eth\\\"0\"",
+        "port=~\"eth\"",
+        "port=~\"e.h.*\"",
+    ] {
+        assert!(Matcher::parse(input).is_err(), "{input}");
+    }
+}
+
+#[test]
+fn fmt_matching() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter").with_const_labels(&[("region", "eu")]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 4>()
+    };
+    let labels: [&[(&str, &str)]; 3] = [
+        &[("port", "eth0")],
+        &[("port", "eth1")],
+        &[("port", "wlan0")],
+    ];
+    for (i, labels) in labels.into_iter().enumerate() {
+        family
+            .register(labels)
+            .expect("metric must register")
+            .fetch_add(i + 1);
+    }
+
+    let mut exposition = String::new();
+    family
+        .fmt_metric_matching(&[Matcher::prefix("port", "eth")], &mut exposition)
+        .unwrap();
+    let expected = "\
+    # TYPE test_counter counter\n\
+    # UNIT test_counter \n\
+    # HELP test_counter \n\
+    test_counter{port=\"eth0\",region=\"eu\"} 1\n\
+    test_counter{port=\"eth1\",region=\"eu\"} 2\n\n\
+    ";
+    assert_str_eq!(exposition, expected);
+
+    // constant labels are matched, too.
+    let matchers = [Matcher::equal("region", "us")];
+    assert_eq!(family.iter_matching(&matchers).count(), 0);
+    let matchers = [
+        Matcher::equal("region", "eu"),
+        Matcher::not_equal("port", "eth0"),
+    ];
+    let matched = family
+        .iter_matching(&matchers)
+        .map(|entry| entry.value().value())
+        .collect::<Vec<_>>();
+    assert_eq!(matched, [2, 3]);
+
+    let families: [&(dyn FmtMetricFamily + Sync); 1] = [&family];
+    let set = crate::MetricSet::new(&families).with_const_labels(&[("host", "a")]);
+    let mut exposition = String::new();
+    set.fmt_metrics_matching(&[Matcher::equal("port", "wlan0")], &mut exposition)
+        .unwrap();
+    assert!(exposition.contains("test_counter{port=\"wlan0\",region=\"eu\",host=\"a\"} 3\n"));
+    assert!(!exposition.contains("eth"));
+}
+
+#[test]
+fn group_by() {
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 8>()
+    };
+    const LONG: &str = "a-label-value-which-is-longer-than-one-chunk-0";
+    const LONG2: &str = "a-label-value-which-is-longer-than-one-chunk-1";
+    let labels: [&[(&str, &str)]; 7] = [
+        &[("interface", "eth0"), ("direction", "rx")],
+        &[("interface", "eth1"), ("direction", "rx")],
+        &[("interface", "eth0"), ("direction", "tx")],
+        &[("direction", "tx")],
+        &[("interface", LONG), ("direction", "rx")],
+        &[("interface", LONG2), ("direction", "rx")],
+        &[("direction", "rx"), ("interface", LONG)],
+    ];
+    for (i, labels) in labels.into_iter().enumerate() {
+        family
+            .register(labels)
+            .expect("metric must register")
+            .fetch_add(1 << i);
+    }
+
+    let groups = family
+        .group_by("interface")
+        .map(|group| {
+            assert_eq!(group.key(), "interface");
+            (
+                group.value().to_string(),
+                group.total(),
+                group.series().count(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        groups,
+        [
+            ("eth0".to_string(), 0b101, 2),
+            ("eth1".to_string(), 0b10, 1),
+            (String::new(), 0b1000, 1),
+            (LONG.to_string(), 0b1010000, 2),
+            (LONG2.to_string(), 0b100000, 1),
+        ]
+    );
+
+    let group = family.group_by("direction").next().unwrap();
+    assert_eq!(group.min_value(), Some(1));
+    assert_eq!(group.max_value(), Some(64));
+    assert_eq!(group.mean(), Some(115 / 5));
+    let mut formatted = String::new();
+    group.fmt_labels(&mut formatted).unwrap();
+    assert_eq!(formatted, "direction=\"rx\"");
+}
+
+#[test]
+fn merge() {
+    fn family<M: Metric, L: FmtLabels + PartialEq>(
+        help: &'static str,
+    ) -> MetricFamily<'static, M, 4, L> {
+        let builder = MetricBuilder::new("test_merge").with_help(help);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled()
+    }
+    let device1 = family::<Counter, _>("packets");
+    let device2 = family::<Counter, _>("packets");
+    device1.register(("port", "eth0")).unwrap().fetch_add(1);
+    device1.register(("port", "eth1")).unwrap().fetch_add(2);
+    device2.register(("port", "eth0")).unwrap().fetch_add(3);
+
+    let merged = family::<Counter, _>("packets");
+    assert_eq!(merged.merge_instance(("instance", "1"), &device1), Ok(2));
+    assert_eq!(merged.merge_instance(("instance", "2"), &device2), Ok(1));
+    assert_str_eq!(
+        merged.to_string(),
+        "# TYPE test_merge counter\n\
+        # UNIT test_merge \n\
+        # HELP test_merge packets\n\
+        test_merge{instance=\"1\",port=\"eth0\"} 1\n\
+        test_merge{instance=\"1\",port=\"eth1\"} 2\n\
+        test_merge{instance=\"2\",port=\"eth0\"} 3\n\n"
+    );
+
+    // merging the same sources again replaces their values, rather than
+    // counting them twice.
+    device1.register(("port", "eth0")).unwrap().fetch_add(4);
+    assert_eq!(merged.merge_instance(("instance", "1"), &device1), Ok(2));
+    assert_eq!(merged.merge_instance(("instance", "2"), &device2), Ok(1));
+    let eth0 = Instanced::new("instance", "1", ("port", "eth0"));
+    assert_eq!(merged.register(eth0).unwrap().value(), 5);
+
+    // counters which map to the same label set in a single merge are summed.
+    let totals = family::<Counter, _>("packets");
+    assert_eq!(totals.merge_from(&device1, |_, _| ()), Ok(2));
+    assert_eq!(totals.register(()).unwrap().value(), 7);
+    assert_eq!(totals.merge_from(&device2, |_, _| ()), Ok(1));
+    assert_eq!(totals.register(()).unwrap().value(), 3);
+
+    // the source's constant labels are added after the instance label.
+    let device3 = {
+        let builder = MetricBuilder::new("test_merge")
+            .with_help("packets")
+            .with_const_labels(&[("region", "eu")]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_labeled::<Counter, _, 4>()
+    };
+    device3.register(("port", "eth0")).unwrap().fetch_add(6);
+    let regional = family::<Counter, _>("packets");
+    assert_eq!(regional.merge_instance(("instance", "3"), &device3), Ok(1));
+    assert!(regional
+        .to_string()
+        .contains("test_merge{instance=\"3\",region=\"eu\",port=\"eth0\"} 6\n"));
+
+    // gauges keep the last value merged, and unrecorded series are skipped.
+    let temp1 = family::<Gauge, _>("temperature");
+    let temp2 = family::<Gauge, _>("temperature");
+    temp1.register(("sensor", "a")).unwrap().set_value(20.5);
+    temp2.register(("sensor", "a")).unwrap().set_value(22.0);
+    temp2.register(("sensor", "b"));
+    let latest = family::<Gauge, _>("temperature");
+    assert_eq!(latest.merge_from(&temp1, |_, &labels| labels), Ok(1));
+    assert_eq!(latest.merge_from(&temp2, |_, &labels| labels), Ok(1));
+    assert_eq!(latest.register(("sensor", "a")).unwrap().value(), 22.0);
+    assert!(latest.metrics().get(&("sensor", "b")).is_none());
+
+    // families with different metadata can't be merged.
+    let other = family::<Gauge, (&str, &str)>("humidity");
+    let err = latest.merge_from(&other, |_, &labels| labels).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot merge metric families with different help texts"
+    );
+}
+
+#[test]
+fn prometheus_format() {
+    let requests = {
+        let builder = MetricBuilder::new("test_requests")
+            .with_help("a test counter")
+            .with_unit("requests")
+            .with_overflow();
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 1>()
+    };
+    let unknown = {
+        let builder = MetricBuilder::new("test_unknown");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Unknown, 1>()
+    };
+    let histogram = {
+        let builder = MetricBuilder::new("test_queue_wait").with_buckets(&[1.0]);
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<GaugeHistogram<1>, 1>()
+    };
+    requests.register(&[("path", "/")]).unwrap().fetch_add(1);
+    requests
+        .register(&[("path", "/health")])
+        .unwrap()
+        .fetch_add(2);
+    unknown.register(&[]).unwrap().set_value(1.5);
+    histogram.register(&[]).unwrap().observe(0.5);
+
+    let families: [&(dyn FmtMetricFamily + Sync); 3] = [&requests, &unknown, &histogram];
+    let set = crate::MetricSet::new(&families).with_format(Format::Prometheus);
+    let expected = "\
+    # HELP test_requests_total a test counter\n\
+    # TYPE test_requests_total counter\n\
+    test_requests_total{path=\"/\"} 1\n\
+    test_requests_total{overflow=\"true\"} 2\n\n\
+    # HELP test_requests_dropped_registrations_total registrations which failed \
+    because test_requests was full\n\
+    # TYPE test_requests_dropped_registrations_total counter\n\
+    test_requests_dropped_registrations_total 1\n\n\
+    # HELP test_unknown \n\
+    # TYPE test_unknown untyped\n\
+    test_unknown 1.5\n\n\
+    # HELP test_queue_wait \n\
+    # TYPE test_queue_wait untyped\n\
+    test_queue_wait_bucket{le=\"1.0\"} 1\n\
+    test_queue_wait_bucket{le=\"+Inf\"} 1\n\
+    test_queue_wait_gcount 1\n\
+    test_queue_wait_gsum 0.5\n\n\
+    ";
+    assert_str_eq!(set.to_string(), expected);
+
+    // the OpenMetrics format is unchanged.
+    assert!(set
+        .with_format(Format::OpenMetrics)
+        .to_string()
+        .ends_with("test_queue_wait_gsum 0.5\n\n# EOF\n"));
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn prometheus_timestamps() {
+    let family = MetricBuilder::new("test_counter_total")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(100))
+        .build::<Counter, 1>();
+    family.register(&[]).unwrap().fetch_add(3);
+
+    let mut exposition = String::new();
+    family
+        .fmt_metric_as(Format::Prometheus, &mut exposition)
+        .unwrap();
+    // counters whose names already end in `_total` are not renamed.
+    assert_str_eq!(
+        exposition,
+        "# HELP test_counter_total \n\
+        # TYPE test_counter_total counter\n\
+        test_counter_total 3 100000\n\n"
+    );
+}
+
+#[test]
+fn negotiate_format() {
+    let cases = [
+        ("", Format::Prometheus),
+        ("*/*", Format::Prometheus),
+        ("application/openmetrics-text", Format::OpenMetrics),
+        ("application/*", Format::OpenMetrics),
+        (
+            "Application/OpenMetrics-Text; Version=\"1.0.0\"",
+            Format::OpenMetrics,
+        ),
+        ("text/plain;version=0.0.4", Format::Prometheus),
+        (
+            "text/*;q=0.9, application/openmetrics-text",
+            Format::OpenMetrics,
+        ),
+        // unsupported versions are not acceptable.
+        (
+            "application/openmetrics-text;version=2.0.0",
+            Format::Prometheus,
+        ),
+        (
+            "text/plain;version=1.0.0, application/*;q=0.1",
+            Format::OpenMetrics,
+        ),
+        // the most specific matching range determines a format's quality.
+        ("text/plain;q=0, */*", Format::OpenMetrics),
+        (
+            "application/openmetrics-text;q=0.2, */*;q=0.5",
+            Format::Prometheus,
+        ),
+        // invalid quality values are not acceptable.
+        (
+            "application/openmetrics-text;q=2, text/plain;q=0.1",
+            Format::Prometheus,
+        ),
+        (
+            "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;\
+            encoding=delimited;q=0.7,application/openmetrics-text;version=1.0.0;q=0.5,\
+            text/plain;version=0.0.4;q=0.3",
+            Format::OpenMetrics,
+        ),
+    ];
+    for (accept, expected) in cases {
+        assert_eq!(Format::negotiate(accept), expected, "Accept: {accept}");
+    }
+
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build::<Counter, 1>()
+    };
+    family.register(&[]).unwrap().fetch_add(1);
+    let families: [&(dyn FmtMetricFamily + Sync); 1] = [&family];
+    let set = crate::MetricSet::new(&families).negotiate("text/plain");
+    assert_eq!(set.format(), Format::Prometheus);
+    assert_eq!(
+        set.content_type(),
+        "text/plain; version=0.0.4; charset=utf-8"
+    );
+    assert_str_eq!(
+        set.to_string(),
+        "# HELP test_counter_total \n\
+        # TYPE test_counter_total counter\n\
+        test_counter_total 1\n\n"
+    );
+}
+
+#[test]
+fn hashed_storage() {
+    use crate::registry::HashRegistryMap;
+
+    type Labels = (&'static str, &'static str);
+
+    let family = {
+        let builder = MetricBuilder::new("test_counter");
+        #[cfg(feature = "timestamp")]
+        let builder = builder.without_timestamps();
+        builder.build_with_storage::<Counter, Labels, HashRegistryMap<Labels, Counter, 4>, 4>()
+    };
+
+    for (i, peer) in ["1", "2", "3", "4"].into_iter().enumerate() {
+        family
+            .register_ref(("peer", peer))
+            .expect("metric must register")
+            .fetch_add(i + 1);
+    }
+    assert!(family.register_ref(("peer", "5")).is_none());
+    assert_eq!(family.register_ref(("peer", "3")).unwrap().value(), 3);
+    assert_eq!(family.total(), 10);
+
+    // removed entries' slots are reused.
+    assert!(family.unregister(&("peer", "2")));
+    family
+        .register_ref(("peer", "5"))
+        .expect("metric 5 must reuse metric 2's slot")
+        .fetch_add(5);
+    assert_eq!(family.register_ref(("peer", "4")).unwrap().value(), 4);
+
+    let exposition = family.to_string();
+    assert!(exposition.contains("test_counter{peer=\"5\"} 5\n"));
+    assert!(!exposition.contains("test_counter{peer=\"2\"}"));
+}
+
+#[test]
+fn hashed_storage_uses_even_slots() {
+    use crate::registry::HashRegistryMap;
+
+    let map = HashRegistryMap::<usize, usize, 256>::new();
+    for key in 0..64 {
+        map.get_or_register(key, key).expect("map has capacity");
+    }
+    // entries should be spread evenly over the slots, rather than clustered
+    // in the odd ones.
+    let even = (0..64)
+        .filter(|key| map.slot_index(key).expect("key must be stored") % 2 == 0)
+        .count();
+    assert!(even >= 24, "only {even} of 64 entries are in even slots");
+}
+
+#[test]
+#[should_panic = "the capacity of a metric family's storage must equal its number of metrics"]
+fn hashed_storage_capacity_mismatch() {
+    use crate::registry::HashRegistryMap;
+
+    type Labels = (&'static str, &'static str);
+
+    let _ = MetricBuilder::new("test_counter")
+        .build_with_storage::<Counter, Labels, HashRegistryMap<Labels, Counter, 8>, 4>();
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn expiry() {
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    let family = MetricBuilder::new("test_gauge")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .with_expiry(core::time::Duration::from_secs(30))
+        .build_labeled::<Gauge, _, 2>();
+
+    let peer1 = family
+        .register_ref(("peer", "1"))
+        .expect("metric 1 must register");
+    peer1.set_value(1.0);
+    drop(peer1);
+
+    NOW.store(120, Ordering::SeqCst);
+    let peer2 = family
+        .register_ref(("peer", "2"))
+        .expect("metric 2 must register");
+    peer2.set_value(2.0);
+
+    // neither metric has expired yet.
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge \n\
+    test_gauge{peer=\"1\"} 1 100\n\
+    test_gauge{peer=\"2\"} 2 120\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // metric 1 expires, so it is no longer exported.
+    NOW.store(131, Ordering::SeqCst);
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge \n\
+    test_gauge{peer=\"2\"} 2 120\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+
+    // registering a new label set while the family is full reclaims metric
+    // 1's slot.
+    let peer3 = family
+        .register_ref(("peer", "3"))
+        .expect("metric 3 must reuse metric 1's slot");
+    peer3.set_value(3.0);
+    assert_eq!(family.dropped_registrations(), 0);
+
+    // metric 2 is still referenced, so its slot can't be reclaimed until the
+    // reference is dropped, even once it expires.
+    NOW.store(200, Ordering::SeqCst);
+    assert!(family.register_ref(("peer", "4")).is_none());
+    drop(peer2);
+    drop(peer3);
+    let peer4 = family
+        .register_ref(("peer", "4"))
+        .expect("metric 4 must reuse an expired slot");
+    peer4.set_value(4.0);
+
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge \n\
+    test_gauge{peer=\"4\"} 4 200\n\n\
+    # TYPE test_gauge_dropped_registrations counter\n\
+    # UNIT test_gauge_dropped_registrations \n\
+    # HELP test_gauge_dropped_registrations registrations which failed because test_gauge was full\n\
+    test_gauge_dropped_registrations_total 1\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn expiry_pinned() {
+    use portable_atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(100);
+
+    let family = MetricBuilder::new("test_gauge")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(NOW.load(Ordering::SeqCst)))
+        .with_expiry(core::time::Duration::from_secs(30))
+        .build_labeled::<Gauge, _, 1>();
+
+    let peer1 = family
+        .register(("peer", "1"))
+        .expect("metric 1 must register");
+    peer1.set_value(1.0);
+
+    // metric 1 expires, but it was pinned by `register`, so its slot is not
+    // reclaimed.
+    NOW.store(200, Ordering::SeqCst);
+    family.remove_expired();
+    assert!(family.register_ref(("peer", "2")).is_none());
+    assert_eq!(family.dropped_registrations(), 1);
+
+    // updating metric 1 through its reference exports it again.
+    peer1.set_value(2.0);
+    let expected = "\
+    # TYPE test_gauge gauge\n\
+    # UNIT test_gauge \n\
+    # HELP test_gauge \n\
+    test_gauge{peer=\"1\"} 2 200\n\n\
+    # TYPE test_gauge_dropped_registrations counter\n\
+    # UNIT test_gauge_dropped_registrations \n\
+    # HELP test_gauge_dropped_registrations registrations which failed because test_gauge was full\n\
+    test_gauge_dropped_registrations_total 1\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
+
+#[test]
+fn global_register_concurrent() {
+    static FAMILY: CounterFamily<'static, 1> = MetricBuilder::new("test_global_racy").build();
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(|| crate::global::register(&FAMILY).is_ok()))
+        .collect();
+    for thread in threads {
+        assert!(thread.join().unwrap());
+    }
+
+    let mut exposition = String::new();
+    crate::global::fmt_metrics(&mut exposition).unwrap();
+    assert_eq!(
+        exposition
+            .matches("# TYPE test_global_racy counter\n")
+            .count(),
+        1
+    );
+}
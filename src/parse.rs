@@ -0,0 +1,712 @@
+//! A parser for the [OpenMetrics text format].
+//!
+//! This parser is intended both for testing the output of
+//! [`MetricFamily::fmt_metric`](crate::MetricFamily::fmt_metric), and for
+//! ingesting metrics exposed by other processes (such as child processes or
+//! peripheral devices), so that they may be re-exposed. Like the rest of this
+//! crate, it does not allocate: metric names, label sets, and help text are
+//! borrowed from the input.
+//!
+//! Input is parsed one line at a time, either as individual [`Line`]s, using
+//! a [`Parser`], or grouped into metric [`Family`]s, using [`Families`]. The
+//! parser also accepts the Prometheus text format, which differs from the
+//! OpenMetrics text format mostly in which lines are optional: blank lines and
+//! comments are skipped, and the `# EOF` line is not required.
+//!
+//! # Examples
+//!
+//! ```
+//! use tinymetrics::{parse::Families, GaugeFamily, MetricBuilder};
+//!
+//! static TEMPERATURE: GaugeFamily<'static, 2> = MetricBuilder::new("temperature")
+//!     .with_unit("celsius")
+//!     .with_help("the temperature of each sensor")
+//!     .build();
+//!
+//! TEMPERATURE.register(&[("sensor", "cpu")]).unwrap().set_value(45.5);
+//!
+//! let exposition = TEMPERATURE.to_string();
+//! let mut families = Families::new(&exposition);
+//! let family = families.next().unwrap().unwrap();
+//! assert_eq!(family.name(), "temperature");
+//! assert_eq!(family.metric_type(), "gauge");
+//! assert_eq!(family.unit(), "celsius");
+//! assert_eq!(family.help(), "the temperature of each sensor");
+//!
+//! let sample = family.samples().next().unwrap();
+//! assert_eq!(sample.labels().get("sensor").unwrap(), "cpu");
+//! assert_eq!(sample.value(), 45.5);
+//! assert!(families.next().is_none());
+//! ```
+//!
+//! [OpenMetrics text format]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#text-format
+use crate::FmtLabels;
+use core::fmt;
+
+#[cfg(test)]
+mod tests;
+
+/// An iterator over the [`Line`]s of a text exposition.
+///
+/// Blank lines and comments other than metadata are skipped. If a line cannot
+/// be parsed, an error is returned for that line, and parsing resumes at the
+/// next line.
+#[derive(Clone, Debug)]
+pub struct Parser<'a> {
+    input: &'a str,
+    rest: &'a str,
+    line: usize,
+    eof: bool,
+}
+
+/// A line of a text exposition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line<'a> {
+    /// A `# TYPE` line, declaring the type of a metric family.
+    Type {
+        family: &'a str,
+        metric_type: &'a str,
+    },
+    /// A `# UNIT` line, declaring the unit of a metric family.
+    Unit { family: &'a str, unit: &'a str },
+    /// A `# HELP` line, declaring the help text of a metric family.
+    Help { family: &'a str, help: Escaped<'a> },
+    /// A sample.
+    Sample(Sample<'a>),
+    /// The `# EOF` line which ends an OpenMetrics exposition.
+    Eof,
+}
+
+/// A single sample, such as `requests_total{path="/"} 42`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample<'a> {
+    name: &'a str,
+    labels: Labels<'a>,
+    value: f64,
+    timestamp: Option<f64>,
+}
+
+/// The label set of a parsed [`Sample`].
+///
+/// A `Labels` implements [`FmtLabels`], formatting the label set exactly as it
+/// was written in the input, so that a sample's labels may be used as the
+/// label set of a series in a [`MetricFamily`](crate::MetricFamily).
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Labels<'a>(&'a str);
+
+/// An iterator over the labels in a [`Labels`].
+#[derive(Clone, Debug)]
+pub struct LabelsIter<'a>(&'a str);
+
+/// A label value or help text, borrowed from the input with its escape
+/// sequences intact.
+///
+/// An `Escaped` may be compared to a `str`, and its [`Display`](fmt::Display)
+/// implementation writes the unescaped text.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Escaped<'a>(&'a str);
+
+/// An iterator over the metric [`Family`]s in a text exposition.
+///
+/// Metadata lines and samples are grouped into families by name. A sample
+/// belongs to the current family if its name is the family's name, optionally
+/// followed by one of the suffixes OpenMetrics defines for samples (such as
+/// `_total` or `_bucket`). Samples which are not preceded by any metadata are
+/// grouped into a family of type `unknown`.
+#[derive(Clone, Debug)]
+pub struct Families<'a> {
+    parser: Parser<'a>,
+    /// A line which was read while parsing the previous family, but belongs
+    /// to the next one, and its byte range in the input.
+    pending: Option<(usize, usize, Line<'a>)>,
+    /// The family which was being parsed when an error was returned, and the
+    /// byte range of its samples so far, so that parsing it resumes after the
+    /// error.
+    partial: Option<(Family<'a>, Option<(usize, usize)>)>,
+}
+
+/// A metric family parsed by [`Families`].
+#[derive(Copy, Clone)]
+pub struct Family<'a> {
+    name: &'a str,
+    metric_type: &'a str,
+    unit: &'a str,
+    help: Escaped<'a>,
+    /// The lines containing this family's samples.
+    samples: &'a str,
+}
+
+/// An iterator over the [`Sample`]s in a [`Family`].
+#[derive(Clone, Debug)]
+pub struct Samples<'a>(Parser<'a>);
+
+/// An error returned when a line of a text exposition cannot be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    msg: &'static str,
+}
+
+/// The suffixes which may follow a metric family's name in the names of its
+/// samples.
+const SUFFIXES: &[&str] = &[
+    "_total", "_created", "_count", "_sum", "_bucket", "_gcount", "_gsum", "_info",
+];
+
+fn is_metric_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    matches!(bytes.next(), Some(b'a'..=b'z' | b'A'..=b'Z' | b'_' | b':'))
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b':')
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    matches!(bytes.next(), Some(b'a'..=b'z' | b'A'..=b'Z' | b'_'))
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Splits the first `name="value"` label from `s`, returning the label's name
+/// and value and the remainder of `s`.
+fn split_label(s: &str) -> Result<(&str, Escaped<'_>, &str), &'static str> {
+    let (name, rest) = s.split_once('=').ok_or("expected `=` after label name")?;
+    if !is_label_name(name) {
+        return Err("invalid label name");
+    }
+    let rest = rest.strip_prefix('"').ok_or("label value must be quoted")?;
+    let mut escaped = false;
+    for (i, byte) in rest.bytes().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'"' => return Ok((name, Escaped(&rest[..i]), &rest[i + 1..])),
+            _ => {}
+        }
+    }
+    Err("unterminated label value")
+}
+
+/// Returns `true` if `c` separates the fields of a sample.
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Splits the first field from `s`, skipping any spaces or tabs before it.
+fn split_field(s: &str) -> (&str, &str) {
+    let s = s.trim_start_matches(is_blank);
+    s.split_once(is_blank).unwrap_or((s, ""))
+}
+
+/// Parses a sample value or timestamp.
+///
+/// Infinities and NaN must be written as `+Inf`, `-Inf`, or `NaN`, rather
+/// than in any of the other forms `f64`'s `FromStr` implementation accepts
+/// (such as `inf` or `infinity`).
+fn parse_float(s: &str, msg: &'static str) -> Result<f64, &'static str> {
+    match s {
+        "+Inf" => Ok(f64::INFINITY),
+        "-Inf" => Ok(f64::NEG_INFINITY),
+        "NaN" => Ok(f64::NAN),
+        _ if s
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'+' | b'-' | b'e' | b'E')) =>
+        {
+            s.parse().map_err(|_| msg)
+        }
+        _ => Err(msg),
+    }
+}
+
+// === impl Parser ===
+
+impl<'a> Parser<'a> {
+    /// Returns a new parser over `input`.
+    #[must_use]
+    pub const fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            rest: input,
+            line: 0,
+            eof: false,
+        }
+    }
+
+    /// Returns the byte offset of the next line in the input.
+    fn offset(&self) -> usize {
+        self.input.len() - self.rest.len()
+    }
+
+    fn error(&self, msg: &'static str) -> ParseError {
+        ParseError {
+            line: self.line,
+            msg,
+        }
+    }
+
+    fn parse_line(line: &'a str) -> Result<Option<Line<'a>>, &'static str> {
+        let comment = match line.strip_prefix('#') {
+            Some(comment) => comment,
+            None => return Sample::parse(line).map(|sample| Some(Line::Sample(sample))),
+        };
+        let comment = match comment.strip_prefix(' ') {
+            Some(comment) => comment,
+            None => return Ok(None),
+        };
+        if comment == "EOF" {
+            return Ok(Some(Line::Eof));
+        }
+
+        let (keyword, rest) = comment.split_once(' ').unwrap_or((comment, ""));
+        if !matches!(keyword, "TYPE" | "UNIT" | "HELP") {
+            return Ok(None);
+        }
+        let (family, value) = rest.split_once(' ').unwrap_or((rest, ""));
+        if !is_metric_name(family) {
+            return Err("invalid metric family name");
+        }
+        let line = match keyword {
+            "TYPE" => {
+                if value.is_empty() || !value.bytes().all(|b| b.is_ascii_lowercase()) {
+                    return Err("invalid metric type");
+                }
+                Line::Type {
+                    family,
+                    metric_type: value,
+                }
+            }
+            "UNIT" => Line::Unit {
+                family,
+                unit: value,
+            },
+            _ => Line::Help {
+                family,
+                help: Escaped(value),
+            },
+        };
+        Ok(Some(line))
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<Line<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.rest.is_empty() {
+            let (line, rest) = self.rest.split_once('\n').unwrap_or((self.rest, ""));
+            self.rest = rest;
+            self.line += 1;
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            if self.eof {
+                self.rest = "";
+                return Some(Err(self.error("unexpected content after `# EOF`")));
+            }
+
+            match Self::parse_line(line) {
+                Ok(Some(line)) => {
+                    self.eof = line == Line::Eof;
+                    return Some(Ok(line));
+                }
+                Ok(None) => continue,
+                Err(msg) => return Some(Err(self.error(msg))),
+            }
+        }
+        None
+    }
+}
+
+// === impl Sample ===
+
+impl<'a> Sample<'a> {
+    fn parse(line: &'a str) -> Result<Self, &'static str> {
+        let name_end = line
+            .find(['{', ' ', '\t'])
+            .ok_or("expected a sample value")?;
+        let (name, mut rest) = line.split_at(name_end);
+        if !is_metric_name(name) {
+            return Err("invalid metric name");
+        }
+
+        let mut labels = Labels("");
+        if let Some(mut remaining) = rest.strip_prefix('{') {
+            let start = remaining;
+            loop {
+                if let Some(after) = remaining.strip_prefix('}') {
+                    let raw = &start[..start.len() - remaining.len()];
+                    labels = Labels(raw.strip_suffix(',').unwrap_or(raw));
+                    rest = after;
+                    break;
+                }
+                let (_, _, after) = split_label(remaining)?;
+                remaining = match after.strip_prefix(',') {
+                    Some(after) => after,
+                    None if after.starts_with('}') => after,
+                    None => return Err("expected `,` or `}` after label"),
+                };
+            }
+        }
+
+        // fields are separated by a single space in the OpenMetrics format,
+        // but the Prometheus format allows runs of spaces and tabs.
+        if !rest.starts_with(is_blank) {
+            return Err("expected a sample value");
+        }
+        let (value, rest) = split_field(rest);
+        if value.is_empty() {
+            return Err("expected a sample value");
+        }
+        let value = parse_float(value, "invalid sample value")?;
+
+        // the timestamp may be followed by an exemplar, which is ignored.
+        let timestamp = match split_field(rest) {
+            ("", _) | ("#", _) => None,
+            (timestamp, rest) => {
+                let rest = rest.trim_start_matches(is_blank);
+                if !(rest.is_empty() || rest.starts_with('#')) {
+                    return Err("unexpected content after sample");
+                }
+                Some(parse_float(timestamp, "invalid timestamp")?)
+            }
+        };
+
+        Ok(Self {
+            name,
+            labels,
+            value,
+            timestamp,
+        })
+    }
+
+    /// Returns the name of this sample, including any suffix (such as
+    /// `_total` or `_bucket`).
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns this sample's label set.
+    #[must_use]
+    pub fn labels(&self) -> Labels<'a> {
+        self.labels
+    }
+
+    /// Returns this sample's value.
+    #[must_use]
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Returns this sample's timestamp, if it has one, exactly as it was
+    /// written in the input.
+    ///
+    /// Timestamps are in seconds since the Unix epoch in the OpenMetrics
+    /// format, but in milliseconds since the Unix epoch in the Prometheus
+    /// format. Since the parser accepts both formats without knowing which
+    /// one it is parsing, the timestamp is not converted: a timestamp parsed
+    /// from a Prometheus exposition must be divided by 1000 to get seconds.
+    #[must_use]
+    pub fn timestamp(&self) -> Option<f64> {
+        self.timestamp
+    }
+}
+
+// === impl Labels ===
+
+impl<'a> Labels<'a> {
+    /// Returns the value of the label named `name`, if this label set
+    /// contains it.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Escaped<'a>> {
+        self.iter()
+            .find(|&(label, _)| label == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the name and value of each label in this
+    /// label set.
+    #[must_use]
+    pub fn iter(&self) -> LabelsIter<'a> {
+        LabelsIter(self.0)
+    }
+
+    /// Returns the number of labels in this label set.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if this label set contains no labels.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the label set as it was written in the input, without the
+    /// surrounding braces.
+    #[must_use]
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl FmtLabels for Labels<'_> {
+    fn fmt_labels(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        writer.write_str(self.0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for Labels<'a> {
+    type Item = (&'a str, Escaped<'a>);
+    type IntoIter = LabelsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Debug for Labels<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> Iterator for LabelsIter<'a> {
+    type Item = (&'a str, Escaped<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the label set was validated when it was parsed.
+        let (name, value, rest) = split_label(self.0).ok()?;
+        self.0 = rest.strip_prefix(',').unwrap_or(rest);
+        Some((name, value))
+    }
+}
+
+// === impl Escaped ===
+
+impl<'a> Escaped<'a> {
+    /// Returns the text as it was written in the input, including escape
+    /// sequences.
+    #[must_use]
+    pub fn raw(&self) -> &'a str {
+        self.0
+    }
+
+    /// Returns an iterator over the unescaped characters of the text.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        let mut chars = self.0.chars();
+        core::iter::from_fn(move || match chars.next()? {
+            '\\' => match chars.next() {
+                Some('n') => Some('\n'),
+                Some(c) => Some(c),
+                None => Some('\\'),
+            },
+            c => Some(c),
+        })
+    }
+}
+
+impl PartialEq<str> for Escaped<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.chars().eq(other.chars())
+    }
+}
+
+impl PartialEq<&'_ str> for Escaped<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+        self.chars().try_for_each(|c| f.write_char(c))
+    }
+}
+
+impl fmt::Debug for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self.0)
+    }
+}
+
+// === impl Families ===
+
+impl<'a> Families<'a> {
+    /// Returns an iterator over the metric families in `input`.
+    #[must_use]
+    pub const fn new(input: &'a str) -> Self {
+        Self {
+            parser: Parser::new(input),
+            pending: None,
+            partial: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Families<'a> {
+    type Item = Result<Family<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut family, mut samples) = match self.partial.take() {
+            Some((family, samples)) => (Some(family), samples),
+            None => (None, None),
+        };
+        loop {
+            let (start, end, line) = match self.pending.take() {
+                Some(pending) => pending,
+                None => {
+                    let start = self.parser.offset();
+                    match self.parser.next() {
+                        Some(Ok(line)) => (start, self.parser.offset(), line),
+                        Some(Err(error)) => {
+                            // the rest of the current family is parsed by the
+                            // next call.
+                            self.partial = family.map(|family| (family, samples));
+                            return Some(Err(error));
+                        }
+                        None => break,
+                    }
+                }
+            };
+
+            let name = match line {
+                Line::Eof => break,
+                Line::Type { family, .. }
+                | Line::Unit { family, .. }
+                | Line::Help { family, .. } => family,
+                Line::Sample(ref sample) => match family {
+                    Some(ref family) if family.contains(sample.name) => {
+                        samples = Some((samples.map_or(start, |(start, _)| start), end));
+                        continue;
+                    }
+                    Some(_) => sample.name,
+                    None => {
+                        family = Some(Family::new(sample.name));
+                        samples = Some((start, end));
+                        continue;
+                    }
+                },
+            };
+
+            let current = family.get_or_insert_with(|| Family::new(name));
+            if current.name != name {
+                self.pending = Some((start, end, line));
+                break;
+            }
+            match line {
+                Line::Type { metric_type, .. } => current.metric_type = metric_type,
+                Line::Unit { unit, .. } => current.unit = unit,
+                Line::Help { help, .. } => current.help = help,
+                Line::Sample(_) | Line::Eof => unreachable!(),
+            }
+        }
+
+        let mut family = family?;
+        if let Some((start, end)) = samples {
+            family.samples = &self.parser.input[start..end];
+        }
+        Some(Ok(family))
+    }
+}
+
+// === impl Family ===
+
+impl<'a> Family<'a> {
+    fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            metric_type: "unknown",
+            unit: "",
+            help: Escaped(""),
+            samples: "",
+        }
+    }
+
+    fn contains(&self, sample: &str) -> bool {
+        sample == self.name
+            || sample
+                .strip_prefix(self.name)
+                .map_or(false, |suffix| SUFFIXES.contains(&suffix))
+    }
+
+    /// Returns the name of this metric family.
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the type of this metric family, such as `counter`, or
+    /// `unknown` if its type was not declared.
+    #[must_use]
+    pub fn metric_type(&self) -> &'a str {
+        self.metric_type
+    }
+
+    /// Returns the unit of this metric family, or an empty string if its unit
+    /// was not declared.
+    #[must_use]
+    pub fn unit(&self) -> &'a str {
+        self.unit
+    }
+
+    /// Returns the help text of this metric family, or an empty string if its
+    /// help text was not declared.
+    #[must_use]
+    pub fn help(&self) -> Escaped<'a> {
+        self.help
+    }
+
+    /// Returns an iterator over the samples in this metric family.
+    #[must_use]
+    pub fn samples(&self) -> Samples<'a> {
+        Samples(Parser::new(self.samples))
+    }
+}
+
+impl fmt::Debug for Family<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Family")
+            .field("name", &self.name)
+            .field("metric_type", &self.metric_type)
+            .field("unit", &self.unit)
+            .field("help", &self.help)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Iterator for Samples<'a> {
+    type Item = Sample<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a family's samples were validated when the family was parsed.
+        self.0.find_map(|line| match line {
+            Ok(Line::Sample(sample)) => Some(sample),
+            _ => None,
+        })
+    }
+}
+
+// === impl ParseError ===
+
+impl ParseError {
+    /// Returns the (1-based) number of the line which could not be parsed.
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
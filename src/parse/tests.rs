@@ -0,0 +1,361 @@
+use super::*;
+use crate::{
+    Counter, Gauge, GaugeHistogram, Instanced, Metric, MetricBuilder, MetricFamily, MetricSet,
+};
+use proptest::prelude::*;
+
+type LabelSlice<'a> = &'a [(&'a str, &'a str)];
+
+fn builder<'a>(name: &'a str, unit: &'a str, help: &'a str) -> MetricBuilder<'a> {
+    let builder = MetricBuilder::new(name).with_unit(unit).with_help(help);
+    #[cfg(feature = "timestamp")]
+    let builder = builder.without_timestamps();
+    builder
+}
+
+/// Parses `exposition`, asserting that it contains exactly one family, with
+/// the metadata of `family`.
+fn parse_one<'a, M: Metric, L, const METRICS: usize>(
+    exposition: &'a str,
+    family: &MetricFamily<'_, M, METRICS, L>,
+) -> Family<'a> {
+    let mut families = Families::new(exposition);
+    let parsed = families
+        .next()
+        .expect("exposition must contain a family")
+        .expect("exposition must parse");
+    assert_eq!(parsed.name(), family.name());
+    assert_eq!(parsed.metric_type(), M::TYPE);
+    assert_eq!(parsed.unit(), family.unit());
+    assert_eq!(parsed.help(), family.help());
+    assert!(families.next().is_none());
+    parsed
+}
+
+fn assert_labels<'a>(parsed: Labels<'_>, expected: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    let mut parsed = parsed.iter();
+    for (name, value) in expected {
+        let (parsed_name, parsed_value) = parsed.next().expect("missing label");
+        assert_eq!(parsed_name, name);
+        assert_eq!(parsed_value, value, "value of label {name}");
+    }
+    assert!(parsed.next().is_none(), "unexpected label");
+}
+
+fn metric_name() -> impl Strategy<Value = String> {
+    "[a-zA-Z_:][a-zA-Z0-9_:]{0,15}"
+}
+
+/// Generates the label sets of up to 4 series, which are made distinct by
+/// their first label.
+fn series_labels() -> impl Strategy<Value = Vec<Vec<(String, String)>>> {
+    // label values in label slices are not escaped, so they may not contain
+    // characters which must be escaped.
+    let label = ("[a-zA-Z_][a-zA-Z0-9_]{0,8}", "[^\"\\\\\n]{0,12}");
+    prop::collection::vec(prop::collection::vec(label, 0..4), 1..=4).prop_map(|mut series| {
+        for (i, labels) in series.iter_mut().enumerate() {
+            labels.insert(0, ("series".to_string(), i.to_string()));
+        }
+        series
+    })
+}
+
+fn as_slices(series: &[Vec<(String, String)>]) -> Vec<Vec<(&str, &str)>> {
+    series
+        .iter()
+        .map(|labels| {
+            labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect()
+        })
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn round_trip_gauges(
+        name in metric_name(),
+        unit in "[a-z_]{0,8}",
+        help in "[a-zA-Z0-9 ,.]{0,20}",
+        instance in any::<String>(),
+        series in series_labels(),
+        values in prop::collection::vec(any::<f64>(), 4),
+    ) {
+        let series = as_slices(&series);
+        let family: MetricFamily<'_, Gauge, 4, Instanced<'_, LabelSlice<'_>>> =
+            builder(&name, &unit, &help).build_labeled();
+        for (labels, &value) in series.iter().zip(&values) {
+            family
+                .register(Instanced::new("instance", &instance, &labels[..]))
+                .unwrap()
+                .set_value(value);
+        }
+
+        let exposition = family.to_string();
+        let parsed = parse_one(&exposition, &family);
+        let mut samples = parsed.samples();
+        for (labels, &value) in series.iter().zip(&values) {
+            let sample = samples.next().expect("missing sample");
+            prop_assert_eq!(sample.name(), name.as_str());
+            assert_labels(
+                sample.labels(),
+                Some(("instance", instance.as_str())).into_iter().chain(labels.iter().copied()),
+            );
+            prop_assert!(
+                sample.value().to_bits() == value.to_bits()
+                    || (sample.value().is_nan() && value.is_nan()),
+                "expected {}, got {}", value, sample.value(),
+            );
+            prop_assert_eq!(sample.timestamp(), None);
+        }
+        prop_assert!(samples.next().is_none());
+    }
+
+    #[test]
+    fn round_trip_counters(
+        name in metric_name(),
+        unit in "[a-z_]{0,8}",
+        help in "[a-zA-Z0-9 ,.]{0,20}",
+        series in series_labels(),
+        values in prop::collection::vec(any::<usize>(), 4),
+    ) {
+        let series = as_slices(&series);
+        let family: MetricFamily<'_, Counter, 4> = builder(&name, &unit, &help).build();
+        for (labels, &value) in series.iter().zip(&values) {
+            family.register(&labels[..]).unwrap().fetch_add(value);
+        }
+
+        let exposition = family.to_string();
+        let parsed = parse_one(&exposition, &family);
+        let mut samples = parsed.samples();
+        for (labels, &value) in series.iter().zip(&values) {
+            let sample = samples.next().expect("missing sample");
+            prop_assert_eq!(sample.name(), name.as_str());
+            assert_labels(sample.labels(), labels.iter().copied());
+            prop_assert_eq!(sample.value(), value as f64);
+        }
+        prop_assert!(samples.next().is_none());
+    }
+
+    #[test]
+    fn round_trip_labels(series in series_labels()) {
+        // a parsed label set formats exactly as it was written.
+        for labels in as_slices(&series) {
+            let mut formatted = String::new();
+            (&labels[..]).fmt_labels(&mut formatted).unwrap();
+            let line = format!("test{{{formatted}}} 1");
+            let sample = Sample::parse(&line).unwrap();
+            let mut reformatted = String::new();
+            sample.labels().fmt_labels(&mut reformatted).unwrap();
+            prop_assert_eq!(reformatted, formatted);
+        }
+    }
+}
+
+#[test]
+fn gauge_histogram() {
+    let family = builder("test_queue_wait", "seconds", "a test gauge histogram")
+        .with_buckets(&[0.5, 1.0])
+        .build::<GaugeHistogram<2>, 1>();
+    let metric = family.register(&[("queue", "1")]).unwrap();
+    for value in [0.25, 0.75, 2.5] {
+        metric.observe(value);
+    }
+
+    let exposition = family.to_string();
+    let parsed = parse_one(&exposition, &family);
+    let samples = parsed
+        .samples()
+        .map(|sample| {
+            let le = sample.labels().get("le").map(|le| le.to_string());
+            (sample.name(), le, sample.value())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        samples,
+        [
+            ("test_queue_wait_bucket", Some("0.5".to_string()), 1.0),
+            ("test_queue_wait_bucket", Some("1.0".to_string()), 2.0),
+            ("test_queue_wait_bucket", Some("+Inf".to_string()), 3.0),
+            ("test_queue_wait_gcount", None, 3.0),
+            ("test_queue_wait_gsum", None, 3.5),
+        ]
+    );
+}
+
+#[test]
+fn metric_set() {
+    let requests = builder("requests", "", "")
+        .with_overflow()
+        .build::<Counter, 1>();
+    let temperature = builder("temperature", "celsius", "").build::<Gauge, 1>();
+    requests.register(&[("path", "/")]).unwrap().fetch_add(1);
+    requests
+        .register(&[("path", "/health")])
+        .unwrap()
+        .fetch_add(2);
+    temperature.register(&[]).unwrap().set_value(21.5);
+
+    let families: [&dyn crate::FmtMetricFamily; 2] = [&requests, &temperature];
+    let exposition = MetricSet::new(&families).to_string();
+    let parsed = Families::new(&exposition)
+        .map(|family| {
+            let family = family.unwrap();
+            let samples = family
+                .samples()
+                .map(|sample| (sample.labels().as_str(), sample.value()))
+                .collect::<Vec<_>>();
+            (family.name(), family.metric_type(), samples)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        parsed,
+        [
+            (
+                "requests",
+                "counter",
+                vec![("path=\"/\"", 1.0), ("overflow=\"true\"", 2.0)]
+            ),
+            ("requests_dropped_registrations", "counter", vec![("", 1.0)]),
+            ("temperature", "gauge", vec![("", 21.5)]),
+        ]
+    );
+}
+
+#[test]
+fn prometheus_format() {
+    let input = "\
+        # HELP http_requests_total The total number of \\\"requests\\\".\n\
+        # TYPE http_requests_total counter\n\
+        http_requests_total{method=\"post\",code=\"200\",} 1027 1395066363000\n\
+        http_requests_total{method=\"post\",code=\"400\"}    3\t1395066363000\n\
+        \n\
+        # a comment\n\
+        metric_without_metadata 12.47 # {trace_id=\"abc\"} 1\n\
+        # EOF\n";
+
+    let mut families = Families::new(input);
+    let requests = families.next().unwrap().unwrap();
+    assert_eq!(requests.name(), "http_requests_total");
+    assert_eq!(requests.metric_type(), "counter");
+    assert_eq!(requests.help(), "The total number of \"requests\".");
+    assert_eq!(
+        requests.help().raw(),
+        "The total number of \\\"requests\\\"."
+    );
+    let samples = requests.samples().collect::<Vec<_>>();
+    assert_eq!(samples.len(), 2);
+    assert_labels(samples[0].labels(), [("method", "post"), ("code", "200")]);
+    assert_eq!(samples[0].labels().as_str(), "method=\"post\",code=\"200\"");
+    assert_eq!(samples[0].value(), 1027.0);
+    assert_eq!(samples[0].timestamp(), Some(1395066363000.0));
+    assert_eq!(samples[1].labels().get("code").unwrap(), "400");
+    assert_eq!(samples[1].value(), 3.0);
+    assert_eq!(samples[1].timestamp(), Some(1395066363000.0));
+
+    let unknown = families.next().unwrap().unwrap();
+    assert_eq!(unknown.name(), "metric_without_metadata");
+    assert_eq!(unknown.metric_type(), "unknown");
+    let sample = unknown.samples().next().unwrap();
+    assert!(sample.labels().is_empty());
+    assert_eq!(sample.value(), 12.47);
+    assert_eq!(sample.timestamp(), None);
+    assert!(families.next().is_none());
+}
+
+#[test]
+#[cfg(feature = "timestamp")]
+fn timestamps() {
+    let family = MetricBuilder::new("test_timestamps")
+        .with_timestamp(|| crate::UnixTimestamp::from_secs(1234))
+        .build::<Gauge, 1>();
+    family.register(&[]).unwrap().set_value(1.5);
+
+    let exposition = family.to_string();
+    let parsed = parse_one(&exposition, &family);
+    let sample = parsed.samples().next().unwrap();
+    assert_eq!(sample.value(), 1.5);
+    assert_eq!(sample.timestamp(), Some(1234.0));
+}
+
+#[test]
+fn errors() {
+    let error = |line: &str| match Parser::new(line).next() {
+        Some(Err(error)) => error.msg,
+        other => panic!("expected an error for {line:?}, got {other:?}"),
+    };
+    assert_eq!(error("1metric 1"), "invalid metric name");
+    assert_eq!(error("metric"), "expected a sample value");
+    assert_eq!(error("metric one"), "invalid sample value");
+    assert_eq!(error("metric 1 2 3"), "unexpected content after sample");
+    assert_eq!(error("metric 1 \t 2  3"), "unexpected content after sample");
+    assert_eq!(error("metric 1 two"), "invalid timestamp");
+    for value in ["inf", "+inf", "Inf", "infinity", "nan", "NAN", "0x1"] {
+        assert_eq!(error(&format!("metric {value}")), "invalid sample value");
+    }
+    assert_eq!(error("metric{a=\"b} 1"), "unterminated label value");
+    assert_eq!(error("metric{a=b} 1"), "label value must be quoted");
+    assert_eq!(
+        error("metric{a=\"b\" c=\"d\"} 1"),
+        "expected `,` or `}` after label"
+    );
+    assert_eq!(error("metric{1a=\"b\"} 1"), "invalid label name");
+    assert_eq!(error("# TYPE metric Gauge"), "invalid metric type");
+    assert_eq!(error("# HELP 1metric help"), "invalid metric family name");
+
+    let mut parser = Parser::new("# EOF\nmetric 1");
+    assert_eq!(parser.next(), Some(Ok(Line::Eof)));
+    assert_eq!(
+        parser.next().unwrap().unwrap_err().msg,
+        "unexpected content after `# EOF`"
+    );
+
+    let mut parser = Parser::new("metric 1\nmetric two\nmetric 3");
+    assert!(matches!(parser.next(), Some(Ok(Line::Sample(_)))));
+    let error = parser.next().unwrap().unwrap_err();
+    assert_eq!(error.line(), 2);
+    assert_eq!(error.to_string(), "line 2: invalid sample value");
+    // parsing resumes after an error.
+    assert!(matches!(parser.next(), Some(Ok(Line::Sample(_)))));
+    assert!(parser.next().is_none());
+}
+
+#[test]
+fn special_values() {
+    let value = |line: &str| match Parser::new(line).next() {
+        Some(Ok(Line::Sample(sample))) => sample.value(),
+        other => panic!("expected a sample for {line:?}, got {other:?}"),
+    };
+    assert_eq!(value("metric +Inf"), f64::INFINITY);
+    assert_eq!(value("metric -Inf"), f64::NEG_INFINITY);
+    assert!(value("metric NaN").is_nan());
+    assert_eq!(value("metric -1.5e3"), -1500.0);
+    assert_eq!(value("metric\t\t2 # {trace_id=\"abc\"} 1"), 2.0);
+}
+
+#[test]
+fn error_within_family() {
+    let input = "\
+        # TYPE requests counter\n\
+        requests_total{path=\"/a\"} 1\n\
+        requests_total{path=\"/b\"} two\n\
+        requests_total{path=\"/c\"} 3\n\
+        # TYPE temperature gauge\n\
+        temperature 21.5\n";
+
+    let mut families = Families::new(input);
+    let error = families.next().unwrap().unwrap_err();
+    assert_eq!(error.line(), 3);
+    // the family which contained the invalid line is still returned, with
+    // the samples on either side of it.
+    let requests = families.next().unwrap().unwrap();
+    assert_eq!(requests.name(), "requests");
+    assert_eq!(requests.metric_type(), "counter");
+    let values = requests.samples().map(|s| s.value()).collect::<Vec<_>>();
+    assert_eq!(values, [1.0, 3.0]);
+
+    let temperature = families.next().unwrap().unwrap();
+    assert_eq!(temperature.name(), "temperature");
+    assert!(families.next().is_none());
+}
@@ -1,12 +1,34 @@
 //! A statically-constructable, dynamically-initialized fixed size array of values.
+//!
+//! # Removal
+//!
+//! Values may be removed from a [`Registry`] or [`RegistryMap`], allowing their
+//! slots to be reused by subsequently registered values. Because a slot's
+//! value may be dropped and replaced by a new value, a removed value must not
+//! be dropped while any references to it still exist. Therefore, references
+//! to values in a registry come in two forms:
+//!
+//! - Counted references ([`Ref`] and [`MapRef`]), returned by methods such as
+//!   [`Registry::try_register_ref`] and [`Registry::refs`]. A removed value is
+//!   dropped, and its slot becomes available for reuse, once the last counted
+//!   reference to it is dropped.
+//! - Plain references (`&T`), returned by methods such as
+//!   [`Registry::register`] and [`Registry::iter`]. Since a plain reference
+//!   may live as long as the registry itself, handing one out _pins_ the
+//!   slot: a pinned value may still be removed (so that it is no longer
+//!   returned by lookups or iteration), but its slot is never reused.
+//!
+//! Code which removes values should therefore use the counted reference APIs
+//! exclusively.
 use core::{
     cell::UnsafeCell,
     fmt,
     iter::{DoubleEndedIterator, FusedIterator},
     mem::MaybeUninit,
+    ops::Deref,
     ptr, slice,
 };
-use portable_atomic::{AtomicBool, AtomicUsize, Ordering::*};
+use portable_atomic::{AtomicUsize, Ordering::*};
 
 #[cfg(feature = "serde")]
 use serde::{
@@ -14,11 +36,14 @@ use serde::{
     Serialize, Serializer,
 };
 
+mod hash;
+pub(crate) use self::hash::FnvHasher;
+pub use self::hash::HashRegistryMap;
+
 /// A statically-constructed but dynamically-initialized array of up to
 /// `CAPACITY` `T`-typed values.
 pub struct Registry<T, const CAPACITY: usize> {
     values: [Slot<T>; CAPACITY],
-    next: AtomicUsize,
 }
 
 /// A [`Registry`] of `(K, V)` pairs.
@@ -48,15 +73,96 @@ pub struct Values<'registry, K, V> {
     slots: slice::Iter<'registry, Slot<(K, V)>>,
 }
 
+/// An iterator over counted references to the values in a [`Registry`].
+#[derive(Debug)]
+pub struct Refs<'registry, T> {
+    slots: slice::Iter<'registry, Slot<T>>,
+}
+
+/// An iterator over counted references to the entries in a [`RegistryMap`].
+#[derive(Debug)]
+pub struct MapRefs<'registry, K, V> {
+    slots: slice::Iter<'registry, Slot<(K, V)>>,
+}
+
+/// A counted reference to a value in a [`Registry`].
+///
+/// While a `Ref` exists, the value it references will not be dropped, even if
+/// it is removed from the registry. See [the module-level
+/// documentation](self#removal) for details.
+pub struct Ref<'registry, T> {
+    slot: &'registry Slot<T>,
+}
+
+/// A counted reference to an entry in a [`RegistryMap`].
+///
+/// While a `MapRef` exists, the entry it references will not be dropped, even
+/// if it is removed from the map. See [the module-level
+/// documentation](self#removal) for details.
+pub struct MapRef<'registry, K, V>(Ref<'registry, (K, V)>);
+
+/// A fixed-capacity map which may be used to store the metrics in a
+/// [`MetricFamily`](crate::MetricFamily).
+///
+/// This trait is implemented by [`RegistryMap`], which looks up keys using a
+/// linear scan, and [`HashRegistryMap`], which looks up keys by their hash.
+pub trait Storage<K, V> {
+    /// An empty map.
+    const EMPTY: Self;
+
+    /// The maximum number of entries which may be stored in this map.
+    const CAPACITY: usize;
+
+    /// Returns a [counted reference](MapRef) to the entry for the given `key`,
+    /// or registers the value returned by `init` with that key if no entry
+    /// exists, returning the `key` back if the map is full.
+    fn get_or_try_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Result<MapRef<'_, K, V>, K>;
+
+    /// Removes the entry for the given `key`, returning `true` if an entry was
+    /// removed.
+    fn remove(&self, key: &K) -> bool;
+
+    /// Removes every entry for which `f` returns `false`.
+    fn retain(&self, f: impl FnMut(&K, &V) -> bool);
+
+    /// Returns an iterator over [counted references](MapRef) to the entries in
+    /// this map.
+    fn refs(&self) -> MapRefs<'_, K, V>;
+
+    /// Returns `true` if no more entries can be stored in this map.
+    fn is_full(&self) -> bool;
+}
+
 struct Slot<T> {
     value: UnsafeCell<MaybeUninit<T>>,
-    initialized: AtomicBool,
+    /// The slot's state. The low bits are the flags defined below, and the
+    /// remaining bits count outstanding [`Ref`]s to the slot's value.
+    state: AtomicUsize,
 }
 
+/// The slot contains an initialized value.
+const INITIALIZED: usize = 1 << 0;
+/// The slot's value has been removed, and will be dropped once it is no longer
+/// referenced.
+const REMOVED: usize = 1 << 1;
+/// A plain reference to the slot's value has been handed out, so the value
+/// may never be dropped.
+const PINNED: usize = 1 << 2;
+/// A thread has exclusive access to the slot, in order to initialize or drop
+/// its value.
+const LOCKED: usize = 1 << 3;
+/// One outstanding `Ref` to the slot's value.
+const REF_ONE: usize = 1 << 4;
+
 impl<T, const CAPACITY: usize> Registry<T, CAPACITY> {
+    #[allow(clippy::declare_interior_mutable_const)]
     const NEW_SLOT: Slot<T> = Slot {
         value: UnsafeCell::new(MaybeUninit::uninit()),
-        initialized: AtomicBool::new(false),
+        state: AtomicUsize::new(0),
     };
 
     /// Returns a new `Registry` which can store up to `CAPACITY` values.
@@ -64,7 +170,6 @@ impl<T, const CAPACITY: usize> Registry<T, CAPACITY> {
     pub const fn new() -> Self {
         Self {
             values: [Self::NEW_SLOT; CAPACITY],
-            next: AtomicUsize::new(0),
         }
     }
 
@@ -139,32 +244,140 @@ impl<T, const CAPACITY: usize> Registry<T, CAPACITY> {
     /// // Now that the registry is full, `try_register` returns an error
     /// // containing the original value:
     /// assert!(REGISTRY.try_register("baz").is_err());
+    /// // Failed registrations don't change the registry's length.
+    /// assert_eq!(REGISTRY.len(), 2);
+    /// assert_eq!(REGISTRY.remaining_capacity(), 0);
     /// ```
     ///
     /// [full]: Self::is_full
     pub fn try_register(&self, value: T) -> Result<&T, T> {
-        let idx = self.next.fetch_add(1, AcqRel);
-
-        let Some(slot) = self.values.get(idx) else {
-            return Err(value);
-        };
-        assert!(!slot.initialized.load(Acquire), "slot already initialized!");
-
-        let init = unsafe {
-            // Safety: we have exclusive access to the slot.
-            let uninit = &mut *slot.value.get();
-            ptr::write(uninit.as_mut_ptr(), value);
-            uninit.assume_init_ref()
-        };
-
-        let _was_init = slot.initialized.swap(true, AcqRel);
-        debug_assert!(
-            !_was_init,
-            "slot initialized while we were initializing it, wtf!"
-        );
+        let slot = self.insert(value, INITIALIZED | PINNED)?;
+        Ok(unsafe {
+            // Safety: the slot is pinned, so its value will never be dropped.
+            slot.value()
+        })
+    }
 
-        // value initialized!
-        Ok(init)
+    /// Attempt to store `value` in this registry, returning a [counted
+    /// reference](Ref) to the stored value if it is successfully registered,
+    /// or the original `value` if the registry is [full].
+    ///
+    /// Unlike [`try_register`](Self::try_register), this does not pin the
+    /// value's slot, so the slot may be reused once the value is
+    /// [removed](Self::retain) and the returned `Ref` is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::registry::Registry;
+    ///
+    /// static REGISTRY: Registry<&'static str, 1> = Registry::new();
+    ///
+    /// let foo = REGISTRY.try_register_ref("foo").expect("registry has capacity");
+    /// assert_eq!(*foo, "foo");
+    /// assert!(REGISTRY.is_full());
+    ///
+    /// // Removing the value doesn't free its slot while it's still referenced.
+    /// REGISTRY.retain(|&value| value != "foo");
+    /// assert!(REGISTRY.is_full());
+    /// assert_eq!(*foo, "foo");
+    ///
+    /// // Once the last reference is dropped, the slot may be reused.
+    /// drop(foo);
+    /// assert!(REGISTRY.is_empty());
+    /// assert!(REGISTRY.try_register_ref("bar").is_ok());
+    /// ```
+    ///
+    /// [full]: Self::is_full
+    pub fn try_register_ref(&self, value: T) -> Result<Ref<'_, T>, T> {
+        let slot = self.insert(value, INITIALIZED | REF_ONE)?;
+        Ok(Ref { slot })
+    }
+
+    /// Removes every value for which `f` returns `false`.
+    ///
+    /// Removed values are no longer returned by iterators over this registry.
+    /// Each removed value is dropped, and its slot becomes available for
+    /// reuse, once it is no longer referenced. Values which have been
+    /// [pinned](self#removal) are removed, but their slots are never reused.
+    pub fn retain(&self, mut f: impl FnMut(&T) -> bool) {
+        for value in self.refs() {
+            if !f(&value) {
+                value.slot.remove();
+            }
+        }
+    }
+
+    /// Returns an iterator over [counted references](Ref) to all the entries
+    /// currently stored in this `Registry`.
+    ///
+    /// Unlike [`iter`](Self::iter), this does not pin the entries' slots.
+    #[must_use]
+    #[inline]
+    pub fn refs(&self) -> Refs<'_, T> {
+        Refs {
+            slots: self.values.iter(),
+        }
+    }
+
+    /// Stores `value` in this registry unless an equal value (according to
+    /// `eq`) is already stored, returning a reference to the stored value.
+    ///
+    /// Slots are visited in order, and each empty slot is claimed with a
+    /// compare-and-swap, so concurrent calls with equal values store only one
+    /// of them. A slot which another thread is initializing is waited on
+    /// (which only takes as long as moving its value in) and then compared.
+    ///
+    /// This only guarantees uniqueness for registries whose values are never
+    /// removed, since a removed value's slot may be reused out of order.
+    pub(crate) fn try_register_unique(
+        &self,
+        value: T,
+        eq: impl Fn(&T, &T) -> bool,
+    ) -> Result<&T, T> {
+        for slot in &self.values {
+            loop {
+                if slot.try_lock() {
+                    unsafe {
+                        // Safety: we just locked the slot, and the slot is
+                        // pinned, so its value will never be dropped.
+                        slot.init(value, INITIALIZED | PINNED);
+                        return Ok(slot.value());
+                    }
+                }
+
+                if let Some(existing) = slot.pin() {
+                    if eq(existing, &value) {
+                        return Ok(existing);
+                    }
+                    break;
+                }
+
+                if slot.state.load(Acquire) & LOCKED == 0 {
+                    // the slot's value was removed; skip it.
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+
+        Err(value)
+    }
+
+    /// Moves `value` into a free slot, leaving the slot in the provided
+    /// (initialized) `state`.
+    fn insert(&self, value: T, state: usize) -> Result<&Slot<T>, T> {
+        for slot in &self.values {
+            if slot.try_lock() {
+                unsafe {
+                    // Safety: we just locked the slot.
+                    slot.init(value, state);
+                }
+                return Ok(slot);
+            }
+        }
+
+        Err(value)
     }
 
     /// Attempt to store the value of `T::default` in this registry, returning a
@@ -187,6 +400,10 @@ impl<T, const CAPACITY: usize> Registry<T, CAPACITY> {
 
     /// Returns an iterator over all the entries currently stored in this
     /// `Registry`.
+    ///
+    /// Since the returned references may live as long as the registry itself,
+    /// this [pins](self#removal) the slot of every entry it returns. Use
+    /// [`refs`](Self::refs) to iterate without pinning.
     #[must_use]
     #[inline]
     pub fn iter(&self) -> Iter<'_, T> {
@@ -196,10 +413,16 @@ impl<T, const CAPACITY: usize> Registry<T, CAPACITY> {
     }
 
     /// Returns the number of entries currently stored in this `Registry`.
+    ///
+    /// This includes entries which have been removed, but whose slots cannot
+    /// be reused yet because they are still referenced (or pinned).
     #[must_use]
     #[inline]
     pub fn len(&self) -> usize {
-        self.next.load(Acquire)
+        self.values
+            .iter()
+            .filter(|slot| slot.state.load(Acquire) != 0)
+            .count()
     }
 
     /// Returns `true` if _no_ entries pairs are currently stored in this
@@ -360,14 +583,16 @@ impl<T, const CAPACITY: usize> Registry<T, CAPACITY> {
 }
 
 unsafe impl<T: Send, const CAPACITY: usize> Send for Registry<T, CAPACITY> {}
-unsafe impl<T: Sync, const CAPACITY: usize> Sync for Registry<T, CAPACITY> {}
+// values may be dropped by whichever thread removes them (or drops the last
+// reference to them), so sharing a registry requires `T: Send`.
+unsafe impl<T: Send + Sync, const CAPACITY: usize> Sync for Registry<T, CAPACITY> {}
 
 impl<T, const CAPACITY: usize> fmt::Debug for Registry<T, CAPACITY>
 where
     T: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_list().entries(self.iter()).finish()
+        f.debug_list().entries(self.refs()).finish()
     }
 }
 
@@ -389,9 +614,9 @@ where
     where
         S: Serializer,
     {
-        let mut seq = serializer.serialize_seq(Some(self.len()))?;
-        for value in self.iter() {
-            seq.serialize_element(value)?;
+        let mut seq = serializer.serialize_seq(None)?;
+        for value in self.refs() {
+            seq.serialize_element(&*value)?;
         }
         seq.end()
     }
@@ -447,14 +672,121 @@ impl<K, V, const CAPACITY: usize> RegistryMap<K, V, CAPACITY> {
     where
         K: PartialEq,
     {
-        for (k, v) in self.iter() {
-            // already exists!
-            if &key == k {
-                return Some(v);
-            }
+        let entry = self.get_or_register_ref_with(key, init)?;
+        Some(entry.leak().1)
+    }
+
+    /// Returns a [counted reference](MapRef) to the entry for the given `key`,
+    /// or registers the value returned by `init` with that key if no entry
+    /// exists.
+    ///
+    /// Unlike [`get_or_register_with`](Self::get_or_register_with), this does
+    /// not pin the entry's slot, so the slot may be reused once the entry is
+    /// [removed](Self::remove) and the returned `MapRef` is dropped.
+    ///
+    /// This is an O(_n_) operation, where _n_ is the [capacity](Self::capacity)
+    /// of this `RegistryMap`.
+    ///
+    /// # Returns
+    ///
+    /// A counted reference to the entry for `key`, or `None` if this
+    /// `RegistryMap` is [full](Self::is_full).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::registry::RegistryMap;
+    ///
+    /// static REGISTRY: RegistryMap<&'static str, usize, 1> = RegistryMap::new();
+    ///
+    /// let entry = REGISTRY.get_or_register_ref_with("answer", || 42).unwrap();
+    /// assert_eq!(entry.key(), &"answer");
+    /// assert_eq!(entry.value(), &42);
+    /// drop(entry);
+    ///
+    /// // Once the entry is removed, its slot may be reused.
+    /// assert!(REGISTRY.remove(&"answer"));
+    /// let entry = REGISTRY.get_or_register_ref_with("question", || 0).unwrap();
+    /// assert_eq!(entry.value(), &0);
+    /// ```
+    pub fn get_or_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Option<MapRef<'_, K, V>>
+    where
+        K: PartialEq,
+    {
+        self.get_or_try_register_ref_with(key, init).ok()
+    }
+
+    fn get_or_try_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Result<MapRef<'_, K, V>, K>
+    where
+        K: PartialEq,
+    {
+        // already exists!
+        if let Some(entry) = self.get(&key) {
+            return Ok(entry);
         }
 
-        self.0.try_register((key, init())).ok().map(|(_, val)| val)
+        self.0
+            .try_register_ref((key, init()))
+            .map(MapRef)
+            .map_err(|(key, _)| key)
+    }
+
+    /// Returns a [counted reference](MapRef) to the entry for the given
+    /// `key`, if one exists.
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<MapRef<'_, K, V>>
+    where
+        K: PartialEq,
+    {
+        self.refs().find(|entry| entry.key() == key)
+    }
+
+    /// Removes the entry for the given `key`, returning `true` if an entry was
+    /// removed.
+    ///
+    /// The removed entry is dropped, and its slot becomes available for reuse,
+    /// once it is no longer referenced. If the entry has been
+    /// [pinned](self#removal), it is removed, but its slot is never reused.
+    pub fn remove(&self, key: &K) -> bool
+    where
+        K: PartialEq,
+    {
+        let mut removed = false;
+        self.retain(|k, _| {
+            let matches = k == key;
+            removed |= matches;
+            !matches
+        });
+        removed
+    }
+
+    /// Removes every entry for which `f` returns `false`.
+    ///
+    /// Each removed entry is dropped, and its slot becomes available for
+    /// reuse, once it is no longer referenced. Entries which have been
+    /// [pinned](self#removal) are removed, but their slots are never reused.
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
+        self.0.retain(|(key, value)| f(key, value))
+    }
+
+    /// Returns an iterator over [counted references](MapRef) to the entries in
+    /// this `RegistryMap`.
+    ///
+    /// Unlike [`iter`](Self::iter), this does not pin the entries' slots.
+    #[must_use]
+    #[inline]
+    pub fn refs(&self) -> MapRefs<'_, K, V> {
+        MapRefs {
+            slots: self.0.values.iter(),
+        }
     }
 
     /// Returns the value associated with the given `key`, or registers the
@@ -545,6 +877,10 @@ impl<K, V, const CAPACITY: usize> RegistryMap<K, V, CAPACITY> {
 
     /// Returns an iterator that borrows the key-value pairs in this
     /// `RegistryMap`.
+    ///
+    /// Since the returned references may live as long as the map itself, this
+    /// [pins](self#removal) the slot of every entry it returns. Use
+    /// [`refs`](Self::refs) to iterate without pinning.
     #[must_use]
     #[inline]
     pub fn iter(&self) -> Entries<'_, K, V> {
@@ -555,6 +891,9 @@ impl<K, V, const CAPACITY: usize> RegistryMap<K, V, CAPACITY> {
 
     /// Returns an iterator that borrows the `K`-typed keys in this
     /// `RegistryMap`.
+    ///
+    /// Like [`iter`](Self::iter), this [pins](self#removal) the slot of every
+    /// entry it returns.
     #[must_use]
     #[inline]
     pub fn keys(&self) -> Keys<'_, K, V> {
@@ -565,6 +904,9 @@ impl<K, V, const CAPACITY: usize> RegistryMap<K, V, CAPACITY> {
 
     /// Returns an iterator that borrows the `V`-typed values in this
     /// `RegistryMap`.
+    ///
+    /// Like [`iter`](Self::iter), this [pins](self#removal) the slot of every
+    /// entry it returns.
     #[must_use]
     #[inline]
     pub fn values(&self) -> Values<'_, K, V> {
@@ -754,13 +1096,47 @@ impl<K, V, const CAPACITY: usize> RegistryMap<K, V, CAPACITY> {
     }
 }
 
+impl<K: PartialEq, V, const CAPACITY: usize> Storage<K, V> for RegistryMap<K, V, CAPACITY> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Self::new();
+    const CAPACITY: usize = CAPACITY;
+
+    fn get_or_try_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Result<MapRef<'_, K, V>, K> {
+        Self::get_or_try_register_ref_with(self, key, init)
+    }
+
+    fn remove(&self, key: &K) -> bool {
+        Self::remove(self, key)
+    }
+
+    fn retain(&self, f: impl FnMut(&K, &V) -> bool) {
+        Self::retain(self, f)
+    }
+
+    fn refs(&self) -> MapRefs<'_, K, V> {
+        Self::refs(self)
+    }
+
+    fn is_full(&self) -> bool {
+        Self::is_full(self)
+    }
+}
+
 impl<K, V, const CAPACITY: usize> fmt::Debug for RegistryMap<K, V, CAPACITY>
 where
     K: fmt::Debug,
     V: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_map().entries(self.iter()).finish()
+        let mut map = f.debug_map();
+        for entry in self.refs() {
+            map.entry(entry.key(), entry.value());
+        }
+        map.finish()
     }
 }
 
@@ -774,9 +1150,9 @@ where
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(self.len()))?;
-        for (key, value) in self.iter() {
-            map.serialize_entry(key, value)?;
+        let mut map = serializer.serialize_map(None)?;
+        for entry in self.refs() {
+            map.serialize_entry(entry.key(), entry.value())?;
         }
         map.end()
     }
@@ -798,7 +1174,7 @@ impl<'registry, T> Iterator for Iter<'registry, T> {
         loop {
             let slot = self.slots.next()?;
             // skip over uninitialized slots.
-            if let Some(value) = slot.get() {
+            if let Some(value) = slot.pin() {
                 return Some(value);
             }
         }
@@ -816,7 +1192,7 @@ impl<'registry, T> DoubleEndedIterator for Iter<'registry, T> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let slot = self.slots.next_back()?;
-            if let Some(value) = slot.get() {
+            if let Some(value) = slot.pin() {
                 return Some(value);
             }
         }
@@ -833,7 +1209,7 @@ impl<'registry, K, V> Iterator for Entries<'registry, K, V> {
         loop {
             let slot = self.slots.next()?;
             // skip over uninitialized slots.
-            if let Some((ref key, ref value)) = slot.get() {
+            if let Some((ref key, ref value)) = slot.pin() {
                 return Some((key, value));
             }
         }
@@ -851,7 +1227,7 @@ impl<'registry, K, V> DoubleEndedIterator for Entries<'registry, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let slot = self.slots.next_back()?;
-            if let Some((ref key, ref value)) = slot.get() {
+            if let Some((ref key, ref value)) = slot.pin() {
                 return Some((key, value));
             }
         }
@@ -868,7 +1244,7 @@ impl<'registry, K, V> Iterator for Keys<'registry, K, V> {
         loop {
             let slot = self.slots.next()?;
             // skip over uninitialized slots.
-            if let Some((ref key, _)) = slot.get() {
+            if let Some((ref key, _)) = slot.pin() {
                 return Some(key);
             }
         }
@@ -886,7 +1262,7 @@ impl<'registry, K, V> DoubleEndedIterator for Keys<'registry, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let slot = self.slots.next_back()?;
-            if let Some((ref key, _)) = slot.get() {
+            if let Some((ref key, _)) = slot.pin() {
                 return Some(key);
             }
         }
@@ -903,7 +1279,7 @@ impl<'registry, K, V> Iterator for Values<'registry, K, V> {
         loop {
             let slot = self.slots.next()?;
             // skip over uninitialized slots.
-            if let Some((_, ref value)) = slot.get() {
+            if let Some((_, ref value)) = slot.pin() {
                 return Some(value);
             }
         }
@@ -921,35 +1297,303 @@ impl<'registry, K, V> DoubleEndedIterator for Values<'registry, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let slot = self.slots.next_back()?;
-            if let Some((_, value)) = slot.get() {
+            if let Some((_, value)) = slot.pin() {
                 return Some(value);
             }
         }
     }
 }
 
-// === impl Slot ==
+// === impl Refs ===
+
+impl<'registry, T> Iterator for Refs<'registry, T> {
+    type Item = Ref<'registry, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.find_map(Slot::acquire)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.slots.size_hint().1)
+    }
+}
+
+impl<'registry, T> FusedIterator for Refs<'registry, T> {}
+
+impl<'registry, T> DoubleEndedIterator for Refs<'registry, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.slots.by_ref().rev().find_map(Slot::acquire)
+    }
+}
+
+// === impl MapRefs ===
+
+impl<'registry, K, V> Iterator for MapRefs<'registry, K, V> {
+    type Item = MapRef<'registry, K, V>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.find_map(Slot::acquire).map(MapRef)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.slots.size_hint().1)
+    }
+}
+
+impl<'registry, K, V> FusedIterator for MapRefs<'registry, K, V> {}
+
+impl<'registry, K, V> DoubleEndedIterator for MapRefs<'registry, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.slots
+            .by_ref()
+            .rev()
+            .find_map(Slot::acquire)
+            .map(MapRef)
+    }
+}
+
+// === impl Ref ===
+
+impl<'registry, T> Ref<'registry, T> {
+    /// Converts this counted reference into a plain reference which lives as
+    /// long as the registry.
+    ///
+    /// This [pins](self#removal) the value's slot, so that it will never be
+    /// reused, even if the value is removed.
+    ///
+    /// This is an associated function, rather than a method, so that it does
+    /// not shadow methods on `T`.
+    #[must_use]
+    pub fn leak(this: Self) -> &'registry T {
+        this.slot.state.fetch_or(PINNED, AcqRel);
+        let slot = this.slot;
+        drop(this);
+        unsafe {
+            // Safety: the slot is now pinned, so its value will never be
+            // dropped.
+            slot.value()
+        }
+    }
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe {
+            // Safety: the value will not be dropped while this `Ref` exists.
+            self.slot.value()
+        }
+    }
+}
+
+impl<T> Clone for Ref<'_, T> {
+    fn clone(&self) -> Self {
+        // we already hold a reference, so the slot can't have been reclaimed.
+        self.slot.state.fetch_add(REF_ONE, AcqRel);
+        Self { slot: self.slot }
+    }
+}
+
+impl<T> Drop for Ref<'_, T> {
+    fn drop(&mut self) {
+        let state = self.slot.state.fetch_sub(REF_ONE, AcqRel) - REF_ONE;
+        if state == INITIALIZED | REMOVED {
+            // that was the last reference to a removed value.
+            self.slot.try_reclaim();
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Send + Sync> Send for Ref<'_, T> {}
+unsafe impl<T: Send + Sync> Sync for Ref<'_, T> {}
+
+// === impl MapRef ===
+
+impl<'registry, K, V> MapRef<'registry, K, V> {
+    /// Returns the entry's key.
+    #[must_use]
+    #[inline]
+    pub fn key(&self) -> &K {
+        &self.0 .0
+    }
+
+    /// Returns the entry's value.
+    #[must_use]
+    #[inline]
+    pub fn value(&self) -> &V {
+        &self.0 .1
+    }
+
+    /// Converts this counted reference into plain references to the entry's
+    /// key and value, which live as long as the map.
+    ///
+    /// This [pins](self#removal) the entry's slot, so that it will never be
+    /// reused, even if the entry is removed.
+    #[must_use]
+    pub fn leak(self) -> (&'registry K, &'registry V) {
+        let (key, value) = Ref::leak(self.0);
+        (key, value)
+    }
+
+    /// Removes this entry from its map, unless its slot has been
+    /// [pinned](self#removal), returning `true` if it was removed.
+    pub(crate) fn remove_unpinned(&self) -> bool {
+        let removed = self
+            .0
+            .slot
+            .state
+            .fetch_update(AcqRel, Acquire, |state| {
+                if state & (INITIALIZED | REMOVED | LOCKED | PINNED) != INITIALIZED {
+                    return None;
+                }
+                Some(state | REMOVED)
+            })
+            .is_ok();
+        if removed {
+            self.0.slot.try_reclaim();
+        }
+        removed
+    }
+}
+
+impl<K, V> Clone for MapRef<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for MapRef<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapRef")
+            .field("key", self.key())
+            .field("value", self.value())
+            .finish()
+    }
+}
+
+// === impl Slot ===
 
 impl<T> Slot<T> {
-    fn get(&self) -> Option<&T> {
-        if !self.initialized.load(Acquire) {
-            return None;
+    /// Returns a counted reference to this slot's value, or `None` if the slot
+    /// is empty or its value has been removed.
+    fn acquire(&self) -> Option<Ref<'_, T>> {
+        self.state
+            .fetch_update(AcqRel, Acquire, |state| {
+                if state & (INITIALIZED | REMOVED | LOCKED) != INITIALIZED {
+                    return None;
+                }
+                Some(
+                    state
+                        .checked_add(REF_ONE)
+                        .expect("too many references to a registry slot"),
+                )
+            })
+            .ok()?;
+        Some(Ref { slot: self })
+    }
+
+    /// Returns a reference to this slot's value which lives as long as the
+    /// slot, pinning the slot, or `None` if the slot is empty or its value has
+    /// been removed.
+    fn pin(&self) -> Option<&T> {
+        self.state
+            .fetch_update(AcqRel, Acquire, |state| {
+                if state & (INITIALIZED | REMOVED | LOCKED) != INITIALIZED {
+                    return None;
+                }
+                Some(state | PINNED)
+            })
+            .ok()?;
+        Some(unsafe {
+            // Safety: the slot is now pinned, so its value will never be
+            // dropped.
+            self.value()
+        })
+    }
+
+    /// Locks this slot for initialization, returning `false` if it is not
+    /// empty.
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(0, LOCKED, Acquire, Relaxed)
+            .is_ok()
+    }
+
+    /// Moves `value` into this slot, leaving it in the provided (initialized)
+    /// `state`.
+    ///
+    /// # Safety
+    ///
+    /// The slot must have been locked by [`Slot::try_lock`].
+    unsafe fn init(&self, value: T, state: usize) {
+        ptr::write((*self.value.get()).as_mut_ptr(), value);
+        // value initialized!
+        self.state.store(state, Release);
+    }
+
+    /// Marks this slot's value as removed, dropping it if it isn't referenced.
+    fn remove(&self) {
+        let removed = self
+            .state
+            .fetch_update(AcqRel, Acquire, |state| {
+                if state & (INITIALIZED | REMOVED | LOCKED) != INITIALIZED {
+                    return None;
+                }
+                Some(state | REMOVED)
+            })
+            .is_ok();
+        if removed {
+            self.try_reclaim();
+        }
+    }
+
+    /// Drops this slot's value and empties the slot, if the value has been
+    /// removed and is neither referenced nor pinned.
+    fn try_reclaim(&self) {
+        if self
+            .state
+            .compare_exchange(INITIALIZED | REMOVED, LOCKED, AcqRel, Acquire)
+            .is_err()
+        {
+            return;
         }
 
         unsafe {
-            // Safety: we just checked the bit that tracks whether this value
-            // was initialized.
-            Some((*self.value.get()).assume_init_ref())
+            // Safety: we have exclusive access to the slot, and its value is
+            // initialized.
+            ptr::drop_in_place((*self.value.get()).as_mut_ptr());
         }
+        self.state.store(0, Release);
+    }
+
+    /// # Safety
+    ///
+    /// The slot must be initialized, and the caller must ensure that its value
+    /// is not dropped for the lifetime of the returned reference (by holding a
+    /// `Ref` or pinning the slot).
+    unsafe fn value(&self) -> &T {
+        (*self.value.get()).assume_init_ref()
     }
 }
 
 impl<T: fmt::Debug> fmt::Debug for Slot<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut tuple = f.debug_tuple("Slot");
-        match self.get() {
-            Some(value) => tuple.field(value).finish(),
-            None => tuple.field(&"<uninitialized>").finish(),
+        match self.acquire() {
+            Some(value) => tuple.field(&*value).finish(),
+            None => tuple.field(&"<empty>").finish(),
         }
     }
 }
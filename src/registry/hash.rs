@@ -0,0 +1,450 @@
+//! A fixed-capacity map which looks up keys by their hash.
+use super::{Entries, Keys, MapRef, MapRefs, Ref, Slot, Storage, Values, INITIALIZED, REF_ONE};
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+use portable_atomic::{AtomicUsize, Ordering::*};
+
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+/// A statically-constructed, fixed-capacity map of up to `CAPACITY` key-value
+/// pairs, which looks up keys by their [`Hash`].
+///
+/// Unlike [`RegistryMap`](super::RegistryMap), whose lookups are a linear scan
+/// over every entry, `HashRegistryMap` is a lock-free open-addressing hash
+/// table using linear probing. Lookups are O(1) on average, which is
+/// significantly faster for maps with more than a few dozen entries.
+///
+/// Keys are hashed using the [FNV-1a] hash function, which is fast for the
+/// short keys typical of label sets, but is not resistant to collision
+/// attacks. Since lookups degrade as the map fills up, `CAPACITY` should be
+/// somewhat larger than the number of entries the map is expected to hold.
+///
+/// Like `RegistryMap`, entries may be [removed](Self::remove), and their slots
+/// reused. See [the module-level documentation](super#removal) for details.
+///
+/// # Examples
+///
+/// A `HashRegistryMap` may be used as the storage of a
+/// [`MetricFamily`](crate::MetricFamily):
+///
+/// ```
+/// use tinymetrics::{registry::HashRegistryMap, Counter, MetricBuilder, MetricFamily};
+///
+/// type Labels = &'static [(&'static str, &'static str)];
+///
+/// static REQUESTS: MetricFamily<'static, Counter, 256, Labels, HashRegistryMap<Labels, Counter, 256>> =
+///     MetricBuilder::new("requests").build_with_storage();
+///
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(1);
+/// assert_eq!(REQUESTS.register(&[("path", "/")]).unwrap().value(), 1);
+/// ```
+///
+/// [FNV-1a]: https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function
+pub struct HashRegistryMap<K, V, const CAPACITY: usize> {
+    slots: [Slot<(K, V)>; CAPACITY],
+    /// The hash of the key most recently stored in each slot, with the low
+    /// bit set, or 0 if the slot has never been used.
+    ///
+    /// Since a slot's tag is never reset to 0, probe sequences are never
+    /// broken by the removal of an entry.
+    tags: [AtomicUsize; CAPACITY],
+}
+
+/// A 64-bit [FNV-1a] hasher.
+///
+/// [FNV-1a]: https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function
+pub(crate) struct FnvHasher(u64);
+
+impl<K, V, const CAPACITY: usize> HashRegistryMap<K, V, CAPACITY> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW_SLOT: Slot<(K, V)> = super::Registry::<(K, V), CAPACITY>::NEW_SLOT;
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW_TAG: AtomicUsize = AtomicUsize::new(0);
+
+    /// Returns a new `HashRegistryMap` with space for up to `CAPACITY`
+    /// key-value pairs.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            slots: [Self::NEW_SLOT; CAPACITY],
+            tags: [Self::NEW_TAG; CAPACITY],
+        }
+    }
+
+    /// Returns a [counted reference](MapRef) to the entry for the given
+    /// `key`, if one exists.
+    ///
+    /// This is an O(1) operation on average.
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<MapRef<'_, K, V>>
+    where
+        K: Hash + PartialEq,
+    {
+        self.find(key, hash(key)).map(|(_, entry)| entry)
+    }
+
+    /// Returns a [counted reference](MapRef) to the entry for the given `key`,
+    /// or registers the value returned by `init` with that key if no entry
+    /// exists.
+    ///
+    /// This is an O(1) operation on average.
+    ///
+    /// # Returns
+    ///
+    /// A counted reference to the entry for `key`, or `None` if there is no
+    /// free slot for a new entry.
+    pub fn get_or_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Option<MapRef<'_, K, V>>
+    where
+        K: Hash + PartialEq,
+    {
+        self.get_or_try_register_ref_with(key, init).ok()
+    }
+
+    fn get_or_try_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Result<MapRef<'_, K, V>, K>
+    where
+        K: Hash + PartialEq,
+    {
+        let hash = hash(&key);
+        // already exists!
+        if let Some((_, entry)) = self.find(&key, hash) {
+            return Ok(entry);
+        }
+
+        let idx = match self.probe(hash).find(|&idx| self.slots[idx].try_lock()) {
+            Some(idx) => idx,
+            None => return Err(key),
+        };
+        let value = (key, init());
+        let slot = &self.slots[idx];
+        // the tag must be set before the slot is initialized, so that a
+        // concurrent lookup can't observe the new entry with the old tag.
+        self.tags[idx].store(tag(hash), Release);
+        unsafe {
+            // Safety: we just locked the slot.
+            slot.init(value, INITIALIZED | REF_ONE);
+        }
+        Ok(MapRef(Ref { slot }))
+    }
+
+    /// Returns the value associated with the given `key`, or registers the
+    /// value returned by `init` with that key if no value exists.
+    ///
+    /// This [pins](super#removal) the entry's slot.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the value associated with `key`, or `None` if there is
+    /// no free slot for a new entry.
+    pub fn get_or_register_with(&self, key: K, init: impl FnOnce() -> V) -> Option<&V>
+    where
+        K: Hash + PartialEq,
+    {
+        let entry = self.get_or_register_ref_with(key, init)?;
+        Some(entry.leak().1)
+    }
+
+    /// Returns the value associated with the given `key`, or registers `value`
+    /// associated with that key if no value exists.
+    ///
+    /// This [pins](super#removal) the entry's slot.
+    pub fn get_or_register(&self, key: K, value: V) -> Option<&V>
+    where
+        K: Hash + PartialEq,
+    {
+        self.get_or_register_with(key, move || value)
+    }
+
+    /// Removes the entry for the given `key`, returning `true` if an entry was
+    /// removed.
+    ///
+    /// The removed entry is dropped, and its slot becomes available for reuse,
+    /// once it is no longer referenced.
+    pub fn remove(&self, key: &K) -> bool
+    where
+        K: Hash + PartialEq,
+    {
+        match self.get(key) {
+            Some(entry) => {
+                entry.0.slot.remove();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes every entry for which `f` returns `false`.
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
+        for entry in self.refs() {
+            if !f(entry.key(), entry.value()) {
+                entry.0.slot.remove();
+            }
+        }
+    }
+
+    /// Returns an iterator over [counted references](MapRef) to the entries in
+    /// this `HashRegistryMap`, in an unspecified order.
+    #[must_use]
+    #[inline]
+    pub fn refs(&self) -> MapRefs<'_, K, V> {
+        MapRefs {
+            slots: self.slots.iter(),
+        }
+    }
+
+    /// Returns an iterator that borrows the key-value pairs in this
+    /// `HashRegistryMap`, in an unspecified order.
+    ///
+    /// This [pins](super#removal) the slot of every entry it returns. Use
+    /// [`refs`](Self::refs) to iterate without pinning.
+    #[must_use]
+    #[inline]
+    pub fn iter(&self) -> Entries<'_, K, V> {
+        Entries {
+            slots: self.slots.iter(),
+        }
+    }
+
+    /// Returns an iterator that borrows the `K`-typed keys in this
+    /// `HashRegistryMap`.
+    ///
+    /// Like [`iter`](Self::iter), this [pins](super#removal) the slot of every
+    /// entry it returns.
+    #[must_use]
+    #[inline]
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            slots: self.slots.iter(),
+        }
+    }
+
+    /// Returns an iterator that borrows the `V`-typed values in this
+    /// `HashRegistryMap`.
+    ///
+    /// Like [`iter`](Self::iter), this [pins](super#removal) the slot of every
+    /// entry it returns.
+    #[must_use]
+    #[inline]
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            slots: self.slots.iter(),
+        }
+    }
+
+    /// Returns the number of entries currently stored in this
+    /// `HashRegistryMap`.
+    ///
+    /// This includes entries which have been removed, but whose slots cannot
+    /// be reused yet because they are still referenced (or pinned).
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.state.load(Acquire) != 0)
+            .count()
+    }
+
+    /// Returns `true` if _no_ entries are currently stored in this
+    /// `HashRegistryMap`.
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the _total_ number of entries that may be stored in this
+    /// `HashRegistryMap`.
+    #[must_use]
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        CAPACITY
+    }
+
+    /// Returns `true` if no more entries can be stored in this
+    /// `HashRegistryMap`.
+    #[must_use]
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Returns the number of entries that can _still_ be stored in this
+    /// `HashRegistryMap`.
+    #[must_use]
+    #[inline]
+    pub fn remaining_capacity(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Returns the index of the slot in which the entry for `key` is stored,
+    /// if there is one.
+    #[cfg(test)]
+    pub(crate) fn slot_index(&self, key: &K) -> Option<usize>
+    where
+        K: Hash + PartialEq,
+    {
+        self.find(key, hash(key)).map(|(idx, _)| idx)
+    }
+
+    /// Returns the index of the slot containing the entry for `key`, whose
+    /// hash is `hash`, and the entry.
+    fn find(&self, key: &K, hash: usize) -> Option<(usize, MapRef<'_, K, V>)>
+    where
+        K: PartialEq,
+    {
+        let tag = tag(hash);
+        for idx in self.probe(hash) {
+            match self.tags[idx].load(Acquire) {
+                // this slot has never been used, so the key can't be stored
+                // later in the probe sequence.
+                0 => return None,
+                slot_tag if slot_tag != tag => continue,
+                _ => {}
+            }
+
+            if let Some(entry) = self.slots[idx].acquire().map(MapRef) {
+                if entry.key() == key {
+                    return Some((idx, entry));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the indices of the slots to probe for a key with the provided
+    /// `hash`, starting at the key's home slot and wrapping around.
+    fn probe(&self, hash: usize) -> impl Iterator<Item = usize> {
+        // the home slot is taken from the hash, rather than the tag, whose low
+        // bit is always set: otherwise, only odd slots would be home slots
+        // when the capacity is even.
+        let home = hash.checked_rem(CAPACITY).unwrap_or(0);
+        (home..CAPACITY).chain(0..home)
+    }
+}
+
+/// Returns the hash of `key`.
+fn hash<K: Hash + ?Sized>(key: &K) -> usize {
+    let mut hasher = FnvHasher::new();
+    key.hash(&mut hasher);
+    // truncating the hash on 32-bit targets is fine.
+    hasher.finish() as usize
+}
+
+/// Returns the tag stored for a key with the provided `hash`: the hash with
+/// the low bit set, so that it is never 0.
+fn tag(hash: usize) -> usize {
+    hash | 1
+}
+
+impl<K, V, const CAPACITY: usize> Storage<K, V> for HashRegistryMap<K, V, CAPACITY>
+where
+    K: Hash + PartialEq,
+{
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Self::new();
+    const CAPACITY: usize = CAPACITY;
+
+    fn get_or_try_register_ref_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+    ) -> Result<MapRef<'_, K, V>, K> {
+        Self::get_or_try_register_ref_with(self, key, init)
+    }
+
+    fn remove(&self, key: &K) -> bool {
+        Self::remove(self, key)
+    }
+
+    fn retain(&self, f: impl FnMut(&K, &V) -> bool) {
+        Self::retain(self, f)
+    }
+
+    fn refs(&self) -> MapRefs<'_, K, V> {
+        Self::refs(self)
+    }
+
+    fn is_full(&self) -> bool {
+        Self::is_full(self)
+    }
+}
+
+impl<K, V, const CAPACITY: usize> fmt::Debug for HashRegistryMap<K, V, CAPACITY>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for entry in self.refs() {
+            map.entry(entry.key(), entry.value());
+        }
+        map.finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, const CAPACITY: usize> Serialize for HashRegistryMap<K, V, CAPACITY>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for entry in self.refs() {
+            map.serialize_entry(entry.key(), entry.value())?;
+        }
+        map.end()
+    }
+}
+
+impl<K, V, const CAPACITY: usize> Default for HashRegistryMap<K, V, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<K: Send, V: Send, const CAPACITY: usize> Send for HashRegistryMap<K, V, CAPACITY> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, const CAPACITY: usize> Sync
+    for HashRegistryMap<K, V, CAPACITY>
+{
+}
+
+// === impl FnvHasher ===
+
+impl FnvHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    pub(crate) const fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
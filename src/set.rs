@@ -0,0 +1,331 @@
+//! Collections of heterogeneous [`MetricFamily`](crate::MetricFamily)s.
+use crate::{FmtMetricFamily, Format, Matcher};
+use core::fmt;
+#[cfg(feature = "serde-set")]
+use {
+    crate::{registry::Storage, FmtLabels, Metric, MetricFamily},
+    alloc::boxed::Box,
+};
+
+/// A set of metric families of (potentially) different types, which are
+/// exposed together.
+///
+/// A `MetricSet` holds a slice of references to [`FmtMetricFamily`] trait
+/// objects, so it may be constructed in a `static` from families with
+/// different metric types, capacities, and label set types.
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::{CounterFamily, GaugeFamily, MetricBuilder, MetricSet};
+///
+/// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests")
+///     .with_help("requests handled")
+///     .build();
+/// static TEMPERATURE: GaugeFamily<'static, 2> = MetricBuilder::new("temperature")
+///     .with_unit("celsius")
+///     .build();
+///
+/// static METRICS: MetricSet<'static> = MetricSet::new(&[&REQUESTS, &TEMPERATURE]);
+///
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(1);
+///
+/// let exposition = METRICS.to_string();
+/// assert!(exposition.starts_with("# TYPE requests counter\n"));
+/// assert!(exposition.contains("# TYPE temperature gauge\n"));
+/// assert!(exposition.ends_with("# EOF\n"));
+/// ```
+///
+/// # Constant Labels
+///
+/// Labels which should be added to every series in every family in the set
+/// (such as the identity of the device exporting the metrics) may be provided
+/// using [`MetricSet::with_const_labels`]. These are added after each
+/// family's own [constant labels](crate::MetricBuilder::with_const_labels):
+///
+/// ```
+/// use tinymetrics::{CounterFamily, MetricBuilder, MetricSet};
+///
+/// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests")
+///     .with_const_labels(&[("service", "api")])
+///     .build();
+///
+/// static METRICS: MetricSet<'static> = <MetricSet>::new(&[&REQUESTS])
+///     .with_const_labels(&[("device_id", "1234"), ("region", "eu")]);
+///
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(1);
+///
+/// let exposition = METRICS.to_string();
+/// assert!(exposition.contains(
+///     "requests{path=\"/\",service=\"api\",device_id=\"1234\",region=\"eu\"} 1"
+/// ));
+/// ```
+///
+/// # Formats
+///
+/// A `MetricSet` is formatted in the OpenMetrics text format, unless another
+/// [`Format`] is selected using [`MetricSet::with_format`]:
+///
+/// ```
+/// use tinymetrics::{CounterFamily, Format, MetricBuilder, MetricSet};
+///
+/// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests").build();
+///
+/// static METRICS: MetricSet<'static> = <MetricSet>::new(&[&REQUESTS]).with_format(Format::Prometheus);
+///
+/// REQUESTS.register(&[("path", "/")]).unwrap().fetch_add(1);
+///
+/// let exposition = METRICS.to_string();
+/// assert!(exposition.contains("# TYPE requests_total counter\n"));
+/// assert!(exposition.contains("requests_total{path=\"/\"} 1"));
+/// assert!(!exposition.contains("# EOF"));
+/// ```
+///
+/// # Serialization
+///
+/// When the "serde-set" feature flag is enabled, a `MetricSet` of
+/// [`SerializeMetricFamily`] trait objects implements `serde::Serialize`:
+///
+/// ```
+/// # #[cfg(feature = "serde-set")] {
+/// use tinymetrics::{CounterFamily, MetricBuilder, MetricSet, SerializeMetricFamily};
+///
+/// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests").build();
+///
+/// static METRICS: MetricSet<'static, dyn SerializeMetricFamily + Sync> =
+///     MetricSet::new(&[&REQUESTS]);
+/// # }
+/// ```
+pub struct MetricSet<'a, F: ?Sized = dyn FmtMetricFamily + Sync> {
+    families: &'a [&'a F],
+    const_labels: &'a [(&'a str, &'a str)],
+    format: Format,
+}
+
+/// A [`FmtMetricFamily`] which may also be serialized.
+///
+/// This trait is implemented for all [`MetricFamily`](crate::MetricFamily)s
+/// whose metric and label types implement `serde::Serialize`. It is used to serialize a
+/// [`MetricSet`] of heterogeneous metric families, which requires type
+/// erasure (and, therefore, the "serde-set" feature flag, which requires
+/// `alloc`).
+#[cfg(feature = "serde-set")]
+pub trait SerializeMetricFamily: FmtMetricFamily {
+    /// Returns this metric family as a type-erased `Serialize` trait object.
+    fn as_serialize(&self) -> &dyn erased_serde::Serialize;
+
+    /// Returns this metric family as a type-erased `Serialize` trait object,
+    /// which adds `const_labels` to the family's own constant labels.
+    fn as_serialize_with_labels<'a>(
+        &'a self,
+        const_labels: &'a [(&'a str, &'a str)],
+    ) -> Box<dyn erased_serde::Serialize + 'a>;
+}
+
+// === impl MetricSet ===
+
+impl<'a, F: FmtMetricFamily + ?Sized> MetricSet<'a, F> {
+    /// Returns a new `MetricSet` containing the provided metric families.
+    #[must_use]
+    pub const fn new(families: &'a [&'a F]) -> Self {
+        Self {
+            families,
+            const_labels: &[],
+            format: Format::OpenMetrics,
+        }
+    }
+
+    /// Sets labels which are added to every series in every metric family in
+    /// this set.
+    #[must_use]
+    pub const fn with_const_labels(self, const_labels: &'a [(&'a str, &'a str)]) -> Self {
+        Self {
+            const_labels,
+            ..self
+        }
+    }
+
+    /// Sets the text exposition format in which this set is formatted.
+    #[must_use]
+    pub const fn with_format(self, format: Format) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Returns the text exposition format in which this set is formatted.
+    #[must_use]
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Sets the format in which this set is formatted to the best format for
+    /// a scrape request with the provided `Accept` header value.
+    ///
+    /// See [`Format::negotiate`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tinymetrics::{CounterFamily, MetricBuilder, MetricSet};
+    ///
+    /// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests").build();
+    /// static METRICS: MetricSet<'static> = MetricSet::new(&[&REQUESTS]);
+    ///
+    /// // in a `/metrics` HTTP handler:
+    /// let accept = "application/openmetrics-text; version=1.0.0, text/plain; q=0.5";
+    /// let metrics = METRICS.negotiate(accept);
+    ///
+    /// let content_type = metrics.content_type();
+    /// let body = metrics.to_string();
+    /// assert_eq!(
+    ///     content_type,
+    ///     "application/openmetrics-text; version=1.0.0; charset=utf-8"
+    /// );
+    /// assert!(body.ends_with("# EOF\n"));
+    /// ```
+    #[must_use]
+    pub fn negotiate(self, accept: &str) -> Self {
+        self.with_format(Format::negotiate(accept))
+    }
+
+    /// Returns the `Content-Type` header value for this set's exposition, in
+    /// its [format](Self::with_format).
+    #[must_use]
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    /// Returns the labels which are added to every series in this set.
+    #[must_use]
+    pub fn const_labels(&self) -> &'a [(&'a str, &'a str)] {
+        self.const_labels
+    }
+
+    /// Returns an iterator over the metric families in this set.
+    pub fn iter(&self) -> impl Iterator<Item = &'a F> + '_ {
+        self.families.iter().copied()
+    }
+
+    /// Returns the metric family in this set with the provided `name`, if one
+    /// exists.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&'a F> {
+        self.iter().find(|family| family.name() == name)
+    }
+
+    /// Formats every metric family in this set as a single exposition, in
+    /// this set's [format](Self::with_format).
+    ///
+    /// In the OpenMetrics format, this includes the terminating `# EOF` line.
+    pub fn fmt_metrics(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        self.fmt_metrics_matching(&[], writer)
+    }
+
+    /// Formats every metric family in this set as a single exposition, in
+    /// this set's [format](Self::with_format), including only the series
+    /// selected by every matcher in `matchers`.
+    ///
+    /// Matchers are tested against each series' complete label set, including
+    /// constant labels.
+    pub fn fmt_metrics_matching(
+        &self,
+        matchers: &[Matcher<'_>],
+        writer: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        for family in self.families {
+            family.fmt_metric_family_as(self.format, self.const_labels, matchers, writer)?;
+        }
+        if self.format == Format::OpenMetrics {
+            writer.write_str("# EOF\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<F: FmtMetricFamily + ?Sized> fmt::Display for MetricSet<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_metrics(f)
+    }
+}
+
+impl<F: FmtMetricFamily + ?Sized> fmt::Debug for MetricSet<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|family| family.name()))
+            .finish()
+    }
+}
+
+impl<F: ?Sized> Clone for MetricSet<'_, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F: ?Sized> Copy for MetricSet<'_, F> {}
+
+#[cfg(feature = "serde-set")]
+impl<F> serde::Serialize for MetricSet<'_, F>
+where
+    F: SerializeMetricFamily + ?Sized,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeSeq;
+
+        let mut seq = serializer.serialize_seq(Some(self.families.len()))?;
+        for family in self.families {
+            if self.const_labels.is_empty() {
+                seq.serialize_element(family.as_serialize())?;
+            } else {
+                seq.serialize_element(&family.as_serialize_with_labels(self.const_labels))?;
+            }
+        }
+        seq.end()
+    }
+}
+
+// === impl SerializeMetricFamily ===
+
+#[cfg(feature = "serde-set")]
+impl<M, const METRICS: usize, L, R> SerializeMetricFamily for MetricFamily<'_, M, METRICS, L, R>
+where
+    M: Metric + serde::Serialize,
+    L: FmtLabels + PartialEq,
+    R: Storage<L, M> + serde::Serialize,
+{
+    fn as_serialize(&self) -> &dyn erased_serde::Serialize {
+        self
+    }
+
+    fn as_serialize_with_labels<'a>(
+        &'a self,
+        const_labels: &'a [(&'a str, &'a str)],
+    ) -> Box<dyn erased_serde::Serialize + 'a> {
+        struct WithLabels<'a, F> {
+            family: &'a F,
+            const_labels: &'a [(&'a str, &'a str)],
+        }
+
+        impl<M, const METRICS: usize, L, R> serde::Serialize
+            for WithLabels<'_, MetricFamily<'_, M, METRICS, L, R>>
+        where
+            M: Metric + serde::Serialize,
+            R: serde::Serialize,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                self.family
+                    .serialize_with_labels(self.const_labels, serializer)
+            }
+        }
+
+        Box::new(WithLabels {
+            family: self,
+            const_labels,
+        })
+    }
+}
@@ -0,0 +1,166 @@
+//! Timing sections of code and recording their durations in metrics.
+//!
+//! The [`RecordDuration`] trait is implemented by metric types which can
+//! record an elapsed [`Duration`]. Calling [`RecordDuration::start_timer`]
+//! (when the "std" feature is enabled) or [`RecordDuration::start_timer_with`]
+//! returns a [`Timer`] guard, which records the time elapsed since it was
+//! started when it is dropped.
+//!
+//! # Examples
+//!
+//! ```
+//! use tinymetrics::{timer::RecordDuration, FixedGauge, MetricBuilder, MetricFamily};
+//! use core::time::Duration;
+//!
+//! // On `no_std` targets, any monotonic tick source can be used as a clock,
+//! // such as a hardware timer.
+//! fn uptime() -> Duration {
+//!     // ...
+//!     # Duration::from_millis(0)
+//! }
+//!
+//! // A fixed-point gauge with a scale of 3 records durations in milliseconds,
+//! // and exports them in seconds.
+//! static REQUEST_TIME: MetricFamily<'static, FixedGauge<3>, 4> =
+//!     MetricBuilder::new("request_time")
+//!         .with_help("time spent handling the last request")
+//!         .with_unit("seconds")
+//!         .build();
+//!
+//! let metric = REQUEST_TIME.register(&[("path", "/")]).unwrap();
+//! {
+//!     let _timer = metric.start_timer_with(uptime);
+//!     // handle the request...
+//! } // when `_timer` is dropped, the elapsed time is recorded.
+//! ```
+use crate::{Counter, FixedGauge, Gauge};
+use core::time::Duration;
+
+/// Trait implemented by metrics which can record the duration of a timed
+/// section of code.
+pub trait RecordDuration {
+    /// Records `duration` in this metric.
+    fn record_duration(&self, duration: Duration);
+
+    /// Starts a [`Timer`] which records the time elapsed until it is dropped
+    /// in this metric, using [`std::time::Instant`] as the clock.
+    #[cfg(feature = "std")]
+    fn start_timer(&self) -> Timer<'_, Self> {
+        Timer {
+            metric: self,
+            start: Start::Instant(std::time::Instant::now()),
+            done: false,
+        }
+    }
+
+    /// Starts a [`Timer`] which records the time elapsed until it is dropped
+    /// in this metric, using `clock` as the clock.
+    ///
+    /// The `clock` function must return a [monotonic] time, such as the time
+    /// elapsed since the system booted.
+    ///
+    /// [monotonic]: https://doc.rust-lang.org/std/time/struct.Instant.html#monotonicity
+    fn start_timer_with(&self, clock: fn() -> Duration) -> Timer<'_, Self> {
+        Timer {
+            metric: self,
+            start: Start::Clock {
+                clock,
+                start: clock(),
+            },
+            done: false,
+        }
+    }
+}
+
+/// A guard which records the time elapsed since it was started when it is
+/// dropped.
+///
+/// This type is returned by [`RecordDuration::start_timer`] and
+/// [`RecordDuration::start_timer_with`].
+#[derive(Debug)]
+#[must_use = "a `Timer` records the elapsed time when it is dropped"]
+pub struct Timer<'metric, M: RecordDuration + ?Sized> {
+    metric: &'metric M,
+    start: Start,
+    done: bool,
+}
+
+#[derive(Debug)]
+enum Start {
+    #[cfg(feature = "std")]
+    Instant(std::time::Instant),
+    Clock {
+        clock: fn() -> Duration,
+        start: Duration,
+    },
+}
+
+// === impl Timer ===
+
+impl<M: RecordDuration + ?Sized> Timer<'_, M> {
+    /// Returns the time elapsed since this timer was started.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        match self.start {
+            #[cfg(feature = "std")]
+            Start::Instant(start) => start.elapsed(),
+            Start::Clock { clock, start } => clock().saturating_sub(start),
+        }
+    }
+
+    /// Stops this timer, recording the elapsed time and returning it.
+    pub fn stop(mut self) -> Duration {
+        let elapsed = self.elapsed();
+        self.metric.record_duration(elapsed);
+        self.done = true;
+        elapsed
+    }
+
+    /// Stops this timer _without_ recording the elapsed time.
+    pub fn cancel(mut self) {
+        self.done = true;
+    }
+}
+
+impl<M: RecordDuration + ?Sized> Drop for Timer<'_, M> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.metric.record_duration(self.elapsed());
+        }
+    }
+}
+
+// === impl RecordDuration ===
+
+/// Sets the gauge to the duration in seconds.
+impl RecordDuration for Gauge {
+    fn record_duration(&self, duration: Duration) {
+        self.set_value(duration.as_secs_f64());
+    }
+}
+
+/// Sets the gauge to the duration in 10<sup>-`SCALE`</sup> seconds, so that
+/// the exported value is in seconds.
+impl<const SCALE: u32> RecordDuration for FixedGauge<SCALE> {
+    fn record_duration(&self, duration: Duration) {
+        let divisor = Self::DIVISOR as u128;
+        let scaled = if divisor >= 1_000_000_000 {
+            duration.as_nanos() * (divisor / 1_000_000_000)
+        } else {
+            duration.as_nanos() / (1_000_000_000 / divisor)
+        };
+        self.set_value(isize::try_from(scaled).unwrap_or(isize::MAX));
+    }
+}
+
+/// Adds the duration to the counter in _whole_ seconds.
+///
+/// Because a [`Counter`] is an integer, any fractional seconds are discarded,
+/// so this is only suitable for timing long-running sections of code. Use a
+/// [`Gauge`] or [`FixedGauge`] to record shorter durations.
+impl RecordDuration for Counter {
+    fn record_duration(&self, duration: Duration) {
+        let secs = usize::try_from(duration.as_secs()).unwrap_or(usize::MAX);
+        self.fetch_add(secs);
+    }
+}
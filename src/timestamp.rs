@@ -87,6 +87,26 @@ impl TimestampCell {
     pub(crate) fn timestamp(&self) -> UnixTimestamp {
         UnixTimestamp::from_secs(self.now.load(Ordering::Relaxed))
     }
+
+    /// Advances this cell's timestamp to `timestamp`, if it is later than the
+    /// current timestamp, or to the current time if `timestamp` is `None`.
+    pub(crate) fn update_to(&self, timestamp: Option<UnixTimestamp>) {
+        match timestamp {
+            Some(UnixTimestamp(secs)) => {
+                self.now.fetch_max(secs, Ordering::AcqRel);
+            }
+            None => self.update_max(),
+        }
+    }
+
+    /// Returns the time of the last update, or `None` if this cell has never
+    /// been updated.
+    pub(crate) fn last_updated(&self) -> Option<UnixTimestamp> {
+        match self.now.load(Ordering::Acquire) {
+            0 => None,
+            secs => Some(UnixTimestamp::from_secs(secs)),
+        }
+    }
 }
 
 impl fmt::Display for TimestampCell {
@@ -0,0 +1,25 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, ItemStatic, StaticMutability};
+
+pub(crate) fn expand(args: TokenStream, item: ItemStatic) -> syn::Result<TokenStream> {
+    if !args.is_empty() {
+        return Err(syn::Error::new(
+            args.span(),
+            "`#[export]` does not take any arguments",
+        ));
+    }
+
+    if let StaticMutability::Mut(mutability) = item.mutability {
+        return Err(syn::Error::new(
+            mutability.span(),
+            "`#[export]` cannot be used on a `static mut`",
+        ));
+    }
+
+    let ident = &item.ident;
+    Ok(quote! {
+        #item
+        ::tinymetrics::__export_family!(#ident);
+    })
+}
@@ -0,0 +1,281 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, Attribute, Data, DataEnum, DeriveInput, Fields, LitStr};
+
+/// Options parsed from `#[label(...)]` attributes.
+#[derive(Default)]
+struct LabelAttrs {
+    rename: Option<LitStr>,
+    flatten: bool,
+    skip: bool,
+}
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let container = LabelAttrs::parse(&input.attrs)?;
+
+    let mut partial_eq = None;
+    let (fmt_labels, is_empty) = match input.data {
+        Data::Struct(ref data) => {
+            container.deny_field_options(name.span())?;
+            if let Some(rename) = container.rename {
+                return Err(syn::Error::new(
+                    rename.span(),
+                    "`#[label(rename)]` may only be used on fields, enums, and enum variants",
+                ));
+            }
+            let expanded = expand_struct(&data.fields)?;
+            partial_eq = expanded.partial_eq;
+            (expanded.fmt_labels, expanded.is_empty)
+        }
+        Data::Enum(ref data) => {
+            container.deny_field_options(name.span())?;
+            let key = match container.rename {
+                Some(rename) => rename,
+                None => LitStr::new(&snake_case(&name.to_string()), name.span()),
+            };
+            expand_enum(&key, data)?
+        }
+        Data::Union(ref data) => {
+            return Err(syn::Error::new(
+                data.union_token.span(),
+                "`FmtLabels` cannot be derived for unions",
+            ))
+        }
+    };
+
+    // if any fields are skipped, label sets which format identically must
+    // also compare equal, or they would be registered as separate series.
+    let partial_eq = partial_eq.map(|(fields, types)| {
+        let mut generics = input.generics.clone();
+        let where_clause = generics.make_where_clause();
+        for ty in types {
+            where_clause
+                .predicates
+                .push(syn::parse_quote! { #ty: ::core::cmp::PartialEq });
+        }
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics ::core::cmp::PartialEq for #name #ty_generics #where_clause {
+                fn eq(&self, other: &Self) -> bool {
+                    true #( && self.#fields == other.#fields )*
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #partial_eq
+
+        impl #impl_generics ::tinymetrics::FmtLabels for #name #ty_generics #where_clause {
+            #[allow(unused_assignments, unused_mut, unused_variables)]
+            fn fmt_labels(
+                &self,
+                writer: &mut impl ::core::fmt::Write,
+            ) -> ::core::fmt::Result {
+                #fmt_labels
+            }
+
+            fn is_empty(&self) -> bool {
+                #is_empty
+            }
+        }
+    })
+}
+
+/// The expansion of `FmtLabels` for a struct.
+struct ExpandedStruct<'a> {
+    fmt_labels: TokenStream,
+    is_empty: TokenStream,
+    /// If any fields are skipped, the names and types of the fields which are
+    /// not, which the generated `PartialEq` implementation compares.
+    partial_eq: Option<(Vec<&'a syn::Ident>, Vec<&'a syn::Type>)>,
+}
+
+fn expand_struct(fields: &Fields) -> syn::Result<ExpandedStruct<'_>> {
+    let Fields::Named(ref fields) = fields else {
+        return Err(syn::Error::new(
+            fields.span(),
+            "`FmtLabels` can only be derived for structs with named fields",
+        ));
+    };
+
+    let mut fmt_fields = Vec::new();
+    let mut flattened = Vec::new();
+    let mut has_labels = false;
+    let mut has_skipped = false;
+    let mut labeled = (Vec::new(), Vec::new());
+    for field in &fields.named {
+        let attrs = LabelAttrs::parse(&field.attrs)?;
+        let ident = field.ident.as_ref().expect("named fields have names");
+        if attrs.skip {
+            has_skipped = true;
+            continue;
+        }
+        labeled.0.push(ident);
+        labeled.1.push(&field.ty);
+
+        if attrs.flatten {
+            if let Some(rename) = attrs.rename {
+                return Err(syn::Error::new(
+                    rename.span(),
+                    "`#[label(flatten)]` fields cannot be renamed",
+                ));
+            }
+            fmt_fields.push(quote! {
+                if !::tinymetrics::FmtLabels::is_empty(&self.#ident) {
+                    if !first {
+                        ::core::fmt::Write::write_char(writer, ',')?;
+                    }
+                    first = false;
+                    ::tinymetrics::FmtLabels::fmt_labels(&self.#ident, writer)?;
+                }
+            });
+            flattened.push(quote! { ::tinymetrics::FmtLabels::is_empty(&self.#ident) });
+            continue;
+        }
+
+        let key = match attrs.rename {
+            Some(rename) => rename,
+            None => {
+                let name = ident.to_string();
+                LitStr::new(name.strip_prefix("r#").unwrap_or(&name), ident.span())
+            }
+        };
+        validate_label_name(&key)?;
+        has_labels = true;
+        fmt_fields.push(quote! {
+            if !first {
+                ::core::fmt::Write::write_char(writer, ',')?;
+            }
+            first = false;
+            ::tinymetrics::fmt_label(writer, #key, &self.#ident)?;
+        });
+    }
+
+    let fmt_labels = quote! {
+        let mut first = true;
+        #(#fmt_fields)*
+        Ok(())
+    };
+    let is_empty = if has_labels {
+        quote! { false }
+    } else {
+        quote! { true #( && #flattened )* }
+    };
+    Ok(ExpandedStruct {
+        fmt_labels,
+        is_empty,
+        partial_eq: if has_skipped { Some(labeled) } else { None },
+    })
+}
+
+fn expand_enum(key: &LitStr, data: &DataEnum) -> syn::Result<(TokenStream, TokenStream)> {
+    validate_label_name(key)?;
+
+    let mut arms = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new(
+                variant.fields.span(),
+                "`FmtLabels` can only be derived for enums whose variants are all unit variants",
+            ));
+        }
+
+        let attrs = LabelAttrs::parse(&variant.attrs)?;
+        attrs.deny_field_options(variant.ident.span())?;
+        let ident = &variant.ident;
+        let value = match attrs.rename {
+            Some(rename) => rename,
+            None => LitStr::new(&ident.to_string(), ident.span()),
+        };
+        arms.push(quote! {
+            Self::#ident => ::tinymetrics::fmt_label(writer, #key, &#value),
+        });
+    }
+
+    let fmt_labels = quote! {
+        match *self {
+            #(#arms)*
+        }
+    };
+    Ok((fmt_labels, quote! { false }))
+}
+
+/// Label names must match the regex `[a-zA-Z_][a-zA-Z0-9_]*`.
+fn validate_label_name(name: &LitStr) -> syn::Result<()> {
+    let value = name.value();
+    let mut chars = value.chars();
+    let valid = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(syn::Error::new(
+            name.span(),
+            format!("`{value}` is not a valid OpenMetrics label name"),
+        ))
+    }
+}
+
+/// Converts a `CamelCase` type name to `snake_case`, treating a run of
+/// capitals (such as an acronym) as a single word, so that `HTTPMethod`
+/// becomes `http_method`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).map_or(false, |c| c.is_lowercase());
+            if (!prev.is_uppercase() && prev != '_') || (prev.is_uppercase() && next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+// === impl LabelAttrs ===
+
+impl LabelAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut parsed = Self::default();
+        for attr in attrs {
+            if !attr.path().is_ident("label") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    parsed.rename = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("flatten") {
+                    parsed.flatten = true;
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    parsed.skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported `label` attribute"))
+                }
+            })?;
+        }
+        Ok(parsed)
+    }
+
+    /// `flatten` and `skip` are only valid on struct fields.
+    fn deny_field_options(&self, span: proc_macro2::Span) -> syn::Result<()> {
+        if self.flatten || self.skip {
+            return Err(syn::Error::new(
+                span,
+                "`#[label(flatten)]` and `#[label(skip)]` may only be used on struct fields",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Derive macros for the [`tinymetrics`] crate.
+//!
+//! These macros are re-exported by `tinymetrics` when its "derive" feature
+//! flag is enabled, and should generally be used through that crate.
+//!
+//! [`tinymetrics`]: https://docs.rs/tinymetrics
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemStatic};
+
+mod export;
+mod labels;
+mod metrics;
+
+/// Derives an implementation of `tinymetrics::FmtLabels` for a struct or enum.
+///
+/// # Structs
+///
+/// When deriving `FmtLabels` for a struct with named fields, each field is
+/// formatted as a label whose name is the name of the field, and whose value
+/// is the field's [`Display`](core::fmt::Display) implementation. Label values
+/// are escaped as required by the OpenMetrics text format.
+///
+/// The following attributes may be placed on fields:
+///
+/// - `#[label(rename = "name")]`: use `name` as the label name, rather than
+///   the field's name.
+/// - `#[label(flatten)]`: rather than formatting the field as a single label,
+///   format it using its own `FmtLabels` implementation. This may be used to
+///   nest label sets, such as a derived enum.
+/// - `#[label(skip)]`: do not include this field in the label set.
+///
+/// If any fields are skipped, a [`PartialEq`] implementation which compares
+/// only the fields that are included in the label set is also derived, so
+/// that label sets which format identically are the same series. Such types
+/// must not also derive `PartialEq`, and any `Hash` implementation must
+/// likewise ignore the skipped fields.
+///
+/// # Enums
+///
+/// When deriving `FmtLabels` for an enum, all of its variants must be unit
+/// variants. The enum is formatted as a single label, whose value is the name
+/// of the variant. By default, the label's name is the name of the enum type,
+/// converted to `snake_case` (with acronyms treated as a single word, so that
+/// `HTTPMethod` becomes `http_method`).
+///
+/// - `#[label(rename = "name")]` on the enum sets the label's name.
+/// - `#[label(rename = "value")]` on a variant sets the label's value for that
+///   variant.
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::FmtLabels;
+///
+/// #[derive(FmtLabels, PartialEq)]
+/// enum Direction {
+///     #[label(rename = "rx")]
+///     Receive,
+///     #[label(rename = "tx")]
+///     Transmit,
+/// }
+///
+/// #[derive(FmtLabels, PartialEq)]
+/// struct InterfaceLabels {
+///     #[label(rename = "interface")]
+///     iface: &'static str,
+///     #[label(flatten)]
+///     direction: Direction,
+/// }
+///
+/// let labels = InterfaceLabels {
+///     iface: "eth0",
+///     direction: Direction::Transmit,
+/// };
+/// let mut formatted = String::new();
+/// labels.fmt_labels(&mut formatted).unwrap();
+/// assert_eq!(formatted, r#"interface="eth0",direction="tx""#);
+/// ```
+///
+/// Since `tinymetrics` metric families look up label sets by comparing them
+/// for equality, types deriving `FmtLabels` should typically also derive
+/// `PartialEq`.
+#[proc_macro_derive(FmtLabels, attributes(label))]
+pub fn derive_fmt_labels(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    labels::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives a constructor and an exposition method for a struct whose fields
+/// are all `tinymetrics::MetricFamily`s.
+///
+/// This generates two inherent methods:
+///
+/// - `const fn new() -> Self`, which constructs each metric family, so that the
+///   struct may be used in a `static`.
+/// - `fn fmt_metrics(&self, writer: &mut impl fmt::Write) -> fmt::Result`,
+///   which formats every metric family in the struct as a single OpenMetrics
+///   exposition, terminated by a `# EOF` line.
+///
+/// By default, each metric family's name is the name of the field. The
+/// following attributes may be placed on fields to configure the
+/// `MetricBuilder` used to construct that field's family:
+///
+/// - `#[metric(name = "name")]`: sets the metric family's name.
+/// - `#[metric(help = "help text")]`: sets the metric family's help text.
+/// - `#[metric(unit = "unit")]`: sets the metric family's unit.
+/// - `#[metric(buckets = EXPR)]`: sets the bucket bounds for histogram metric
+///   types. `EXPR` must be a constant expression of type `&[f64]`.
+/// - `#[metric(overflow)]`: enables the overflow policy, so that label sets
+///   registered once the family is full are folded into a reserved overflow
+///   series.
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::{CounterFamily, GaugeFamily, Metrics};
+///
+/// #[derive(Metrics)]
+/// struct NetMetrics {
+///     #[metric(name = "net_rx_bytes", help = "bytes received", unit = "bytes")]
+///     rx_bytes: CounterFamily<'static, 4>,
+///     #[metric(help = "current link speed", unit = "bits_per_second")]
+///     link_speed: GaugeFamily<'static, 4>,
+/// }
+///
+/// static METRICS: NetMetrics = NetMetrics::new();
+///
+/// METRICS.rx_bytes.register(&[("interface", "eth0")]).unwrap().fetch_add(1024);
+///
+/// let mut exposition = String::new();
+/// METRICS.fmt_metrics(&mut exposition).unwrap();
+/// assert!(exposition.starts_with("# TYPE net_rx_bytes counter\n"));
+/// assert!(exposition.contains("# TYPE link_speed gauge\n"));
+/// assert!(exposition.ends_with("# EOF\n"));
+/// ```
+#[proc_macro_derive(Metrics, attributes(metric))]
+pub fn derive_metrics(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    metrics::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Adds a `static` metric family to the global set of metric families in
+/// `tinymetrics::global`.
+///
+/// When the `tinymetrics` crate's "linkme" feature flag is enabled, the
+/// annotated `static` is collected at link time, so that it is included in
+/// the global exposition without having to be registered explicitly. This
+/// works across crates: any `static` annotated with `#[export]` anywhere in
+/// the dependency graph is collected.
+///
+/// When the "linkme" feature is disabled (such as on targets where linker
+/// sections are not supported), this attribute has no effect, and the family
+/// must be added to the global set using `tinymetrics::global::register`.
+///
+/// The `static`'s type must implement `tinymetrics::FmtMetricFamily` and
+/// `Sync`.
+///
+/// # Examples
+///
+/// ```
+/// use tinymetrics::{global, CounterFamily, MetricBuilder};
+///
+/// #[tinymetrics::export]
+/// static REQUESTS: CounterFamily<'static, 4> = MetricBuilder::new("requests").build();
+///
+/// // without the "linkme" feature, families must be registered at runtime.
+/// // if the family was already collected at link time, this does nothing.
+/// global::register(&REQUESTS).unwrap();
+///
+/// let mut exposition = String::new();
+/// global::fmt_metrics(&mut exposition).unwrap();
+/// assert!(exposition.contains("# TYPE requests counter\n"));
+/// ```
+#[proc_macro_attribute]
+pub fn export(args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemStatic);
+    export::expand(args.into(), item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,139 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, Attribute, Data, DeriveInput, Expr, Fields, LitStr};
+
+/// Options parsed from `#[metric(...)]` attributes.
+#[derive(Default)]
+struct MetricAttrs {
+    name: Option<LitStr>,
+    help: Option<LitStr>,
+    unit: Option<LitStr>,
+    buckets: Option<Expr>,
+    overflow: bool,
+}
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields,
+            ref fields => {
+                return Err(syn::Error::new(
+                    fields.span(),
+                    "`Metrics` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                name.span(),
+                "`Metrics` can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let mut ctors = Vec::new();
+    let mut fmts = Vec::new();
+    for field in &fields.named {
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let attrs = MetricAttrs::parse(&field.attrs)?;
+        let metric_name = match attrs.name {
+            Some(name) => name,
+            None => {
+                let name = ident.to_string();
+                LitStr::new(name.strip_prefix("r#").unwrap_or(&name), ident.span())
+            }
+        };
+        validate_metric_name(&metric_name)?;
+
+        let mut builder = quote! { ::tinymetrics::MetricBuilder::new(#metric_name) };
+        if let Some(help) = attrs.help {
+            builder = quote! { #builder.with_help(#help) };
+        }
+        if let Some(unit) = attrs.unit {
+            builder = quote! { #builder.with_unit(#unit) };
+        }
+        if let Some(buckets) = attrs.buckets {
+            builder = quote! { #builder.with_buckets(#buckets) };
+        }
+        if attrs.overflow {
+            builder = quote! { #builder.with_overflow() };
+        }
+
+        ctors.push(quote! { #ident: #builder.build_with_storage(), });
+        fmts.push(quote! { self.#ident.fmt_metric(writer)?; });
+    }
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Returns a new instance of this set of metric families.
+            #[must_use]
+            pub const fn new() -> Self {
+                Self {
+                    #(#ctors)*
+                }
+            }
+
+            /// Formats every metric family in this struct as a single
+            /// OpenMetrics exposition, including the terminating `# EOF` line.
+            pub fn fmt_metrics(
+                &self,
+                writer: &mut impl ::core::fmt::Write,
+            ) -> ::core::fmt::Result {
+                #(#fmts)*
+                ::core::fmt::Write::write_str(writer, "# EOF\n")
+            }
+        }
+    })
+}
+
+/// Metric names must match the regex `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn validate_metric_name(name: &LitStr) -> syn::Result<()> {
+    let value = name.value();
+    let mut chars = value.chars();
+    let valid = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if valid {
+        Ok(())
+    } else {
+        Err(syn::Error::new(
+            name.span(),
+            format!("`{value}` is not a valid OpenMetrics metric name"),
+        ))
+    }
+}
+
+// === impl MetricAttrs ===
+
+impl MetricAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut parsed = Self::default();
+        for attr in attrs {
+            if !attr.path().is_ident("metric") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    parsed.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("help") {
+                    parsed.help = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("unit") {
+                    parsed.unit = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("buckets") {
+                    parsed.buckets = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("overflow") {
+                    parsed.overflow = true;
+                } else {
+                    return Err(meta.error("unsupported `metric` attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,56 @@
+use tinymetrics::{global, CounterFamily, GaugeFamily, MetricBuilder};
+
+#[tinymetrics::export]
+static EXPORTED_REQUESTS: CounterFamily<'static, 2> = MetricBuilder::new("exported_requests")
+    .without_timestamps()
+    .build();
+
+static REGISTERED_TEMPERATURE: GaugeFamily<'static, 2> =
+    MetricBuilder::new("registered_temperature")
+        .without_timestamps()
+        .build();
+
+#[test]
+fn exported_families() {
+    EXPORTED_REQUESTS
+        .register(&[("path", "/")])
+        .unwrap()
+        .fetch_add(1);
+    REGISTERED_TEMPERATURE
+        .register(&[])
+        .unwrap()
+        .set_value(21.5);
+
+    // exported families are collected at link time.
+    let names = global::families()
+        .map(|family| family.name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["exported_requests"]);
+
+    global::register(&REGISTERED_TEMPERATURE).unwrap();
+    // registering a family twice does nothing.
+    global::register(&REGISTERED_TEMPERATURE).unwrap();
+    global::register(&EXPORTED_REQUESTS).unwrap();
+
+    let names = global::families()
+        .map(|family| family.name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["exported_requests", "registered_temperature"]);
+
+    let mut exposition = String::new();
+    global::fmt_metrics(&mut exposition).unwrap();
+    pretty_assertions::assert_str_eq!(
+        exposition,
+        "\
+        # TYPE exported_requests counter\n\
+        # UNIT exported_requests \n\
+        # HELP exported_requests \n\
+        exported_requests{path=\"/\"} 1\n\n\
+        # TYPE registered_temperature gauge\n\
+        # UNIT registered_temperature \n\
+        # HELP registered_temperature \n\
+        registered_temperature 21.5\n\n\
+        # EOF\n\
+        "
+    );
+}
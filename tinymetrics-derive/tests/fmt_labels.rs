@@ -0,0 +1,147 @@
+use pretty_assertions::assert_str_eq;
+use tinymetrics::{FmtLabels, MetricBuilder};
+
+#[derive(FmtLabels, PartialEq)]
+enum Direction {
+    #[label(rename = "rx")]
+    Receive,
+    #[label(rename = "tx")]
+    Transmit,
+}
+
+#[derive(FmtLabels, PartialEq)]
+enum LinkState {
+    Up,
+    Down,
+}
+
+#[derive(FmtLabels, PartialEq)]
+enum HTTPMethod {
+    Get,
+}
+
+// N.B. that `PartialEq` is derived by `FmtLabels`, since a field is skipped.
+#[derive(FmtLabels)]
+struct InterfaceLabels {
+    #[label(rename = "interface")]
+    iface: &'static str,
+    #[label(flatten)]
+    direction: Direction,
+    #[label(flatten)]
+    state: LinkState,
+    #[label(skip)]
+    _ignored: usize,
+}
+
+#[derive(FmtLabels, PartialEq)]
+struct Escaped<'a> {
+    path: &'a str,
+    port: u16,
+}
+
+#[derive(FmtLabels, PartialEq)]
+struct Empty {}
+
+#[derive(FmtLabels, PartialEq)]
+struct FlattenedEmpty {
+    #[label(flatten)]
+    empty: Empty,
+}
+
+fn fmt_labels(labels: &impl FmtLabels) -> String {
+    let mut s = String::new();
+    labels.fmt_labels(&mut s).unwrap();
+    s
+}
+
+#[test]
+fn derived_struct() {
+    let labels = InterfaceLabels {
+        iface: "eth0",
+        direction: Direction::Receive,
+        state: LinkState::Up,
+        _ignored: 1,
+    };
+    assert_str_eq!(
+        fmt_labels(&labels),
+        r#"interface="eth0",direction="rx",link_state="Up""#
+    );
+    assert!(!labels.is_empty());
+}
+
+#[test]
+fn derived_enum() {
+    assert_str_eq!(fmt_labels(&Direction::Transmit), r#"direction="tx""#);
+    assert_str_eq!(fmt_labels(&LinkState::Down), r#"link_state="Down""#);
+    // acronyms are a single word.
+    assert_str_eq!(fmt_labels(&HTTPMethod::Get), r#"http_method="Get""#);
+    assert!(!Direction::Transmit.is_empty());
+}
+
+#[test]
+fn derived_escapes_values() {
+    let labels = Escaped {
+        path: "C:\\\"metrics\"\n",
+        port: 8080,
+    };
+    assert_str_eq!(
+        fmt_labels(&labels),
+        r#"path="C:\\\"metrics\"\n",port="8080""#
+    );
+}
+
+#[test]
+fn derived_empty() {
+    assert!(Empty {}.is_empty());
+    assert!(FlattenedEmpty { empty: Empty {} }.is_empty());
+    assert_str_eq!(fmt_labels(&FlattenedEmpty { empty: Empty {} }), "");
+}
+
+#[test]
+fn derived_labels_in_family() {
+    let family = MetricBuilder::new("test_bytes")
+        .with_unit("bytes")
+        .without_timestamps()
+        .build_labeled::<tinymetrics::Counter, InterfaceLabels, 2>();
+    let labels = |direction| InterfaceLabels {
+        iface: "eth0",
+        direction,
+        state: LinkState::Up,
+        _ignored: 0,
+    };
+    // skipped fields are ignored when comparing label sets.
+    assert!(
+        labels(Direction::Receive)
+            == InterfaceLabels {
+                _ignored: 1,
+                ..labels(Direction::Receive)
+            }
+    );
+    assert!(labels(Direction::Receive) != labels(Direction::Transmit));
+    family
+        .register(labels(Direction::Receive))
+        .unwrap()
+        .fetch_add(10);
+    family
+        .register(labels(Direction::Transmit))
+        .unwrap()
+        .fetch_add(20);
+    // looking up the same labels again returns the same counter, even if a
+    // skipped field differs.
+    family
+        .register(InterfaceLabels {
+            _ignored: 5,
+            ..labels(Direction::Receive)
+        })
+        .unwrap()
+        .fetch_add(1);
+
+    let expected = "\
+    # TYPE test_bytes counter\n\
+    # UNIT test_bytes bytes\n\
+    # HELP test_bytes \n\
+    test_bytes{interface=\"eth0\",direction=\"rx\",link_state=\"Up\"} 11\n\
+    test_bytes{interface=\"eth0\",direction=\"tx\",link_state=\"Up\"} 20\n\n\
+    ";
+    assert_str_eq!(family.to_string(), expected);
+}
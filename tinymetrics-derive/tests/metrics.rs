@@ -0,0 +1,62 @@
+use tinymetrics::{CounterFamily, GaugeHistogram, IntGauge, MetricFamily, Metrics};
+
+const QUEUE_WAIT_BUCKETS: &[f64] = &[0.5, 1.0];
+
+#[derive(Metrics)]
+struct TestMetrics {
+    #[metric(name = "test_requests", help = "requests handled", unit = "requests")]
+    requests: CounterFamily<'static, 2>,
+    #[metric(help = "connections currently open")]
+    open_connections: MetricFamily<'static, IntGauge, 2>,
+    #[metric(unit = "seconds", buckets = QUEUE_WAIT_BUCKETS)]
+    queue_wait: MetricFamily<'static, GaugeHistogram<2>, 1>,
+}
+
+static METRICS: TestMetrics = TestMetrics::new();
+
+#[test]
+fn derived_metrics() {
+    METRICS
+        .requests
+        .register(&[("path", "/")])
+        .unwrap()
+        .fetch_add(2);
+    METRICS.open_connections.register(&[]).unwrap().set_value(3);
+    METRICS.queue_wait.register(&[]).unwrap().observe(0.75);
+
+    let mut exposition = String::new();
+    METRICS.fmt_metrics(&mut exposition).unwrap();
+
+    // N.B. that timestamps are enabled by default with the "std" feature, so
+    // only check the prefix of each sample.
+    let lines: Vec<&str> = exposition.lines().collect();
+    let expected = [
+        "# TYPE test_requests counter",
+        "# UNIT test_requests requests",
+        "# HELP test_requests requests handled",
+        "test_requests{path=\"/\"} 2",
+        "",
+        "# TYPE open_connections gauge",
+        "# UNIT open_connections ",
+        "# HELP open_connections connections currently open",
+        "open_connections 3",
+        "",
+        "# TYPE queue_wait gaugehistogram",
+        "# UNIT queue_wait seconds",
+        "# HELP queue_wait ",
+        "queue_wait_bucket{le=\"0.5\"} 0",
+        "queue_wait_bucket{le=\"1.0\"} 1",
+        "queue_wait_bucket{le=\"+Inf\"} 1",
+        "queue_wait_gcount 1",
+        "queue_wait_gsum 0.75",
+        "",
+        "# EOF",
+    ];
+    assert_eq!(lines.len(), expected.len(), "{exposition}");
+    for (line, expected) in lines.iter().zip(expected) {
+        assert!(
+            line.starts_with(expected),
+            "expected line to start with {expected:?}\n  line: {line:?}"
+        );
+    }
+}